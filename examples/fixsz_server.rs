@@ -13,8 +13,12 @@ use std::io::{self, Read};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use unix_named_pipe::FileFIFOExt;
 
+/// How long to wait for data before checking for a keyboard interrupt.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 fn main() {
     let pipe_path = env::args()
         .nth(1)
@@ -36,10 +40,15 @@ fn main() {
         let res = file.read(&mut payload);
         if let Err(err) = res {
             // Named pipes, by design, only support nonblocking reads and writes.
-            // If a read would block, an error is thrown, but we can safely ignore it.
+            // If a read would block, wait for data instead of retrying at once.
+            // The timeout keeps the keyboard interrupt flag checked.
             match err.kind() {
-                io::ErrorKind::WouldBlock => continue,
-                _ => panic!(format!("error while reading from pipe: {:?}", err)),
+                io::ErrorKind::WouldBlock => {
+                    file.poll_readable(Some(POLL_INTERVAL))
+                        .expect("could not wait for pipe");
+                    continue;
+                }
+                _ => panic!("error while reading from pipe: {:?}", err),
             }
         } else if let Ok(count) = res {
             if count != payload.len() {
@@ -52,7 +61,7 @@ fn main() {
         }
     }
 
-    fs::remove_file(&pipe_path).expect("could not remove pipe during shutdown");
+    unix_named_pipe::remove_if_fifo(&pipe_path).expect("could not remove pipe during shutdown");
 }
 
 fn make_loop_flag() -> Arc<AtomicBool> {
@@ -65,16 +74,16 @@ fn make_loop_flag() -> Arc<AtomicBool> {
     })
     .expect("could not set up keyboard interrupt handler");
 
-    return running;
+    running
 }
 
 /// Tries to open the pipe at `pipe_path`.
 ///   1. Attempt to open the path for writing
-///     a. If `open_write()` fails with `io::ErrorKind::NotFound`, create the pipe and try again
-///     b. If `open_write()` fails with any other error, raise the error.
+///      a. If `open_write()` fails with `io::ErrorKind::NotFound`, create the pipe and try again
+///      b. If `open_write()` fails with any other error, raise the error.
 ///   2. Now that the file is opened for writing, ensure that it is a named pipe
-///     a. If `is_fifo()` fails, panic.
-///     b. If `is_fifo()` returns `false`, panic.
+///      a. If `is_fifo()` fails, panic.
+///      b. If `is_fifo()` returns `false`, panic.
 ///   3. Return the newly opened pipe file wrapped in an `io::Result`
 fn try_open<P: AsRef<Path> + Clone>(pipe_path: P) -> io::Result<fs::File> {
     let pipe = unix_named_pipe::open_read(&pipe_path);
@@ -99,14 +108,11 @@ fn try_open<P: AsRef<Path> + Clone>(pipe_path: P) -> io::Result<fs::File> {
         .is_fifo()
         .expect("could not read type of file at pipe path");
     if !is_fifo {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "expected file at {:?} to be fifo, is actually {:?}",
-                &pipe_path.clone().as_ref(),
-                pipe_file.metadata()?.file_type(),
-            ),
-        ));
+        return Err(io::Error::other(format!(
+            "expected file at {:?} to be fifo, is actually {:?}",
+            &pipe_path.clone().as_ref(),
+            pipe_file.metadata()?.file_type(),
+        )));
     }
 
     Ok(pipe_file)
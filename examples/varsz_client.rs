@@ -4,6 +4,9 @@
 //! The client opens the named pipe for writing and emits randomly generated numbers
 //! into the pipe, separated by newlines.
 
+// The miniserde 0.1 derives expand to impls nested inside a const block.
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate miniserde;
 extern crate rand;
@@ -35,7 +38,7 @@ fn main() {
         let payload = payload.as_bytes();
 
         let res = pipe
-            .write(&payload)
+            .write(payload)
             .expect("could not write payload to pipe");
         if res != payload.len() {
             println!("could not write {} bytes to pipe", payload.len());
@@ -53,4 +56,4 @@ fn make_payload() -> Message {
     let numbers: Vec<u8> = (0..count).map(|_| random::<u8>()).collect();
 
     Message { numbers }
-}
\ No newline at end of file
+}
@@ -4,6 +4,9 @@
 //! The server will read pairs of bytes at a time and print the randomly generated number
 //! to stdout.
 
+// The miniserde 0.1 derives expand to impls nested inside a const block.
+#![allow(non_local_definitions)]
+
 extern crate ctrlc;
 #[macro_use]
 extern crate miniserde;
@@ -11,12 +14,14 @@ extern crate unix_named_pipe;
 
 use miniserde::json;
 use std::env;
-use std::fs;
-use std::io::{self, BufRead};
-use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use unix_named_pipe::FileFIFOExt;
+use std::thread;
+use std::time::Duration;
+use unix_named_pipe::{Direction, FifoBufReader, FileFIFOExt, NamedPipe};
+
+/// How long to wait for data before checking for a keyboard interrupt.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, MiniDeserialize)]
 struct Message {
@@ -33,33 +38,40 @@ fn main() {
     // the process is shut down.
     let running = make_loop_flag();
 
-    // Open the pipe file for reading
-    let file = try_open(&pipe_path).expect("could not open pipe for reading");
-    let mut reader = io::BufReader::new(file);
+    // Create the pipe if needed and open it for reading. The pipe is removed
+    // again when `pipe` is dropped, even if the read loop panics.
+    unix_named_pipe::ensure_exists(&pipe_path, Some(0o660)).expect("could not create pipe");
+    let pipe = NamedPipe::open(&pipe_path, Direction::Read)
+        .expect("could not open pipe for reading")
+        .unlink_on_drop(true);
+    // A line may arrive split across several reads, so partial lines are
+    // buffered until the rest of them arrives.
+    let mut lines = FifoBufReader::new(pipe)
+        .expect("could not size read buffer")
+        .lines_nonblocking();
 
     // Loop reading from the pipe until a keyboard interrupt is received
     while running.load(Ordering::SeqCst) {
-        // If an error occurs during read, panic
-        let mut line = String::new();
-        let res = reader.read_line(&mut line);
-        if let Err(err) = res {
+        match lines.next() {
             // Named pipes, by design, only support nonblocking reads and writes.
-            // If a read would block, an error is thrown, but we can safely ignore it.
-            match err.kind() {
-                io::ErrorKind::WouldBlock => continue,
-                _ => panic!(format!("error while reading from pipe: {:?}", err)),
+            // If no complete line has arrived, wait for data instead of retrying
+            // at once. The timeout keeps the keyboard interrupt flag checked.
+            Some(None) => {
+                lines
+                    .poll_readable(Some(POLL_INTERVAL))
+                    .expect("could not wait for pipe");
             }
-        } else if let Ok(count) = res {
-            if count == 0 {
-                continue;
-            } else {
+            // If an error occurs during read, panic
+            Some(Some(Err(err))) => panic!("error while reading from pipe: {:?}", err),
+            Some(Some(Ok(line))) => {
                 let payload: Message = json::from_str(&line).expect("could not deserialize line");
-                println!("got message from client: {:?}", payload);
+                println!("got message from client: {:?}", payload.numbers);
             }
+            // Every client has gone away. The pipe reports a hang-up until the
+            // next one connects, so sleep instead of polling.
+            None => thread::sleep(POLL_INTERVAL),
         }
     }
-
-    fs::remove_file(&pipe_path).expect("could not remove pipe during shutdown");
 }
 
 fn make_loop_flag() -> Arc<AtomicBool> {
@@ -72,49 +84,5 @@ fn make_loop_flag() -> Arc<AtomicBool> {
     })
     .expect("could not set up keyboard interrupt handler");
 
-    return running;
-}
-
-/// Tries to open the pipe at `pipe_path`.
-///   1. Attempt to open the path for writing
-///     a. If `open_write()` fails with `io::ErrorKind::NotFound`, create the pipe and try again
-///     b. If `open_write()` fails with any other error, raise the error.
-///   2. Now that the file is opened for writing, ensure that it is a named pipe
-///     a. If `is_fifo()` fails, panic.
-///     b. If `is_fifo()` returns `false`, panic.
-///   3. Return the newly opened pipe file wrapped in an `io::Result`
-fn try_open<P: AsRef<Path> + Clone>(pipe_path: P) -> io::Result<fs::File> {
-    let pipe = unix_named_pipe::open_read(&pipe_path);
-    if let Err(err) = pipe {
-        match err.kind() {
-            io::ErrorKind::NotFound => {
-                println!("creating pipe at: {:?}", pipe_path.clone().as_ref());
-                unix_named_pipe::create(&pipe_path, Some(0o660))?;
-
-                // Note that this has the possibility to recurse forever if creation `open_write`
-                // fails repeatedly with `io::ErrorKind::NotFound`, which is certainly not nice behaviour.
-                return try_open(pipe_path);
-            }
-            _ => {
-                return Err(err);
-            }
-        }
-    }
-
-    let pipe_file = pipe.unwrap();
-    let is_fifo = pipe_file
-        .is_fifo()
-        .expect("could not read type of file at pipe path");
-    if !is_fifo {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!(
-                "expected file at {:?} to be fifo, is actually {:?}",
-                &pipe_path.clone().as_ref(),
-                pipe_file.metadata()?.file_type(),
-            ),
-        ));
-    }
-
-    Ok(pipe_file)
+    running
 }
@@ -0,0 +1,184 @@
+//! Provides an optional audit trail of the pipe lifecycle operations
+//! performed by this crate.
+//!
+//! Nothing is recorded until a sink is installed with `set_audit_sink`.
+
+use std::fs;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+static SINK: RwLock<Option<Box<dyn AuditSink>>> = RwLock::new(None);
+
+/// The lifecycle operation an `AuditEvent` describes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum AuditAction {
+    /// A FIFO was created with the given mode.
+    Create { mode: u32 },
+    /// A FIFO was opened for reading or writing.
+    Open,
+    /// A FIFO was unlinked.
+    Unlink,
+    /// An end of a FIFO held by a `NamedPipe` was closed.
+    Close,
+    /// A FIFO's permissions were changed to the given mode.
+    SetMode { mode: u32 },
+    /// A FIFO's owner or group was changed; `None` means left unchanged.
+    SetOwner { uid: Option<u32>, gid: Option<u32> },
+    /// A FIFO was given a new SELinux security context.
+    SetContext,
+    /// A FIFO was given a new access control list.
+    SetAcl,
+}
+
+/// A single audited operation on a pipe.
+#[derive(Clone, Debug)]
+pub struct AuditEvent {
+    /// When the operation completed. Use `duration_since(UNIX_EPOCH)` for
+    /// a Unix timestamp.
+    pub timestamp: SystemTime,
+    /// What was done to the pipe.
+    pub action: AuditAction,
+    /// The path the operation targeted. For an operation on an open
+    /// descriptor, this is the path `/proc/self/fd` resolves it to, or the
+    /// `/proc/self/fd` entry itself if it cannot be resolved.
+    pub path: PathBuf,
+    /// Whether the operation succeeded.
+    pub succeeded: bool,
+}
+
+/// Receives audit events. Implemented for any `Fn(&AuditEvent)` closure.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: &AuditEvent);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditEvent) + Send + Sync,
+{
+    fn record(&self, event: &AuditEvent) {
+        self(event)
+    }
+}
+
+/// Installs `sink` as the process-wide audit sink, replacing any previous one.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::{set_audit_sink, clear_audit_sink, AuditEvent};
+///
+/// set_audit_sink(|event: &AuditEvent| eprintln!("{:?}", event));
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// unix_named_pipe::create(file_name, None).expect("could not create fifo");
+/// # fs::remove_file(file_name).expect("could not remove fifo");
+/// clear_audit_sink();
+/// ```
+pub fn set_audit_sink<S: AuditSink + 'static>(sink: S) {
+    let mut slot = SINK.write().unwrap_or_else(|err| err.into_inner());
+    *slot = Some(Box::new(sink));
+}
+
+/// Removes the process-wide audit sink, if one is installed.
+pub fn clear_audit_sink() {
+    let mut slot = SINK.write().unwrap_or_else(|err| err.into_inner());
+    *slot = None;
+}
+
+/// Hands an event to the installed sink, if any.
+pub(crate) fn record<T, E>(action: AuditAction, path: &Path, result: &Result<T, E>) {
+    emit(action, || path.to_path_buf(), result)
+}
+
+/// Hands an event about the FIFO open at `fd` to the installed sink, if
+/// any. The path is only looked up when there is a sink.
+pub(crate) fn record_fd<T, E>(action: AuditAction, fd: BorrowedFd<'_>, result: &Result<T, E>) {
+    emit(
+        action,
+        || {
+            let link = PathBuf::from(format!("/proc/self/fd/{}", fd.as_raw_fd()));
+            fs::read_link(&link).unwrap_or(link)
+        },
+        result,
+    )
+}
+
+fn emit<T, E, P>(action: AuditAction, path: P, result: &Result<T, E>)
+where
+    P: FnOnce() -> PathBuf,
+{
+    let slot = SINK.read().unwrap_or_else(|err| err.into_inner());
+    if let Some(sink) = slot.as_ref() {
+        sink.record(&AuditEvent {
+            timestamp: SystemTime::now(),
+            action,
+            path: path(),
+            succeeded: result.is_ok(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        create, create_exact, open_read, remove_if_fifo, Direction, FileFIFOExt, Mode, NamedPipe,
+    };
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn records_lifecycle_events() {
+        let file_name = "/tmp/audited-fifo";
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink_events = events.clone();
+        set_audit_sink(move |event: &AuditEvent| {
+            if event.path == Path::new(file_name) {
+                sink_events.lock().unwrap().push(event.clone());
+            }
+        });
+
+        create(file_name, Some(0o600)).expect("could not create fifo");
+        let _ = create(file_name, Some(0o600));
+        let _file = open_read(file_name).expect("could not open fifo for reading");
+        let pipe = NamedPipe::open(file_name, Direction::Read).expect("could not open fifo");
+        pipe.set_mode(Mode::owner_rw().group_read())
+            .expect("could not change fifo mode");
+        pipe.set_owner(None, None)
+            .expect("could not change fifo owner");
+        drop(pipe);
+        remove_if_fifo(file_name).expect("could not remove fifo");
+        create_exact(file_name, Some(0o640)).expect("could not create fifo");
+        remove_if_fifo(file_name).expect("could not remove fifo");
+        clear_audit_sink();
+
+        let events = events.lock().unwrap();
+        let actions: Vec<(AuditAction, bool)> =
+            events.iter().map(|e| (e.action, e.succeeded)).collect();
+        assert_eq!(
+            actions,
+            vec![
+                (AuditAction::Create { mode: 0o600 }, true),
+                (AuditAction::Create { mode: 0o600 }, false),
+                (AuditAction::Open, true),
+                (AuditAction::Open, true),
+                (AuditAction::SetMode { mode: 0o640 }, true),
+                (
+                    AuditAction::SetOwner {
+                        uid: None,
+                        gid: None
+                    },
+                    true
+                ),
+                (AuditAction::Close, true),
+                (AuditAction::Unlink, true),
+                (AuditAction::Create { mode: 0o640 }, true),
+                (AuditAction::SetMode { mode: 0o640 }, true),
+                (AuditAction::Unlink, true),
+            ]
+        );
+    }
+}
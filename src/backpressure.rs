@@ -0,0 +1,21 @@
+//! Provides `Backpressure`, which says what a writer does with a message
+//! when there is no room for it.
+
+/// What a writer does with a message when the pipe, or its spool, is full.
+///
+/// Messages which are dropped are still reported as written, and counted
+/// by the writer.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Backpressure {
+    /// Wait until there is room for the message.
+    Block,
+    /// Drop the message which does not fit.
+    DropNewest,
+    /// Drop the oldest messages held back until the new one fits. A writer
+    /// which holds nothing back drops the new message instead, as the pipe
+    /// cannot give back what is in it.
+    DropOldest,
+    /// Fail the write with `io::ErrorKind::WouldBlock`.
+    #[default]
+    Error,
+}
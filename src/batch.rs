@@ -0,0 +1,218 @@
+//! Provides `create_many`, which creates a set of pipes at arbitrary paths
+//! as a single all-or-nothing step.
+
+use super::{create, remove_if_fifo, Mode};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Describes one pipe to be created by `create_many`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PipeSpec {
+    path: PathBuf,
+    mode: Option<Mode>,
+}
+
+impl PipeSpec {
+    /// Describes a pipe at `path`, created with the default mode of `create`.
+    pub fn new<P: AsRef<Path>>(path: P) -> PipeSpec {
+        PipeSpec {
+            path: path.as_ref().to_path_buf(),
+            mode: None,
+        }
+    }
+
+    /// Sets the mode the pipe is created with.
+    pub fn mode(mut self, mode: Mode) -> PipeSpec {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Returns the path of the pipe.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The pipes created by a successful `create_many`, in the order given.
+///
+/// Unlike `PipeGroup`, the pipes are left in place when this is dropped.
+#[derive(Debug)]
+pub struct Created {
+    paths: Vec<PathBuf>,
+}
+
+impl Created {
+    /// Returns the paths of every created pipe.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.paths.iter().map(|path| path.as_path())
+    }
+
+    /// Consumes the record, returning the paths of every created pipe.
+    pub fn into_paths(self) -> Vec<PathBuf> {
+        self.paths
+    }
+}
+
+/// The error returned when `create_many` could not create every pipe.
+///
+/// By the time this is returned, the pipes which had already been created
+/// have been removed again.
+#[derive(Debug)]
+pub struct PartialFailure {
+    index: usize,
+    path: PathBuf,
+    error: io::Error,
+    not_removed: Vec<PathBuf>,
+}
+
+impl PartialFailure {
+    /// Returns the index into the specs of the pipe which failed.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the path of the pipe which failed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the error which stopped the batch.
+    pub fn error(&self) -> &io::Error {
+        &self.error
+    }
+
+    /// Returns the already created pipes which could not be removed during
+    /// rollback. This is normally empty.
+    pub fn not_removed(&self) -> &[PathBuf] {
+        &self.not_removed
+    }
+
+    /// Consumes the failure, returning the error which stopped the batch.
+    pub fn into_error(self) -> io::Error {
+        self.error
+    }
+}
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "could not create {}: {}",
+            self.path.display(),
+            self.error
+        )?;
+        for (n, path) in self.not_removed.iter().enumerate() {
+            let separator = if n == 0 {
+                " (could not roll back "
+            } else {
+                ", "
+            };
+            write!(f, "{}{}", separator, path.display())?;
+        }
+        if !self.not_removed.is_empty() {
+            write!(f, ")")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for PartialFailure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<PartialFailure> for io::Error {
+    fn from(failure: PartialFailure) -> io::Error {
+        io::Error::new(failure.error.kind(), failure)
+    }
+}
+
+/// Creates every pipe described by `specs`, in order. If any of them cannot
+/// be created, the ones created so far are removed again, so either all of
+/// the pipes exist afterwards or none of them do.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::{Mode, PipeSpec};
+///
+/// # let dir = unix_named_pipe::unique_path("fifo-dir");
+/// # fs::create_dir_all(&dir).unwrap();
+/// let created = unix_named_pipe::create_many(&[
+///     PipeSpec::new(dir.join("cmd")).mode(Mode::owner_rw().group_write()),
+///     PipeSpec::new(dir.join("events")),
+/// ])
+/// .expect("could not create fifos");
+/// assert_eq!(created.paths().count(), 2);
+/// # fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - Any error returned by `create`, wrapped in a `PartialFailure` which
+///   names the pipe that failed.
+pub fn create_many(specs: &[PipeSpec]) -> Result<Created, PartialFailure> {
+    let mut paths = Vec::with_capacity(specs.len());
+    for (index, spec) in specs.iter().enumerate() {
+        if let Err(error) = create(&spec.path, spec.mode.map(Mode::bits)) {
+            let not_removed = paths
+                .into_iter()
+                .rev()
+                .filter(|path| remove_if_fifo(path).is_err())
+                .collect();
+
+            return Err(PartialFailure {
+                index,
+                path: spec.path.clone(),
+                error,
+                not_removed,
+            });
+        }
+        paths.push(spec.path.clone());
+    }
+
+    Ok(Created { paths })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn create_many_all_or_nothing() {
+        let dir = "/tmp/batch-create";
+        fs::create_dir_all(dir).expect("could not create test dir");
+        let blocker = Path::new(dir).join("resp");
+        fs::write(&blocker, "").expect("could not write test file");
+
+        let specs = [
+            PipeSpec::new(Path::new(dir).join("cmd")),
+            PipeSpec::new(Path::new(dir).join("events")),
+            PipeSpec::new(&blocker),
+        ];
+        let failure = create_many(&specs).unwrap_err();
+        assert_eq!(failure.index(), 2);
+        assert_eq!(failure.path(), blocker.as_path());
+        assert_eq!(failure.error().kind(), io::ErrorKind::AlreadyExists);
+        assert!(failure.not_removed().is_empty());
+        assert!(failure
+            .to_string()
+            .starts_with(&format!("could not create {}: ", blocker.display())));
+        assert!(!specs[0].path().exists());
+        assert!(!specs[1].path().exists());
+        assert!(blocker.exists());
+
+        fs::remove_file(&blocker).expect("could not remove test file");
+        let created = create_many(&specs).expect("could not create fifos");
+        assert_eq!(created.into_paths().len(), 3);
+
+        fs::remove_dir_all(dir).expect("could not remove test dir");
+    }
+}
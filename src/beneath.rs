@@ -0,0 +1,205 @@
+//! Provides opens which are confined to a directory, for pipe names that come
+//! from untrusted clients.
+
+use super::audit::{self, AuditAction};
+use super::open_at;
+use libc::{c_int, EXDEV, O_DIRECTORY, O_NOFOLLOW, O_RDONLY};
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Component, Path};
+
+/// Opens a named pipe for reading at `path`, which must resolve to a file
+/// beneath the directory open as `dir` without crossing any symbolic link.
+/// The file is opened for non-blocking reads like `open_read`.
+///
+/// On Linux 5.6 and newer this uses `openat2` with `RESOLVE_BENEATH` and
+/// `RESOLVE_NO_SYMLINKS`. Elsewhere, or where `openat2` is unavailable, the
+/// path is walked one component at a time with `O_NOFOLLOW`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # let dir_name = &unix_named_pipe::unique_path("fifo-dir");
+/// # fs::create_dir_all(dir_name).unwrap();
+/// let spool = fs::File::open(dir_name).expect("could not open directory");
+/// # unix_named_pipe::create_at(&spool, "client.pipe", None).unwrap();
+/// let file = unix_named_pipe::open_read_beneath(&spool, "client.pipe")
+///     .expect("could not open fifo for reading");
+/// assert!(unix_named_pipe::open_read_beneath(&spool, "../client.pipe").is_err());
+/// # fs::remove_dir_all(dir_name).expect("could not remove fifo");
+/// ```
+///
+/// # Errors
+///
+/// - If `path` is absolute or would escape `dir` through `..`, the `EXDEV`
+///   error will be returned. The component-wise fallback refuses every `..`,
+///   even one which would stay inside `dir`.
+/// - If any component of `path` is a symbolic link, the `ELOOP` error will
+///   be returned.
+pub fn open_read_beneath<D: AsRawFd, P: AsRef<Path>>(dir: &D, path: P) -> io::Result<File> {
+    let result = open_beneath(dir.as_raw_fd(), path.as_ref(), O_RDONLY);
+    audit::record(AuditAction::Open, path.as_ref(), &result);
+
+    result
+}
+
+/// Opens a named pipe for writing at `path`, confined beneath the directory
+/// open as `dir` in the same way as `open_read_beneath`. The file is opened
+/// for non-blocking writes like `open_write`.
+pub fn open_write_beneath<D: AsRawFd, P: AsRef<Path>>(dir: &D, path: P) -> io::Result<File> {
+    let result = open_beneath(dir.as_raw_fd(), path.as_ref(), libc::O_WRONLY);
+    audit::record(AuditAction::Open, path.as_ref(), &result);
+
+    result
+}
+
+fn open_beneath(dirfd: RawFd, path: &Path, flags: c_int) -> io::Result<File> {
+    #[cfg(target_os = "linux")]
+    match openat2_beneath(dirfd, path, flags) {
+        // Kernels before 5.6 lack openat2, and some seccomp profiles deny it.
+        Err(ref err) if err.raw_os_error() == Some(libc::ENOSYS) => {}
+        Err(ref err) if err.raw_os_error() == Some(libc::EPERM) => {}
+        result => return result,
+    }
+
+    walk_beneath(dirfd, path, flags)
+}
+
+#[cfg(target_os = "linux")]
+fn openat2_beneath(dirfd: RawFd, path: &Path, flags: c_int) -> io::Result<File> {
+    use super::{path_to_cstring, retry_interrupted};
+    use std::mem;
+    use std::os::unix::io::FromRawFd;
+
+    let c_path = path_to_cstring(path)?;
+    let mut how: libc::open_how = unsafe { mem::zeroed() };
+    how.flags = (flags | libc::O_NONBLOCK | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_SYMLINKS;
+
+    let fd = retry_interrupted(|| unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dirfd,
+            c_path.as_ptr(),
+            &how as *const libc::open_how,
+            mem::size_of::<libc::open_how>(),
+        )
+    });
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { File::from_raw_fd(fd as RawFd) })
+}
+
+/// Portable equivalent of `openat2_beneath`: every directory on the way is
+/// opened with `O_NOFOLLOW`, and `..` or absolute paths are refused outright.
+fn walk_beneath(dirfd: RawFd, path: &Path, flags: c_int) -> io::Result<File> {
+    let mut names = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => names.push(name),
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) | Component::ParentDir => {
+                return Err(io::Error::from_raw_os_error(EXDEV));
+            }
+        }
+    }
+
+    let (last, parents) = match names.split_last() {
+        Some(split) => split,
+        None => return Err(io::Error::from_raw_os_error(libc::ENOENT)),
+    };
+
+    let mut current: Option<File> = None;
+    for name in parents {
+        let at = current.as_ref().map_or(dirfd, |dir| dir.as_raw_fd());
+        let dir = open_at(at, Path::new(name), O_RDONLY | O_DIRECTORY | O_NOFOLLOW)?;
+        current = Some(dir);
+    }
+
+    let at = current.as_ref().map_or(dirfd, |dir| dir.as_raw_fd());
+    open_at(at, Path::new(last), flags | O_NOFOLLOW)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create_at, open_write, FileFIFOExt};
+    use super::*;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::symlink;
+
+    fn make_spool(dirname: &str) -> File {
+        fs::create_dir_all(format!("{}/sub", dirname)).expect("could not create test dir");
+        let dir = File::open(dirname).expect("could not open test dir");
+        create_at(&dir, "sub/pipe", None).expect("could not create pipe");
+        symlink("sub/pipe", format!("{}/link", dirname)).expect("could not create symlink");
+
+        dir
+    }
+
+    #[test]
+    fn open_beneath_nested_path() {
+        let dirname = "/tmp/beneath";
+        let dir = make_spool(dirname);
+
+        let mut read_file =
+            open_read_beneath(&dir, "sub/pipe").expect("could not open pipe for reading");
+        let mut write_file =
+            open_write_beneath(&dir, "./sub/pipe").expect("could not open pipe for writing");
+        write_file
+            .write_all(b"ok")
+            .expect("could not write to pipe");
+        let mut actual = [0; 2];
+        read_file
+            .read_exact(&mut actual)
+            .expect("could not read from pipe");
+        assert_eq!(&actual, b"ok");
+
+        fs::remove_dir_all(dirname).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn open_beneath_rejects_escapes() {
+        let dirname = "/tmp/beneath-escape";
+        let dir = make_spool(dirname);
+        let _reader = open_read_beneath(&dir, "sub/pipe").unwrap();
+        assert!(open_write("/tmp/beneath-escape/link").is_ok());
+
+        for flags in &[O_RDONLY, libc::O_WRONLY] {
+            let err = open_beneath(dir.as_raw_fd(), Path::new("link"), *flags).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+            let err = walk_beneath(dir.as_raw_fd(), Path::new("link"), *flags).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+
+            let escape = Path::new("sub/../../beneath-escape/sub/pipe");
+            let err = open_beneath(dir.as_raw_fd(), escape, *flags).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(EXDEV));
+            let err = walk_beneath(dir.as_raw_fd(), escape, *flags).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(EXDEV));
+
+            let absolute = Path::new("/tmp/beneath-escape/sub/pipe");
+            let err = walk_beneath(dir.as_raw_fd(), absolute, *flags).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(EXDEV));
+        }
+
+        fs::remove_dir_all(dirname).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn walk_beneath_opens_nested_path() {
+        let dirname = "/tmp/beneath-walk";
+        let dir = make_spool(dirname);
+
+        let file = walk_beneath(dir.as_raw_fd(), Path::new("sub/pipe"), O_RDONLY)
+            .expect("could not open pipe for reading");
+        assert!(file.is_fifo().unwrap());
+        assert!(file.is_nonblocking().unwrap());
+
+        fs::remove_dir_all(dirname).expect("could not remove test dir");
+    }
+}
@@ -0,0 +1,311 @@
+//! Provides `Broadcaster`, which writes every message to a set of named
+//! pipes, so one event stream can be fanned out to several consumers.
+
+use super::error::is_no_reader;
+use super::sigpipe::write_blocking_sigpipe;
+use super::{open_write, FileFIFOExt, SpoolingWriter};
+use libc::EPIPE;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::AsFd;
+use std::path::{Path, PathBuf};
+
+/// What a `Broadcaster` does when a destination cannot take a message.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DeliveryPolicy {
+    /// Skip the message for this destination while it has no reader or its
+    /// pipe is full, and count it.
+    #[default]
+    Skip,
+    /// Hold the message back in a `SpoolingWriter` until the destination
+    /// takes it.
+    Buffer,
+    /// Fail the broadcast with the error.
+    FailFast,
+}
+
+/// Where a destination's messages go.
+#[derive(Debug)]
+enum Sink {
+    Pipe(Option<File>),
+    Spool(SpoolingWriter),
+}
+
+#[derive(Debug)]
+struct Destination {
+    path: PathBuf,
+    policy: DeliveryPolicy,
+    sink: Sink,
+    skipped: u64,
+}
+
+impl Destination {
+    fn deliver(&mut self, message: &[u8]) -> io::Result<()> {
+        let pipe = match self.sink {
+            Sink::Spool(ref mut writer) => return writer.write_all(message),
+            Sink::Pipe(ref mut pipe) => pipe,
+        };
+
+        let result = match pipe {
+            Some(ref file) => write_message(file, message),
+            None => open_write(&self.path).and_then(|file| {
+                let file = pipe.get_or_insert(file);
+                write_message(file, message)
+            }),
+        };
+        let err = match result {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        let hung_up = err.raw_os_error() == Some(EPIPE);
+        if hung_up {
+            *pipe = None;
+        }
+        let full = err.kind() == io::ErrorKind::WouldBlock;
+        if self.policy == DeliveryPolicy::Skip && (hung_up || full || is_no_reader(&err)) {
+            self.skipped += 1;
+            return Ok(());
+        }
+
+        Err(err)
+    }
+}
+
+/// Writes all of `message` to `file`, or none of it if the pipe is full.
+///
+/// Once part of a message is in the pipe, the rest is waited for, so the
+/// reader never sees half a message.
+fn write_message(file: &File, message: &[u8]) -> io::Result<()> {
+    let mut written = 0;
+    while written < message.len() {
+        match write_blocking_sigpipe(file.as_fd(), &message[written..]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock && written > 0 => {
+                file.poll_writable(None)?;
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+/// A writer which writes every message to each of a set of FIFOs.
+///
+/// Each destination has its own `DeliveryPolicy` for when it has no reader
+/// or its pipe is full, and destinations can be added and removed between
+/// messages. Destinations are written in the order they were added, and
+/// every call to `write` is one message.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::Write;
+/// use unix_named_pipe::{Broadcaster, DeliveryPolicy};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # let other_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// # unix_named_pipe::create(other_name, None).unwrap();
+/// # let reader = unix_named_pipe::open_read(file_name).unwrap();
+/// let mut broadcaster = Broadcaster::new();
+/// broadcaster.add(file_name, DeliveryPolicy::FailFast);
+/// broadcaster.add(other_name, DeliveryPolicy::Skip);
+/// broadcaster.write_all(b"event\n").expect("could not broadcast event");
+/// assert_eq!(broadcaster.skipped_messages(other_name), Some(1));
+/// # fs::remove_file(file_name).unwrap();
+/// # fs::remove_file(other_name).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct Broadcaster {
+    destinations: Vec<Destination>,
+}
+
+impl Broadcaster {
+    /// Creates a broadcaster without any destinations.
+    pub fn new() -> Broadcaster {
+        Broadcaster::default()
+    }
+
+    /// Adds the FIFO at `path` as a destination, replacing it if it is one
+    /// already. The pipe is opened when the first message is written.
+    ///
+    /// With `DeliveryPolicy::Buffer`, messages are held back by a
+    /// `SpoolingWriter` with its defaults; use `add_spool` to set it up.
+    pub fn add<P: AsRef<Path>>(&mut self, path: P, policy: DeliveryPolicy) {
+        let sink = match policy {
+            DeliveryPolicy::Buffer => Sink::Spool(SpoolingWriter::new(&path)),
+            DeliveryPolicy::Skip | DeliveryPolicy::FailFast => Sink::Pipe(None),
+        };
+        self.insert(path.as_ref(), policy, sink);
+    }
+
+    /// Adds `writer` as a destination with `DeliveryPolicy::Buffer`,
+    /// replacing any destination for its path.
+    pub fn add_spool(&mut self, writer: SpoolingWriter) {
+        let path = writer.path().to_path_buf();
+        self.insert(&path, DeliveryPolicy::Buffer, Sink::Spool(writer));
+    }
+
+    fn insert(&mut self, path: &Path, policy: DeliveryPolicy, sink: Sink) {
+        let destination = Destination {
+            path: path.to_path_buf(),
+            policy,
+            sink,
+            skipped: 0,
+        };
+        match self.position(path) {
+            Some(index) => self.destinations[index] = destination,
+            None => self.destinations.push(destination),
+        }
+    }
+
+    /// Removes the destination for `path`, closing its pipe. Returns
+    /// whether there was one.
+    ///
+    /// Anything a buffering destination still holds back is lost; flush
+    /// the broadcaster first to give it a last chance.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        match self.position(path.as_ref()) {
+            Some(index) => {
+                self.destinations.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+        self.destinations
+            .iter()
+            .position(|destination| destination.path == path)
+    }
+
+    /// Returns the paths of the destinations, in the order they are
+    /// written.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.destinations
+            .iter()
+            .map(|destination| destination.path.as_path())
+    }
+
+    /// Returns how many destinations there are.
+    pub fn len(&self) -> usize {
+        self.destinations.len()
+    }
+
+    /// Returns whether there are no destinations.
+    pub fn is_empty(&self) -> bool {
+        self.destinations.is_empty()
+    }
+
+    /// Returns the policy of the destination for `path`, if there is one.
+    pub fn policy<P: AsRef<Path>>(&self, path: P) -> Option<DeliveryPolicy> {
+        self.position(path.as_ref())
+            .map(|index| self.destinations[index].policy)
+    }
+
+    /// Returns how many messages the destination for `path` has skipped,
+    /// if there is one.
+    pub fn skipped_messages<P: AsRef<Path>>(&self, path: P) -> Option<u64> {
+        self.position(path.as_ref())
+            .map(|index| self.destinations[index].skipped)
+    }
+
+    /// Writes `message` to every destination.
+    ///
+    /// # Errors
+    ///
+    /// The first error from a destination which does not skip the message,
+    /// after which the destinations behind it are not written. With
+    /// `DeliveryPolicy::FailFast` this includes `PipeError::NoReader` and
+    /// `PipeError::ReaderGone`, and `io::ErrorKind::WouldBlock` if its pipe
+    /// is full; with `DeliveryPolicy::Buffer`, whatever its `SpoolingWriter`
+    /// returns.
+    pub fn broadcast(&mut self, message: &[u8]) -> io::Result<()> {
+        for destination in &mut self.destinations {
+            destination.deliver(message)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for Broadcaster {
+    /// Writes all of `buf` to every destination, as `broadcast` does.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.broadcast(buf).map(|()| buf.len())
+    }
+
+    /// Writes as much as the buffering destinations hold back as their
+    /// pipes will take.
+    fn flush(&mut self) -> io::Result<()> {
+        for destination in &mut self.destinations {
+            if let Sink::Spool(ref mut writer) = destination.sink {
+                writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, PipeError};
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn policies_apply_per_destination() {
+        let (skip, buffer, fail) = (
+            "/tmp/broadcast-skip.pipe",
+            "/tmp/broadcast-buffer.pipe",
+            "/tmp/broadcast-fail.pipe",
+        );
+        for file_name in &[skip, buffer, fail] {
+            create(file_name, None).expect("could not create fifo");
+        }
+
+        let mut broadcaster = Broadcaster::new();
+        broadcaster.add(skip, DeliveryPolicy::Skip);
+        broadcaster.add(buffer, DeliveryPolicy::Buffer);
+        broadcaster.write_all(b"one,").unwrap();
+        assert_eq!(broadcaster.skipped_messages(skip), Some(1));
+
+        let skip_reader = open_read(skip).unwrap();
+        let buffer_reader = open_read(buffer).unwrap();
+        broadcaster.write_all(b"two,").unwrap();
+        let mut received = Vec::new();
+        skip_reader.read_available(&mut received).unwrap();
+        assert_eq!(received, b"two,");
+        received.clear();
+        buffer_reader.read_available(&mut received).unwrap();
+        assert_eq!(received, b"one,two,");
+
+        broadcaster.add(fail, DeliveryPolicy::FailFast);
+        let err = broadcaster.write_all(b"three,").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENXIO));
+        assert!(matches!(PipeError::of(&err), Some(PipeError::NoReader)));
+
+        assert!(broadcaster.remove(fail));
+        assert!(!broadcaster.remove(fail));
+        broadcaster.write_all(b"four,").unwrap();
+        assert_eq!(
+            broadcaster.paths().collect::<Vec<_>>(),
+            [Path::new(skip), Path::new(buffer)]
+        );
+        received.clear();
+        skip_reader.read_available(&mut received).unwrap();
+        assert_eq!(received, b"three,four,");
+
+        for file_name in &[skip, buffer, fail] {
+            fs::remove_file(file_name).expect("could not remove fifo");
+        }
+    }
+}
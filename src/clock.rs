@@ -0,0 +1,101 @@
+//! Provides the `Clock` abstraction used by the time-based features of this
+//! crate, so that they can be driven by a mock clock in tests.
+//!
+//! Waits which `poll` a pipe still block in the kernel, for no longer than
+//! the clock says is left. Everything in this crate blocks the calling
+//! thread, so there is no async runtime to take timers from.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A source of time and a way to wait for it to pass.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+    /// Waits until `duration` has passed.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real clock, backed by `Instant::now` and `thread::sleep`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// A manually driven clock for deterministic tests. Time only moves when
+/// `advance` or `sleep` is called, and `sleep` returns immediately.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// use std::time::Duration;
+/// use unix_named_pipe::{Clock, MockClock};
+///
+/// let clock = MockClock::new();
+/// let start = clock.now();
+/// clock.sleep(Duration::from_secs(60));
+/// assert_eq!(clock.now() - start, Duration::from_secs(60));
+/// ```
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<Instant>,
+}
+
+impl MockClock {
+    /// Creates a mock clock starting at the current instant.
+    pub fn new() -> MockClock {
+        MockClock {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|err| err.into_inner());
+        *now += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> MockClock {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_only_moves_when_told() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::from_secs(1));
+        clock.sleep(Duration::from_secs(2));
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+    }
+}
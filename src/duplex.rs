@@ -0,0 +1,287 @@
+//! Provides `Duplex`, which pairs a FIFO to read from with a FIFO to write
+//! to, so two processes can talk both ways.
+
+use super::sigpipe::write_blocking_sigpipe;
+use super::{
+    ensure_exists, open_read, open_write_retry, remove_if_fifo, FileFIFOExt, PipeError, Readiness,
+    RetryPolicy,
+};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsFd;
+use std::path::{Path, PathBuf};
+
+/// Both ends of a two-way connection over a pair of FIFOs.
+///
+/// Each side reads from the FIFO the other writes to. Opening a write end
+/// fails while its FIFO has no reader, so both sides open their read end
+/// first, and then retry opening their write end until the other side has
+/// opened its read end. `bind` creates the FIFOs for the side which owns
+/// them, and `connect` opens them for the other.
+///
+/// Reads wait for data, and return `Ok(0)` once the other side has hung
+/// up; writes wait for room in the pipe. Until a side has heard from the
+/// other, it cannot tell whether the other has opened its write end yet,
+/// so a side which hangs up straight after connecting may leave the other
+/// waiting in `connect` for good unless its `RetryPolicy` has a deadline.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// use std::io::{Read, Write};
+/// use std::thread;
+/// use unix_named_pipe::Duplex;
+///
+/// # let requests = unix_named_pipe::unique_path("fifo");
+/// # let responses = unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(&requests, None).unwrap();
+/// # unix_named_pipe::create(&responses, None).unwrap();
+/// # let (server_in, server_out) = (requests.clone(), responses.clone());
+/// let server = thread::spawn(move || {
+///     let mut server = Duplex::bind(&server_in, &server_out).expect("could not bind");
+///     let mut request = [0; 4];
+///     server.read_exact(&mut request).unwrap();
+///     server.write_all(b"pong").unwrap();
+/// });
+///
+/// let mut client = Duplex::connect(&responses, &requests).expect("could not connect");
+/// client.write_all(b"ping").unwrap();
+/// let mut response = [0; 4];
+/// client.read_exact(&mut response).unwrap();
+/// assert_eq!(&response, b"pong");
+/// server.join().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Duplex {
+    reader: File,
+    writer: File,
+    in_path: PathBuf,
+    out_path: PathBuf,
+    bound: bool,
+}
+
+impl Duplex {
+    /// Connects to a peer by reading from the FIFO at `in_path` and writing
+    /// to the FIFO at `out_path`, waiting for as long as it takes the peer
+    /// to open `out_path` for reading.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by `open_read` or `open_write_retry`.
+    pub fn connect<P: AsRef<Path>, Q: AsRef<Path>>(in_path: P, out_path: Q) -> io::Result<Duplex> {
+        Duplex::connect_retry(in_path, out_path, &RetryPolicy::new())
+    }
+
+    /// Connects like `connect`, waiting for the peer as described by
+    /// `policy`.
+    ///
+    /// # Errors
+    ///
+    /// - If the peer does not open `out_path` for reading before the
+    ///   deadline of `policy`, `Err(io::ErrorKind::TimedOut)` will be
+    ///   returned, carrying `PipeError::TimedOut`.
+    /// - Any other error returned by `open_read` or `open_write_retry`.
+    pub fn connect_retry<P: AsRef<Path>, Q: AsRef<Path>>(
+        in_path: P,
+        out_path: Q,
+        policy: &RetryPolicy,
+    ) -> io::Result<Duplex> {
+        let (in_path, out_path) = (in_path.as_ref(), out_path.as_ref());
+        let reader = open_read(in_path)?;
+        let writer = open_write_retry(out_path, policy)?;
+
+        Ok(Duplex {
+            reader,
+            writer,
+            in_path: in_path.to_path_buf(),
+            out_path: out_path.to_path_buf(),
+            bound: false,
+        })
+    }
+
+    /// Creates the FIFOs at `in_path` and `out_path` unless they exist, and
+    /// connects through them as `connect` does. Both are removed again when
+    /// the `Duplex` is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by `ensure_exists` or `connect`. FIFOs which have
+    /// been created are left in place if connecting fails.
+    pub fn bind<P: AsRef<Path>, Q: AsRef<Path>>(in_path: P, out_path: Q) -> io::Result<Duplex> {
+        Duplex::bind_retry(in_path, out_path, &RetryPolicy::new())
+    }
+
+    /// Binds like `bind`, waiting for the peer as described by `policy`.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by `ensure_exists` or `connect_retry`.
+    pub fn bind_retry<P: AsRef<Path>, Q: AsRef<Path>>(
+        in_path: P,
+        out_path: Q,
+        policy: &RetryPolicy,
+    ) -> io::Result<Duplex> {
+        ensure_exists(&in_path, None)?;
+        ensure_exists(&out_path, None)?;
+
+        let mut duplex = Duplex::connect_retry(in_path, out_path, policy)?;
+        duplex.bound = true;
+        Ok(duplex)
+    }
+
+    /// Returns the path of the FIFO which is read from.
+    pub fn in_path(&self) -> &Path {
+        &self.in_path
+    }
+
+    /// Returns the path of the FIFO which is written to.
+    pub fn out_path(&self) -> &Path {
+        &self.out_path
+    }
+
+    /// Returns the read end.
+    pub fn reader(&self) -> &File {
+        &self.reader
+    }
+
+    /// Returns the write end.
+    pub fn writer(&self) -> &File {
+        &self.writer
+    }
+}
+
+impl Read for Duplex {
+    /// Reads into `buf`, waiting for data to arrive.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by `read` or `poll`.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match self.reader.read(buf) {
+                // No writer is attached, either since the peer has not
+                // opened its write end yet, or since it has hung up. Only
+                // the latter is reported by `poll`.
+                Ok(0) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+
+            if self.reader.poll_readable(None)? == Readiness::HangUp {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl Write for Duplex {
+    /// Writes some of `buf`, waiting for room in the pipe first if it is
+    /// full.
+    ///
+    /// # Errors
+    ///
+    /// - If the peer has hung up, `Err(io::ErrorKind::BrokenPipe)` will be
+    ///   returned, with the raw `EPIPE` code.
+    /// - Any other error returned by `write` or `poll`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match write_blocking_sigpipe(self.writer.as_fd(), buf) {
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            if self.writer.poll_writable(None)? == Readiness::HangUp {
+                return Err(PipeError::ReaderGone.into());
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Duplex {
+    /// Removes the FIFOs if they were bound.
+    fn drop(&mut self) {
+        if self.bound {
+            let _ = remove_if_fifo(&self.in_path);
+            let _ = remove_if_fifo(&self.out_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::create;
+    use super::*;
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn bound_pair_talks_both_ways() {
+        let requests = "/tmp/duplex-requests.pipe";
+        let responses = "/tmp/duplex-responses.pipe";
+
+        let server = thread::spawn(move || {
+            let mut server = Duplex::bind(requests, responses).expect("could not bind");
+            let mut request = [0; 4];
+            server.read_exact(&mut request).unwrap();
+            server.write_all(b"pong").unwrap();
+
+            let mut rest = Vec::new();
+            server.read_to_end(&mut rest).unwrap();
+            let err = server.write_all(b"late").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+            (request, rest)
+        });
+
+        while !Path::new(responses).exists() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let mut client = Duplex::connect(responses, requests).expect("could not connect");
+        client.write_all(b"ping").unwrap();
+        let mut response = [0; 4];
+        client.read_exact(&mut response).unwrap();
+        assert_eq!(&response, b"pong");
+        drop(client);
+
+        let (request, rest) = server.join().unwrap();
+        assert_eq!(&request, b"ping");
+        assert!(rest.is_empty());
+        assert!(!Path::new(requests).exists());
+        assert!(!Path::new(responses).exists());
+    }
+
+    #[test]
+    fn connect_times_out_without_peer() {
+        let requests = "/tmp/duplex-lonely-requests.pipe";
+        let responses = "/tmp/duplex-lonely-responses.pipe";
+        create(requests, None).expect("could not create fifo");
+        create(responses, None).expect("could not create fifo");
+
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(5))
+            .deadline(Duration::from_millis(50));
+        let err = Duplex::connect_retry(requests, responses, &policy).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        match PipeError::of(&err) {
+            Some(PipeError::TimedOut { path, timeout }) => {
+                assert_eq!(path.as_deref(), Some(Path::new(responses)));
+                assert_eq!(timeout, Duration::from_millis(50));
+            }
+            other => panic!("expected TimedOut, got {:?}", other),
+        }
+
+        fs::remove_file(requests).expect("could not remove fifo");
+        fs::remove_file(responses).expect("could not remove fifo");
+    }
+}
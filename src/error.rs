@@ -0,0 +1,442 @@
+//! Provides `PipeError`, the structured errors carried inside the
+//! `io::Error`s returned throughout this crate.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::FileType;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The reason a named pipe operation failed.
+///
+/// Functions in this crate keep returning `io::Result`. A failed system
+/// call is returned as the raw OS error, so `io::Error::raw_os_error` still
+/// gives its code, and a `PipeError` is the payload of the `io::Error`
+/// where there is more to say than the error kind. Use `PipeError::of` to
+/// get it back out. A `PipeError` which explains a failed system call, such
+/// as `BufferTooLarge`, gives the raw OS error as its `source` instead.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::ffi::OsStr;
+/// use std::os::unix::ffi::OsStrExt;
+/// use unix_named_pipe::PipeError;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let err = unix_named_pipe::create(file_name, None).unwrap_err();
+/// assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+///
+/// let err = unix_named_pipe::create(OsStr::from_bytes(b"fifo\0"), None).unwrap_err();
+/// assert!(matches!(PipeError::of(&err), Some(PipeError::InvalidPath { .. })));
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum PipeError {
+    /// The FIFO could not be opened for writing because no reader has it
+    /// open, which the kernel reports as `ENXIO`. Errors of this kind are
+    /// returned as the raw `ENXIO`, and `PipeError::of` recognises them.
+    NoReader,
+    /// A write failed because the reader closed its end of the FIFO, which
+    /// the kernel reports as `EPIPE`. Errors of this kind are returned as
+    /// the raw `EPIPE`, and `PipeError::of` recognises them.
+    ReaderGone,
+    /// A FIFO was expected, but the file is of another type, such as a
+    /// directory or a regular file.
+    NotAFifo {
+        /// What was being done, such as `"remove"`.
+        action: &'static str,
+        /// The path of the file, if it is known.
+        path: Option<PathBuf>,
+        /// The type the file actually has.
+        actual: FileType,
+    },
+    /// The path contains an interior NUL byte, so it cannot be passed to
+    /// the operating system.
+    InvalidPath {
+        /// The offending path.
+        path: PathBuf,
+    },
+    /// The FIFO at `path` was replaced by another file before it could be
+    /// opened.
+    Replaced {
+        /// The path which no longer refers to the created FIFO.
+        path: PathBuf,
+    },
+    /// The FIFO at `path` is owned by the user `actual` instead of the
+    /// expected `expected`.
+    WrongOwner {
+        /// The path of the FIFO.
+        path: PathBuf,
+        /// The uid the FIFO should be owned by.
+        expected: u32,
+        /// The uid the FIFO is owned by.
+        actual: u32,
+    },
+    /// The FIFO at `path` has the permission bits `actual`, which grant more
+    /// than the `allowed` bits.
+    ModeTooWide {
+        /// The path of the FIFO.
+        path: PathBuf,
+        /// The most the permissions may grant.
+        allowed: u32,
+        /// The permissions the FIFO has.
+        actual: u32,
+    },
+    /// The FIFO at `path` has the permission bits `actual` instead of the
+    /// `expected` bits.
+    ModeMismatch {
+        /// The path of the FIFO.
+        path: PathBuf,
+        /// The permissions the FIFO should have.
+        expected: u32,
+        /// The permissions the FIFO has.
+        actual: u32,
+    },
+    /// The pipe buffer could not be resized to `requested` bytes, because
+    /// that is above the `max` of `/proc/sys/fs/pipe-max-size` and the
+    /// process lacks `CAP_SYS_RESOURCE`. The kernel reports this as `EPERM`,
+    /// which is kept as the `source`.
+    BufferTooLarge {
+        /// The buffer size which was asked for.
+        requested: usize,
+        /// The limit from `/proc/sys/fs/pipe-max-size`.
+        max: usize,
+        /// The raw `EPERM` error returned by `fcntl`.
+        source: Arc<io::Error>,
+    },
+    /// A message of `len` bytes could not be written atomically, because it
+    /// is larger than the `max` bytes a pipe writes in one piece.
+    NotAtomic {
+        /// The length of the message.
+        len: usize,
+        /// The largest atomic write, `PIPE_BUF`.
+        max: usize,
+    },
+    /// Nothing arrived within `timeout`, or the other end of the FIFO did
+    /// not attach in time.
+    TimedOut {
+        /// The path of the FIFO, if it is known.
+        path: Option<PathBuf>,
+        /// How long was waited.
+        timeout: Duration,
+    },
+    /// A message of `len` bytes could not be held back until the pipe has
+    /// room, because the spool already holds all of its `capacity` bytes.
+    SpoolFull {
+        /// The length of the message.
+        len: usize,
+        /// How many bytes the spool holds at most.
+        capacity: usize,
+    },
+    /// A message for `channel` could not be queued, because its queue
+    /// already holds all of its `capacity` messages.
+    QueueFull {
+        /// The channel the message was for.
+        channel: u32,
+        /// How many messages the queue holds at most.
+        capacity: usize,
+    },
+}
+
+impl PipeError {
+    /// Returns the `PipeError` carried by `err`, if there is one, or the one
+    /// a raw OS error such as `ENXIO` stands for.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::PipeError;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let err = unix_named_pipe::open_write(file_name).unwrap_err();
+    /// assert_eq!(err.raw_os_error(), Some(libc::ENXIO));
+    /// assert!(matches!(PipeError::of(&err), Some(PipeError::NoReader)));
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    pub fn of(err: &io::Error) -> Option<PipeError> {
+        if let Some(inner) = err.get_ref() {
+            return inner.downcast_ref::<PipeError>().cloned();
+        }
+
+        match err.raw_os_error() {
+            Some(libc::ENXIO) => Some(PipeError::NoReader),
+            Some(libc::EPIPE) => Some(PipeError::ReaderGone),
+            _ => None,
+        }
+    }
+
+    /// Returns the `io::ErrorKind` this error is reported with.
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            PipeError::NoReader => io::Error::from_raw_os_error(libc::ENXIO).kind(),
+            PipeError::ReaderGone => io::ErrorKind::BrokenPipe,
+            PipeError::NotAFifo { .. }
+            | PipeError::InvalidPath { .. }
+            | PipeError::NotAtomic { .. } => io::ErrorKind::InvalidInput,
+            PipeError::Replaced { .. } | PipeError::ModeMismatch { .. } => io::ErrorKind::Other,
+            PipeError::WrongOwner { .. }
+            | PipeError::ModeTooWide { .. }
+            | PipeError::BufferTooLarge { .. } => io::ErrorKind::PermissionDenied,
+            PipeError::TimedOut { .. } => io::ErrorKind::TimedOut,
+            PipeError::SpoolFull { .. } | PipeError::QueueFull { .. } => io::ErrorKind::WouldBlock,
+        }
+    }
+
+    /// Returns the path the failed operation was on, if it is known.
+    pub fn path(&self) -> Option<&Path> {
+        match *self {
+            PipeError::InvalidPath { ref path }
+            | PipeError::Replaced { ref path }
+            | PipeError::WrongOwner { ref path, .. }
+            | PipeError::ModeTooWide { ref path, .. }
+            | PipeError::ModeMismatch { ref path, .. } => Some(path),
+            PipeError::NotAFifo { ref path, .. } | PipeError::TimedOut { ref path, .. } => {
+                path.as_deref()
+            }
+            PipeError::NoReader
+            | PipeError::ReaderGone
+            | PipeError::BufferTooLarge { .. }
+            | PipeError::NotAtomic { .. }
+            | PipeError::SpoolFull { .. }
+            | PipeError::QueueFull { .. } => None,
+        }
+    }
+
+    /// Returns the raw OS error code, if this error came from a system call.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match *self {
+            PipeError::NoReader => Some(libc::ENXIO),
+            PipeError::ReaderGone => Some(libc::EPIPE),
+            PipeError::BufferTooLarge { ref source, .. } => source.raw_os_error(),
+            PipeError::NotAFifo { .. }
+            | PipeError::InvalidPath { .. }
+            | PipeError::Replaced { .. }
+            | PipeError::WrongOwner { .. }
+            | PipeError::ModeTooWide { .. }
+            | PipeError::ModeMismatch { .. }
+            | PipeError::NotAtomic { .. }
+            | PipeError::TimedOut { .. }
+            | PipeError::SpoolFull { .. }
+            | PipeError::QueueFull { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for PipeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PipeError::NoReader => write!(f, "could not open fifo for writing: no reader attached"),
+            PipeError::ReaderGone => write!(f, "could not write to fifo: reader has gone away"),
+            PipeError::NotAFifo {
+                action,
+                ref path,
+                actual,
+            } => {
+                match *path {
+                    Some(ref path) => write!(f, "could not {} {}", action, path.display())?,
+                    None => write!(f, "could not {} file", action)?,
+                }
+                write!(f, ": expected fifo, is actually {}", describe(actual))
+            }
+            PipeError::InvalidPath { ref path } => {
+                write!(f, "invalid path {}: contains a NUL byte", path.display())
+            }
+            PipeError::Replaced { ref path } => {
+                write!(
+                    f,
+                    "could not open {}: path was replaced after creation",
+                    path.display()
+                )
+            }
+            PipeError::WrongOwner {
+                ref path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "expected fifo at {} to be owned by uid {}, is owned by {}",
+                path.display(),
+                expected,
+                actual
+            ),
+            PipeError::ModeTooWide {
+                ref path,
+                allowed,
+                actual,
+            } => write!(
+                f,
+                "expected fifo at {} to have mode at most {:o}, has {:o}",
+                path.display(),
+                allowed,
+                actual
+            ),
+            PipeError::ModeMismatch {
+                ref path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "expected fifo at {} to have mode {:o}, has {:o}",
+                path.display(),
+                expected,
+                actual
+            ),
+            PipeError::BufferTooLarge { requested, max, .. } => write!(
+                f,
+                "could not resize pipe buffer to {} bytes: exceeds fs.pipe-max-size of {} bytes",
+                requested, max
+            ),
+            PipeError::NotAtomic { len, max } => write!(
+                f,
+                "could not write {} bytes atomically: exceeds PIPE_BUF of {} bytes",
+                len, max
+            ),
+            PipeError::TimedOut {
+                path: Some(ref path),
+                timeout,
+            } => write!(
+                f,
+                "timed out after {:?} waiting for {}",
+                timeout,
+                path.display()
+            ),
+            PipeError::TimedOut {
+                path: None,
+                timeout,
+            } => write!(f, "timed out after {:?} waiting for fifo", timeout),
+            PipeError::SpoolFull { len, capacity } => write!(
+                f,
+                "could not spool {} bytes: exceeds spool capacity of {} bytes",
+                len, capacity
+            ),
+            PipeError::QueueFull { channel, capacity } => write!(
+                f,
+                "could not queue message for channel {}: queue is full at {} messages",
+                channel, capacity
+            ),
+        }
+    }
+}
+
+impl Error for PipeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PipeError::BufferTooLarge { ref source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+/// Names a file type for error messages.
+fn describe(file_type: FileType) -> &'static str {
+    if file_type.is_dir() {
+        "a directory"
+    } else if file_type.is_file() {
+        "a regular file"
+    } else if file_type.is_symlink() {
+        "a symbolic link"
+    } else if file_type.is_socket() {
+        "a socket"
+    } else if file_type.is_block_device() {
+        "a block device"
+    } else if file_type.is_char_device() {
+        "a character device"
+    } else if file_type.is_fifo() {
+        "a fifo"
+    } else {
+        "of unknown type"
+    }
+}
+
+/// Builds the error for finding a file of type `actual` where a FIFO was
+/// expected.
+pub(crate) fn not_a_fifo(action: &'static str, path: Option<&Path>, actual: FileType) -> io::Error {
+    PipeError::NotAFifo {
+        action,
+        path: path.map(Path::to_path_buf),
+        actual,
+    }
+    .into()
+}
+
+/// Returns whether `err` is the `ENXIO` of opening a pipe with no reader.
+pub(crate) fn is_no_reader(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ENXIO)
+}
+
+/// Turns the `EISDIR` from opening a directory at `path` into a
+/// `PipeError::NotAFifo`, passing every other error through untouched.
+pub(crate) fn open_error(err: io::Error, path: &Path) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::EISDIR) => match std::fs::metadata(path) {
+            Ok(metadata) => not_a_fifo("open", Some(path), metadata.file_type()),
+            Err(_) => err,
+        },
+        _ => err,
+    }
+}
+
+impl From<PipeError> for io::Error {
+    /// Builds the raw OS error for variants which stand for one, such as
+    /// `NoReader`, and an error carrying `err` as its payload otherwise.
+    fn from(err: PipeError) -> io::Error {
+        match err {
+            PipeError::NoReader => io::Error::from_raw_os_error(libc::ENXIO),
+            PipeError::ReaderGone => io::Error::from_raw_os_error(libc::EPIPE),
+            err => io::Error::new(err.kind(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_path_and_kind() {
+        let err = io::Error::from(PipeError::Replaced {
+            path: PathBuf::from("/tmp/pipe-error"),
+        });
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+
+        let pipe_err = PipeError::of(&err).expect("payload should be a PipeError");
+        assert!(err
+            .to_string()
+            .starts_with("could not open /tmp/pipe-error: "));
+        assert_eq!(pipe_err.path(), Some(Path::new("/tmp/pipe-error")));
+
+        assert!(PipeError::of(&io::Error::from_raw_os_error(libc::ENOSPC)).is_none());
+    }
+
+    #[test]
+    fn keeps_raw_os_errors() {
+        for (pipe_err, errno) in [
+            (PipeError::NoReader, libc::ENXIO),
+            (PipeError::ReaderGone, libc::EPIPE),
+        ] {
+            let err = io::Error::from(pipe_err.clone());
+            assert_eq!(err.raw_os_error(), Some(errno));
+            assert_eq!(err.kind(), pipe_err.kind());
+            assert_eq!(
+                PipeError::of(&err).and_then(|err| err.raw_os_error()),
+                Some(errno)
+            );
+        }
+
+        let invalid = io::Error::from(PipeError::InvalidPath {
+            path: PathBuf::from("/tmp/pipe-error"),
+        });
+        assert_eq!(invalid.raw_os_error(), None);
+    }
+}
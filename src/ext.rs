@@ -1,43 +1,1396 @@
-//! Provides an extension to `std::fs::File` which implements useful
+//! Provides an extension to file descriptors which implements useful
 //! utilities for working with FIFOs.
 
+use super::audit::{self, AuditAction};
+use super::error::not_a_fifo;
+use super::lock::{self, flock, LockScope, RecordLock};
+use super::poll::poll_fd;
+use super::sigpipe::{write_blocking_sigpipe, writev_blocking_sigpipe};
+use super::{
+    Backlog, Clock, FifoStat, Mode, PipeError, ReadStatus, Readiness, SystemClock, WriteStatus,
+};
+use libc::{c_int, fcntl, EPIPE, F_GETFL, F_SETFL, O_NONBLOCK, POLLERR, POLLHUP, POLLIN, POLLOUT};
 use std::fs;
-use std::io;
-use std::os::unix::fs::FileTypeExt;
+use std::io::{self, IoSlice, Read};
+use std::mem::ManuallyDrop;
+use std::os::unix::fs::{fchown, FileTypeExt};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::time::{Duration, Instant};
 
-/// Definitions for `std::fs::File` extensions for FIFOs
+/// The largest write to a pipe which POSIX guarantees to be atomic, so that
+/// it is never interleaved with the writes of other processes. This is the
+/// compile-time `PIPE_BUF` of the platform: 4096 on Linux, 512 on most BSDs
+/// and macOS. `FileFIFOExt::max_atomic_write` asks the system instead.
+pub const PIPE_BUF: usize = libc::PIPE_BUF;
+
+/// How many bytes `drain` discards per read.
+const DRAIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Definitions for FIFO extensions of file descriptors.
+///
+/// The trait is implemented for everything which is `AsFd`, so it works the
+/// same on a `std::fs::File`, an `OwnedFd` or `BorrowedFd`, or an async
+/// wrapper around one of them.
 pub trait FileFIFOExt {
     fn is_fifo(&self) -> io::Result<bool>;
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+    fn is_nonblocking(&self) -> io::Result<bool>;
+    fn set_mode(&self, mode: Mode) -> io::Result<()>;
+    fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> io::Result<()>;
+    fn duplicate(&self) -> io::Result<fs::File>;
+    fn read_status(&self, buf: &mut [u8]) -> io::Result<ReadStatus>;
+    fn try_write(&self, buf: &[u8]) -> io::Result<WriteStatus>;
+    fn buffer_size(&self) -> io::Result<usize>;
+    fn set_buffer_size(&self, bytes: usize) -> io::Result<usize>;
+    fn bytes_available(&self) -> io::Result<usize>;
+    fn backlog(&self) -> io::Result<Backlog>;
+    fn max_atomic_write(&self) -> io::Result<usize>;
+    fn write_atomic(&self, buf: &[u8]) -> io::Result<()>;
+    fn write_vectored_atomic(&self, bufs: &[IoSlice<'_>]) -> io::Result<()>;
+    fn drain(&self) -> io::Result<usize>;
+    fn read_available(&self, buf: &mut Vec<u8>) -> io::Result<usize>;
+    fn poll_readable(&self, timeout: Option<Duration>) -> io::Result<Readiness>;
+    fn poll_writable(&self, timeout: Option<Duration>) -> io::Result<Readiness>;
+    fn read_timeout(&self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+    fn write_timeout(&self, buf: &[u8], timeout: Duration) -> io::Result<usize>;
+    fn write_all_timeout(&self, buf: &[u8], timeout: Duration) -> io::Result<()>;
+    fn read_timeout_with_clock(
+        &self,
+        buf: &mut [u8],
+        timeout: Duration,
+        clock: &dyn Clock,
+    ) -> io::Result<usize>;
+    fn write_timeout_with_clock(
+        &self,
+        buf: &[u8],
+        timeout: Duration,
+        clock: &dyn Clock,
+    ) -> io::Result<usize>;
+    fn write_all_timeout_with_clock(
+        &self,
+        buf: &[u8],
+        timeout: Duration,
+        clock: &dyn Clock,
+    ) -> io::Result<()>;
+    fn flock_exclusive(&self) -> io::Result<()>;
+    fn flock_shared(&self) -> io::Result<()>;
+    fn try_flock_exclusive(&self) -> io::Result<bool>;
+    fn try_flock_shared(&self) -> io::Result<bool>;
+    fn flock_unlock(&self) -> io::Result<()>;
+    fn try_lock_record(&self, lock: RecordLock, scope: LockScope) -> io::Result<bool>;
+    fn lock_record(
+        &self,
+        lock: RecordLock,
+        scope: LockScope,
+        timeout: Option<Duration>,
+    ) -> io::Result<()>;
+    fn lock_record_with_clock(
+        &self,
+        lock: RecordLock,
+        scope: LockScope,
+        timeout: Option<Duration>,
+        clock: &dyn Clock,
+    ) -> io::Result<()>;
+    fn unlock_record(&self, scope: LockScope) -> io::Result<()>;
+    fn stat(&self) -> io::Result<FifoStat>;
+    #[cfg(all(feature = "splice", target_os = "linux"))]
+    fn splice_to(&self, dst: BorrowedFd<'_>, len: usize) -> io::Result<usize>;
+    #[cfg(all(feature = "splice", target_os = "linux"))]
+    fn splice_from(&self, src: BorrowedFd<'_>, len: usize) -> io::Result<usize>;
+    #[cfg(all(feature = "splice", target_os = "linux"))]
+    fn tee_to(&self, other: BorrowedFd<'_>, len: usize) -> io::Result<usize>;
+}
+
+impl<T: AsFd> FileFIFOExt for T {
+    /// Returns a wrapped boolean to designate if the underlying
+    /// file is a FIFO device.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// assert_eq!(file.is_fifo().unwrap(), true);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn is_fifo(&self) -> io::Result<bool> {
+        let metadata = with_file(self.as_fd(), |file| file.metadata())?;
+        Ok(metadata.file_type().is_fifo())
+    }
+
+    /// Moves the underlying file descriptor into or out of non-blocking
+    /// mode by toggling `O_NONBLOCK` with `fcntl(F_SETFL)`.
+    /// Data already buffered in the pipe is kept, as the file is not reopened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// file.set_nonblocking(false).expect("could not make fifo blocking");
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let fd = self.as_fd().as_raw_fd();
+        let flags = status_flags(fd)?;
+        let flags = if nonblocking {
+            flags | O_NONBLOCK
+        } else {
+            flags & !O_NONBLOCK
+        };
+        if unsafe { fcntl(fd, F_SETFL, flags) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Returns a wrapped boolean to designate if `O_NONBLOCK` is currently
+    /// set on the underlying file descriptor, as reported by `fcntl(F_GETFL)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// assert_eq!(file.is_nonblocking().unwrap(), true);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn is_nonblocking(&self) -> io::Result<bool> {
+        let flags = status_flags(self.as_fd().as_raw_fd())?;
+        Ok(flags & O_NONBLOCK != 0)
+    }
+
+    /// Changes the permissions of the underlying FIFO to `mode` with
+    /// `fchmod`, for relaxing or tightening access after creation.
+    /// Unlike `create`, the mode is not narrowed by the process umask.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// file.set_mode(Mode::owner_rw().group_write())
+    ///     .expect("could not change fifo mode");
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn set_mode(&self, mode: Mode) -> io::Result<()> {
+        let result = with_file(self.as_fd(), |file| file.set_permissions(mode.into()));
+        audit::record_fd(
+            AuditAction::SetMode { mode: mode.bits() },
+            self.as_fd(),
+            &result,
+        );
+
+        result
+    }
+
+    /// Changes the owner and group of the underlying FIFO with `fchown`.
+    /// Either can be `None` to leave it unchanged. Use `lookup_user` and
+    /// `lookup_group` to resolve names, or the path-based `set_owner`.
+    fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        let result = fchown(self, uid, gid);
+        audit::record_fd(AuditAction::SetOwner { uid, gid }, self.as_fd(), &result);
+
+        result
+    }
+
+    /// Duplicates the underlying descriptor, after checking that it refers
+    /// to a FIFO. Both descriptors share one open file, so `O_NONBLOCK` is
+    /// shared between them too; the duplicate is closed on `exec`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let shared = file.duplicate().expect("could not duplicate fifo");
+    /// assert!(shared.is_nonblocking().unwrap());
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the file is not a FIFO, `Err(io::ErrorKind::InvalidInput)` will
+    ///   be returned.
+    fn duplicate(&self) -> io::Result<fs::File> {
+        let fd = self.as_fd();
+        let file_type = with_file(fd, |file| file.metadata())?.file_type();
+        if !file_type.is_fifo() {
+            return Err(not_a_fifo("duplicate", None, file_type));
+        }
+
+        Ok(fs::File::from(fd.try_clone_to_owned()?))
+    }
+
+    /// Reads into `buf` from a non-blocking pipe, telling apart the two
+    /// cases in which `read` has nothing to return: a writer which has not
+    /// written yet, and no writer at all. Interrupted reads are retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut buf = [0; 16];
+    /// assert_eq!(file.read_status(&mut buf).unwrap(), ReadStatus::WriterClosed);
+    ///
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// assert_eq!(file.read_status(&mut buf).unwrap(), ReadStatus::Empty);
+    /// writer.write_all(b"ping").unwrap();
+    /// assert_eq!(file.read_status(&mut buf).unwrap(), ReadStatus::Data(4));
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// An empty `buf` always gives `ReadStatus::Data(0)`, without reading.
+    /// On a blocking descriptor this waits for data like `read` does, and
+    /// `ReadStatus::Empty` is never returned.
+    fn read_status(&self, buf: &mut [u8]) -> io::Result<ReadStatus> {
+        if buf.is_empty() {
+            return Ok(ReadStatus::Data(0));
+        }
+
+        loop {
+            match with_file(self.as_fd(), |mut file| file.read(buf)) {
+                Ok(0) => return Ok(ReadStatus::WriterClosed),
+                Ok(read) => return Ok(ReadStatus::Data(read)),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(ReadStatus::Empty)
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Writes `buf` to a non-blocking pipe, telling apart a full pipe buffer
+    /// from a reader which has gone away. `SIGPIPE` is blocked during the
+    /// write, as with `write_without_sigpipe`, and interrupted writes are
+    /// retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let reader = open_read(file_name).expect("could not open fifo for reading");
+    /// let file = open_write(file_name).expect("could not open fifo for writing");
+    /// assert_eq!(file.try_write(b"ping").unwrap(), WriteStatus::Written(4));
+    ///
+    /// drop(reader);
+    /// assert_eq!(file.try_write(b"ping").unwrap(), WriteStatus::NoReader);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// On a blocking descriptor this waits for room like `write` does, and
+    /// `WriteStatus::Full` is never returned.
+    fn try_write(&self, buf: &[u8]) -> io::Result<WriteStatus> {
+        loop {
+            match write_blocking_sigpipe(self.as_fd(), buf) {
+                Ok(written) => return Ok(WriteStatus::Written(written)),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(WriteStatus::Full)
+                }
+                Err(ref err) if err.raw_os_error() == Some(EPIPE) => {
+                    return Ok(WriteStatus::NoReader)
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns how many bytes the pipe can hold before writes block or fail
+    /// with `WouldBlock`.
+    ///
+    /// On Linux this is the kernel capacity of the pipe, as reported by
+    /// `fcntl(F_GETPIPE_SZ)`, which is 64 KiB unless it has been changed.
+    /// Other systems have no way to query the capacity, so the `PIPE_BUF`
+    /// limit reported by `fpathconf` is returned instead. Every pipe holds at
+    /// least that much, and writes of up to that size are atomic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// assert!(file.buffer_size().unwrap() >= 512);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn buffer_size(&self) -> io::Result<usize> {
+        pipe_capacity(self.as_fd().as_raw_fd())
+    }
+
+    /// Resizes the pipe buffer to hold at least `bytes` bytes with
+    /// `fcntl(F_SETPIPE_SZ)`, returning the capacity the kernel settled on.
+    /// The kernel rounds the size up to a power of two pages. The buffer is
+    /// shared by both ends of the pipe, so either one can resize it.
+    ///
+    /// This is only supported on Linux.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let size = file.set_buffer_size(128 * 1024).expect("could not resize fifo");
+    /// assert_eq!(file.buffer_size().unwrap(), size);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If `bytes` is above `/proc/sys/fs/pipe-max-size` and the process
+    ///   lacks `CAP_SYS_RESOURCE`, `Err(io::ErrorKind::PermissionDenied)`
+    ///   will be returned, carrying `PipeError::BufferTooLarge`, whose
+    ///   source is the raw `EPERM`.
+    /// - If the pipe buffers of the user would exceed
+    ///   `/proc/sys/fs/pipe-user-pages-soft` and the process lacks
+    ///   `CAP_SYS_RESOURCE`, the raw `EPERM` error will be returned.
+    /// - If `bytes` is too large for the kernel to consider at all,
+    ///   `Err(io::ErrorKind::InvalidInput)` will be returned.
+    /// - If the pipe holds more data than fits into `bytes`, the `EBUSY`
+    ///   error will be returned.
+    /// - On systems other than Linux, `Err(io::ErrorKind::Unsupported)` will
+    ///   be returned.
+    fn set_buffer_size(&self, bytes: usize) -> io::Result<usize> {
+        set_pipe_capacity(self.as_fd().as_raw_fd(), bytes)
+    }
+
+    /// Returns how many bytes are waiting in the pipe to be read, as
+    /// reported by `ioctl(FIONREAD)`. Nothing is read, so this can size a
+    /// buffer for the next read or report the backlog of a consumer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// writer.write_all(b"ping").unwrap();
+    /// assert_eq!(file.bytes_available().unwrap(), 4);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn bytes_available(&self) -> io::Result<usize> {
+        let mut available: c_int = 0;
+        if unsafe { libc::ioctl(self.as_fd().as_raw_fd(), libc::FIONREAD, &mut available) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(available as usize)
+    }
+
+    /// Returns how much of the pipe buffer is in use, combining
+    /// `bytes_available` and `buffer_size`, so monitoring can notice a
+    /// consumer falling behind before writes start failing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// writer.write_all(&[0; 256]).unwrap();
+    ///
+    /// let backlog = writer.backlog().unwrap();
+    /// assert_eq!(backlog.used, 256);
+    /// if backlog.fill_level() > 0.9 {
+    ///     eprintln!("consumer is falling behind");
+    /// }
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn backlog(&self) -> io::Result<Backlog> {
+        Ok(Backlog {
+            used: self.bytes_available()?,
+            capacity: self.buffer_size()?,
+        })
+    }
+
+    /// Returns the largest write to this pipe which is atomic, as reported
+    /// by `fpathconf(_PC_PIPE_BUF)`. Writes of up to this many bytes are
+    /// never split or interleaved with those of other writers, so framing
+    /// layers can keep every message within it. Where the system sets no
+    /// limit of its own, `PIPE_BUF` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// assert!(file.max_atomic_write().unwrap() >= 512);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn max_atomic_write(&self) -> io::Result<usize> {
+        pipe_buf(self.as_fd().as_raw_fd())
+    }
+
+    /// Writes all of `buf` with a single `write`, which the kernel performs
+    /// atomically as long as `buf` is no larger than `PIPE_BUF`. Messages of
+    /// several producers sharing one pipe are then never interleaved.
+    /// `SIGPIPE` is blocked during the write, as with `write_without_sigpipe`,
+    /// and interrupted writes are retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let reader = open_read(file_name).expect("could not open fifo for reading");
+    /// let file = open_write(file_name).expect("could not open fifo for writing");
+    /// file.write_atomic(b"event=1\n").expect("could not write message");
+    ///
+    /// let err = file.write_atomic(&[0; PIPE_BUF + 1]).unwrap_err();
+    /// assert!(matches!(PipeError::of(&err), Some(PipeError::NotAtomic { .. })));
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If `buf` is larger than `PIPE_BUF`, `Err(io::ErrorKind::InvalidInput)`
+    ///   will be returned, carrying `PipeError::NotAtomic`, and nothing is
+    ///   written.
+    /// - If the pipe is non-blocking and has no room for all of `buf`,
+    ///   `Err(io::ErrorKind::WouldBlock)` will be returned, and nothing is
+    ///   written.
+    /// - If the reader has gone away, `Err(io::ErrorKind::BrokenPipe)` will
+    ///   be returned, with the raw `EPIPE` code.
+    /// - Any other error returned by `write`.
+    fn write_atomic(&self, buf: &[u8]) -> io::Result<()> {
+        check_atomic(buf.len())?;
+        loop {
+            match write_blocking_sigpipe(self.as_fd(), buf) {
+                Ok(written) => return check_written(written, buf.len()),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Writes all of `bufs` with a single `writev`, like `write_atomic` does
+    /// with one buffer. A header and payload can be sent as one atomic
+    /// message this way without copying them together first. The limit of
+    /// `PIPE_BUF` applies to the total length.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::IoSlice;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let reader = open_read(file_name).expect("could not open fifo for reading");
+    /// let file = open_write(file_name).expect("could not open fifo for writing");
+    /// let payload = b"hello";
+    /// let header = (payload.len() as u32).to_be_bytes();
+    /// file.write_vectored_atomic(&[IoSlice::new(&header), IoSlice::new(payload)])
+    ///     .expect("could not write message");
+    /// assert_eq!(reader.bytes_available().unwrap(), 9);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// The same as for `write_atomic`, where `buf` is all of `bufs`.
+    fn write_vectored_atomic(&self, bufs: &[IoSlice<'_>]) -> io::Result<()> {
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        check_atomic(len)?;
+        loop {
+            match writev_blocking_sigpipe(self.as_fd(), bufs) {
+                Ok(written) => return check_written(written, len),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Reads and discards everything which is buffered in the pipe right
+    /// now, returning how many bytes were thrown away. A server restarting
+    /// against a pipe which outlived it can drop stale requests this way.
+    ///
+    /// Only as much as `bytes_available` reports at the start is read, so
+    /// this never blocks, not even on a blocking descriptor, and returns
+    /// even while a writer keeps sending.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// writer.write_all(b"stale request").unwrap();
+    ///
+    /// assert_eq!(file.drain().unwrap(), 13);
+    /// assert_eq!(file.bytes_available().unwrap(), 0);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn drain(&self) -> io::Result<usize> {
+        let mut remaining = self.bytes_available()?;
+        let mut buf = vec![0; remaining.min(DRAIN_CHUNK_SIZE)];
+        let mut drained = 0;
+        while remaining > 0 {
+            let len = remaining.min(buf.len());
+            match self.read_status(&mut buf[..len])? {
+                ReadStatus::Data(read) => {
+                    drained += read;
+                    remaining -= read.min(remaining);
+                }
+                ReadStatus::Empty | ReadStatus::WriterClosed => break,
+            }
+        }
+
+        Ok(drained)
+    }
+
+    /// Reads everything which is buffered in the pipe right now, appending
+    /// it to `buf`, and returns how many bytes were read. An empty pipe is
+    /// not an error: `Ok(0)` is returned, whether or not a writer is left.
+    ///
+    /// Like `drain`, only as much as `bytes_available` reports at the start
+    /// is read, so this never blocks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// writer.write_all(b"one ").unwrap();
+    /// writer.write_all(b"two").unwrap();
+    ///
+    /// let mut received = Vec::new();
+    /// assert_eq!(file.read_available(&mut received).unwrap(), 7);
+    /// assert_eq!(received, b"one two");
+    /// assert_eq!(file.read_available(&mut received).unwrap(), 0);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn read_available(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let available = self.bytes_available()?;
+        let start = buf.len();
+        buf.resize(start + available, 0);
+
+        let mut filled = start;
+        while filled < buf.len() {
+            match self.read_status(&mut buf[filled..]) {
+                Ok(ReadStatus::Data(read)) => filled += read,
+                Ok(ReadStatus::Empty) | Ok(ReadStatus::WriterClosed) => break,
+                Err(err) => {
+                    buf.truncate(filled);
+                    return Err(err);
+                }
+            }
+        }
+        buf.truncate(filled);
+
+        Ok(filled - start)
+    }
+
+    /// Waits with `poll` until the pipe has data to read, or every writer
+    /// has hung up, for at most `timeout`, or forever if it is `None`. A
+    /// non-blocking reader can sleep on this instead of retrying reads
+    /// which fail with `WouldBlock`.
+    ///
+    /// A reader which has not seen a writer yet does not get
+    /// `Readiness::HangUp` on Linux, but waits for the first writer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use std::time::Duration;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// let timeout = Some(Duration::from_millis(10));
+    /// assert_eq!(file.poll_readable(timeout).unwrap(), Readiness::TimedOut);
+    ///
+    /// writer.write_all(b"ping").unwrap();
+    /// assert_eq!(file.poll_readable(timeout).unwrap(), Readiness::Ready);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn poll_readable(&self, timeout: Option<Duration>) -> io::Result<Readiness> {
+        Ok(match poll_fd(self.as_fd(), POLLIN, timeout)? {
+            None => Readiness::TimedOut,
+            Some(events) if events & POLLIN != 0 => Readiness::Ready,
+            Some(events) if events & POLLHUP != 0 => Readiness::HangUp,
+            Some(_) => Readiness::Ready,
+        })
+    }
+
+    /// Waits with `poll` until the pipe has room to write, or the reader
+    /// has hung up, for at most `timeout`, or forever if it is `None`. A
+    /// producer which finds the pipe full can sleep on this until the
+    /// reader catches up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::time::Duration;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let reader = open_read(file_name).expect("could not open fifo for reading");
+    /// let file = open_write(file_name).expect("could not open fifo for writing");
+    /// let timeout = Some(Duration::from_millis(10));
+    /// assert_eq!(file.poll_writable(timeout).unwrap(), Readiness::Ready);
+    ///
+    /// drop(reader);
+    /// assert_eq!(file.poll_writable(timeout).unwrap(), Readiness::HangUp);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn poll_writable(&self, timeout: Option<Duration>) -> io::Result<Readiness> {
+        // Linux reports a vanished reader as POLLERR, other systems as
+        // POLLHUP, and either may come together with POLLOUT.
+        Ok(match poll_fd(self.as_fd(), POLLOUT, timeout)? {
+            None => Readiness::TimedOut,
+            Some(events) if events & (POLLERR | POLLHUP) != 0 => Readiness::HangUp,
+            Some(_) => Readiness::Ready,
+        })
+    }
+
+    /// Reads into `buf` like `read`, but waits with `poll_readable` for at
+    /// most `timeout` for data to arrive. Returns how many bytes were read,
+    /// which is 0 once every writer has hung up.
+    ///
+    /// This works on blocking and non-blocking descriptors alike. On a
+    /// blocking descriptor `O_NONBLOCK` is set for the duration of the read,
+    /// so that a read racing another reader cannot block past the timeout.
+    /// The flag belongs to the open file description, so duplicates of the
+    /// descriptor see it too until this call returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use std::time::Duration;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// let mut response = [0; 16];
+    /// let err = file.read_timeout(&mut response, Duration::from_millis(10)).unwrap_err();
+    /// assert!(matches!(PipeError::of(&err), Some(PipeError::TimedOut { .. })));
+    ///
+    /// writer.write_all(b"pong").unwrap();
+    /// assert_eq!(file.read_timeout(&mut response, Duration::from_secs(1)).unwrap(), 4);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If nothing arrives within `timeout`, `Err(io::ErrorKind::TimedOut)`
+    ///   will be returned, carrying `PipeError::TimedOut`.
+    /// - Any error returned by `poll` or `read`.
+    fn read_timeout(&self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        self.read_timeout_with_clock(buf, timeout, &SystemClock)
+    }
+
+    /// Reads like `read_timeout`, measuring the time which is left with
+    /// `clock`. Each `poll` still waits in real time, for at most what
+    /// `clock` says is left, so a `MockClock` which has passed the deadline
+    /// times out without blocking.
+    ///
+    /// # Errors
+    ///
+    /// The same as for `read_timeout`.
+    fn read_timeout_with_clock(
+        &self,
+        buf: &mut [u8],
+        timeout: Duration,
+        clock: &dyn Clock,
+    ) -> io::Result<usize> {
+        let deadline = clock.now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(clock.now());
+            match self.poll_readable(Some(left))? {
+                Readiness::TimedOut => {
+                    return Err(PipeError::TimedOut {
+                        path: None,
+                        timeout,
+                    }
+                    .into())
+                }
+                Readiness::HangUp => return Ok(0),
+                Readiness::Ready => {}
+            }
+
+            // Another reader may have taken the data since the poll.
+            match with_nonblocking(self.as_fd(), || self.read_status(buf))? {
+                ReadStatus::Data(read) => return Ok(read),
+                ReadStatus::WriterClosed => return Ok(0),
+                ReadStatus::Empty => {}
+            }
+        }
+    }
+
+    /// Writes `buf` like `write`, but waits with `poll_writable` for at most
+    /// `timeout` for room in the pipe. Returns how many bytes were written,
+    /// which may be fewer than were given. `SIGPIPE` is blocked during the
+    /// write, as with `write_without_sigpipe`.
+    ///
+    /// On a blocking descriptor `O_NONBLOCK` is set for the duration of the
+    /// write, so that a write racing another writer cannot block past the
+    /// timeout. The flag belongs to the open file description, so
+    /// duplicates of the descriptor see it too until this call returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::time::Duration;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let reader = open_read(file_name).expect("could not open fifo for reading");
+    /// let file = open_write(file_name).expect("could not open fifo for writing");
+    /// let written = file.write_timeout(b"request", Duration::from_secs(1)).unwrap();
+    /// assert_eq!(written, 7);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If there is no room within `timeout`, `Err(io::ErrorKind::TimedOut)`
+    ///   will be returned, carrying `PipeError::TimedOut`.
+    /// - If the reader has gone away, `Err(io::ErrorKind::BrokenPipe)` will
+    ///   be returned, with the raw `EPIPE` code.
+    /// - Any other error returned by `poll` or `write`.
+    fn write_timeout(&self, buf: &[u8], timeout: Duration) -> io::Result<usize> {
+        self.write_timeout_with_clock(buf, timeout, &SystemClock)
+    }
+
+    /// Writes all of `buf` within `timeout`, waiting with `poll_writable`
+    /// between partial writes, so that a consumer which stopped reading
+    /// cannot hang the producer forever. The timeout covers the whole
+    /// buffer, not each write.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::time::Duration;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let reader = open_read(file_name).expect("could not open fifo for reading");
+    /// let file = open_write(file_name).expect("could not open fifo for writing");
+    ///
+    /// // Nobody reads, so 1 MiB never fits into the pipe
+    /// let err = file
+    ///     .write_all_timeout(&vec![0; 1 << 20], Duration::from_millis(20))
+    ///     .unwrap_err();
+    /// assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// The same as for `write_timeout`. As with `Write::write_all`, part of
+    /// `buf` may have been written when an error is returned.
+    fn write_all_timeout(&self, buf: &[u8], timeout: Duration) -> io::Result<()> {
+        self.write_all_timeout_with_clock(buf, timeout, &SystemClock)
+    }
+
+    /// Writes like `write_timeout`, measuring the time which is left with
+    /// `clock` as `read_timeout_with_clock` does.
+    ///
+    /// # Errors
+    ///
+    /// The same as for `write_timeout`.
+    fn write_timeout_with_clock(
+        &self,
+        buf: &[u8],
+        timeout: Duration,
+        clock: &dyn Clock,
+    ) -> io::Result<usize> {
+        write_before(self.as_fd(), buf, clock, clock.now() + timeout, timeout)
+    }
+
+    /// Writes like `write_all_timeout`, measuring the time which is left
+    /// with `clock` as `read_timeout_with_clock` does.
+    ///
+    /// # Errors
+    ///
+    /// The same as for `write_all_timeout`.
+    fn write_all_timeout_with_clock(
+        &self,
+        mut buf: &[u8],
+        timeout: Duration,
+        clock: &dyn Clock,
+    ) -> io::Result<()> {
+        let deadline = clock.now() + timeout;
+        while !buf.is_empty() {
+            let written = write_before(self.as_fd(), buf, clock, deadline, timeout)?;
+            buf = &buf[written..];
+        }
+
+        Ok(())
+    }
+
+    /// Takes an exclusive advisory lock on the FIFO with `flock`, waiting
+    /// until no other process holds a lock on it. Cooperating processes can
+    /// elect a single active reader or writer this way.
+    ///
+    /// The lock belongs to the open file, so it is shared with descriptors
+    /// made by `duplicate` and released when the last of them is closed.
+    /// It only keeps out processes which take a lock too.
+    fn flock_exclusive(&self) -> io::Result<()> {
+        flock(self.as_fd(), libc::LOCK_EX).map(drop)
+    }
+
+    /// Takes a shared advisory lock on the FIFO with `flock`, waiting until
+    /// no other process holds an exclusive lock on it.
+    fn flock_shared(&self) -> io::Result<()> {
+        flock(self.as_fd(), libc::LOCK_SH).map(drop)
+    }
+
+    /// Takes an exclusive advisory lock like `flock_exclusive`, but returns
+    /// `false` at once instead of waiting if another process holds a lock.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// if !file.try_flock_exclusive().unwrap() {
+    ///     panic!("another reader is already active");
+    /// }
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn try_flock_exclusive(&self) -> io::Result<bool> {
+        flock(self.as_fd(), libc::LOCK_EX | libc::LOCK_NB)
+    }
+
+    /// Takes a shared advisory lock like `flock_shared`, but returns `false`
+    /// at once instead of waiting if another process holds an exclusive
+    /// lock.
+    fn try_flock_shared(&self) -> io::Result<bool> {
+        flock(self.as_fd(), libc::LOCK_SH | libc::LOCK_NB)
+    }
+
+    /// Releases the advisory lock taken on the FIFO, if there is one.
+    fn flock_unlock(&self) -> io::Result<()> {
+        flock(self.as_fd(), libc::LOCK_UN).map(drop)
+    }
+
+    /// Takes an `fcntl` record lock of kind `lock` on the whole FIFO, owned
+    /// as described by `scope`, returning `false` at once if another owner
+    /// holds a conflicting lock. Where `flock` is not honoured, such as on
+    /// some NFS mounts, this is the lock to use instead of `flock_exclusive`.
+    ///
+    /// `RecordLock::Exclusive` needs a write end, and `RecordLock::Shared` a
+    /// read end, or the `EBADF` error will be returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let reader = open_read(file_name).expect("could not open fifo for reading");
+    /// let file = open_write(file_name).expect("could not open fifo for writing");
+    /// let elected = file
+    ///     .try_lock_record(RecordLock::Exclusive, LockScope::Process)
+    ///     .unwrap();
+    /// assert!(elected);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn try_lock_record(&self, lock: RecordLock, scope: LockScope) -> io::Result<bool> {
+        lock::try_lock_record(self.as_fd(), Some(lock), scope)
+    }
+
+    /// Takes an `fcntl` record lock like `try_lock_record`, waiting for at
+    /// most `timeout` for a conflicting lock to be released, or forever if
+    /// it is `None`. With a timeout, the lock is retried every few
+    /// milliseconds, since `fcntl` cannot wait with a deadline itself.
+    /// Without one, the kernel may fail the wait with `EDEADLK` if it would
+    /// never end.
+    ///
+    /// # Errors
+    ///
+    /// - If the lock is still held elsewhere after `timeout`,
+    ///   `Err(io::ErrorKind::TimedOut)` will be returned, carrying
+    ///   `PipeError::TimedOut`.
+    /// - Any error returned by `try_lock_record`.
+    fn lock_record(
+        &self,
+        lock: RecordLock,
+        scope: LockScope,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        self.lock_record_with_clock(lock, scope, timeout, &SystemClock)
+    }
+
+    /// Takes an `fcntl` record lock like `lock_record`, measuring the
+    /// timeout and sleeping between retries with `clock`.
+    ///
+    /// # Errors
+    ///
+    /// The same as for `lock_record`.
+    fn lock_record_with_clock(
+        &self,
+        lock: RecordLock,
+        scope: LockScope,
+        timeout: Option<Duration>,
+        clock: &dyn Clock,
+    ) -> io::Result<()> {
+        lock::lock_record(self.as_fd(), lock, scope, timeout, clock)
+    }
+
+    /// Releases the `fcntl` record lock of `scope` held on the FIFO, if
+    /// there is one.
+    fn unlock_record(&self, scope: LockScope) -> io::Result<()> {
+        lock::try_lock_record(self.as_fd(), None, scope).map(drop)
+    }
+
+    /// Returns a snapshot of the FIFO's metadata together with its buffer
+    /// state, for dumping in diagnostics. The metadata comes from `fstat` on
+    /// the descriptor, so it describes the pipe actually open.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let stat = file.stat().expect("could not stat fifo");
+    /// assert!(stat.is_fifo);
+    /// eprintln!("{}: {}", file_name.display(), stat);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// ```
+    fn stat(&self) -> io::Result<FifoStat> {
+        let metadata = with_file(self.as_fd(), |file| file.metadata())?;
+        let capacity = if cfg!(target_os = "linux") && metadata.file_type().is_fifo() {
+            self.buffer_size().ok()
+        } else {
+            None
+        };
+
+        Ok(FifoStat::new(
+            &metadata,
+            capacity,
+            self.bytes_available().ok(),
+        ))
+    }
+
+    /// Moves up to `len` bytes out of this pipe into `dst`, which may be a
+    /// file, a socket or another pipe, with `splice`. The data is consumed
+    /// from the pipe without being copied through userspace. Returns how
+    /// many bytes were moved, which is 0 once no writer is left.
+    ///
+    /// Only available on Linux with the `splice` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use std::os::unix::io::AsFd;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # let out_name = &unix_named_pipe::unique_path("out");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// writer.write_all(b"relayed").unwrap();
+    ///
+    /// let out = fs::File::create(out_name).unwrap();
+    /// assert_eq!(file.splice_to(out.as_fd(), 1 << 20).unwrap(), 7);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// # fs::remove_file(out_name).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the pipe is non-blocking and empty, or `dst` is non-blocking and
+    ///   full, `Err(io::ErrorKind::WouldBlock)` will be returned.
+    /// - If `dst` cannot be spliced into, such as a file opened with
+    ///   `O_APPEND`, the `EINVAL` error will be returned.
+    /// - Any other error returned by `splice`.
+    #[cfg(all(feature = "splice", target_os = "linux"))]
+    fn splice_to(&self, dst: BorrowedFd<'_>, len: usize) -> io::Result<usize> {
+        super::splice::splice(self.as_fd(), dst, len)
+    }
+
+    /// Moves up to `len` bytes from `src`, which may be a file, a socket or
+    /// another pipe, into this pipe with `splice`. Returns how many bytes
+    /// were moved, which is 0 at the end of `src`.
+    ///
+    /// Only available on Linux with the `splice` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::os::unix::io::AsFd;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # let data_name = &unix_named_pipe::unique_path("data");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// # fs::write(data_name, b"artifact").unwrap();
+    /// let reader = open_read(file_name).expect("could not open fifo for reading");
+    /// let file = open_write(file_name).expect("could not open fifo for writing");
+    ///
+    /// let data = fs::File::open(data_name).unwrap();
+    /// assert_eq!(file.splice_from(data.as_fd(), 1 << 20).unwrap(), 8);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// # fs::remove_file(data_name).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the pipe is non-blocking and full, or `src` is non-blocking and
+    ///   empty, `Err(io::ErrorKind::WouldBlock)` will be returned.
+    /// - If the reader has gone away, the `EPIPE` error will be returned.
+    ///   `SIGPIPE` is raised as for `write`.
+    /// - Any other error returned by `splice`.
+    #[cfg(all(feature = "splice", target_os = "linux"))]
+    fn splice_from(&self, src: BorrowedFd<'_>, len: usize) -> io::Result<usize> {
+        super::splice::splice(src, self.as_fd(), len)
+    }
+
+    /// Copies up to `len` bytes from this pipe into the pipe `other` with
+    /// `tee`, without consuming them, so a stream can be mirrored into a
+    /// second FIFO while its reader still gets everything. Both descriptors
+    /// must be pipes: this one open for reading and `other` for writing.
+    /// Returns how many bytes were copied.
+    ///
+    /// Only available on Linux with the `splice` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use std::os::unix::io::AsFd;
+    /// use unix_named_pipe::*;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # let tap_name = &unix_named_pipe::unique_path("tap");
+    /// # create(file_name, None).expect("could not create fifo");
+    /// # create(tap_name, None).expect("could not create fifo");
+    /// let file = open_read(file_name).expect("could not open fifo for reading");
+    /// let mut writer = open_write(file_name).expect("could not open fifo for writing");
+    /// let _debug = open_read(tap_name).expect("could not open tap for reading");
+    /// let tap = open_write(tap_name).expect("could not open tap for writing");
+    ///
+    /// writer.write_all(b"event").unwrap();
+    /// assert_eq!(file.tee_to(tap.as_fd(), 1 << 16).unwrap(), 5);
+    /// assert_eq!(file.bytes_available().unwrap(), 5);
+    /// # fs::remove_file(file_name).expect("could not remove fifo");
+    /// # fs::remove_file(tap_name).expect("could not remove fifo");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If either descriptor is not a pipe, the `EINVAL` error will be
+    ///   returned.
+    /// - If this pipe is non-blocking and empty, or `other` is non-blocking
+    ///   and full, `Err(io::ErrorKind::WouldBlock)` will be returned.
+    /// - Any other error returned by `tee`.
+    #[cfg(all(feature = "splice", target_os = "linux"))]
+    fn tee_to(&self, other: BorrowedFd<'_>, len: usize) -> io::Result<usize> {
+        super::splice::tee(self.as_fd(), other, len)
+    }
+}
+
+/// Calls `f` with a `File` which borrows `fd`, so the `File` methods can be
+/// used with any descriptor. The descriptor is not closed afterwards.
+fn with_file<R, F: FnOnce(&fs::File) -> R>(fd: BorrowedFd, f: F) -> R {
+    let file = ManuallyDrop::new(unsafe { fs::File::from_raw_fd(fd.as_raw_fd()) });
+    f(&file)
+}
+
+#[cfg(target_os = "linux")]
+fn pipe_capacity(fd: RawFd) -> io::Result<usize> {
+    let size = unsafe { fcntl(fd, libc::F_GETPIPE_SZ) };
+    if size == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(size as usize)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pipe_capacity(fd: RawFd) -> io::Result<usize> {
+    pipe_buf(fd)
+}
+
+#[cfg(target_os = "linux")]
+fn set_pipe_capacity(fd: RawFd, bytes: usize) -> io::Result<usize> {
+    if bytes > c_int::MAX as usize {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+
+    // Read beforehand, so that an `EPERM` can be told apart from the
+    // per-user limit without looking at a limit changed since.
+    let max = pipe_max_size();
+    let size = unsafe { fcntl(fd, libc::F_SETPIPE_SZ, bytes as c_int) };
+    if size == -1 {
+        return Err(capacity_error(io::Error::last_os_error(), bytes, max));
+    }
+
+    Ok(size as usize)
 }
 
-impl FileFIFOExt for fs::File {
-    /// Returns a wrapped boolean to designate if the underlying
-    /// file is a FIFO device.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # extern crate unix_named_pipe;
-    /// # use std::fs;
-    /// use unix_named_pipe::*;
-    ///
-    /// # let file_name = "/tmp/fifo.5";
-    /// # create(file_name, None).expect("could not create fifo");
-    /// let file = open_read(file_name).expect("could not open fifo for reading");
-    /// assert_eq!(file.is_fifo().unwrap(), true);
-    /// # fs::remove_file(file_name).expect("could not remove fifo");
-    /// ```
-    fn is_fifo(&self) -> io::Result<bool> {
-        let metadata = self.metadata()?;
-        Ok(metadata.file_type().is_fifo())
+/// Returns `/proc/sys/fs/pipe-max-size`, if it can be read.
+#[cfg(target_os = "linux")]
+fn pipe_max_size() -> Option<usize> {
+    fs::read_to_string("/proc/sys/fs/pipe-max-size")
+        .ok()
+        .and_then(|max| max.trim().parse().ok())
+}
+
+/// Explains the `EPERM` of resizing a pipe buffer to `bytes` with
+/// `PipeError::BufferTooLarge` if `bytes` is above `max`, passing every
+/// other error through untouched.
+#[cfg(target_os = "linux")]
+fn capacity_error(err: io::Error, bytes: usize, max: Option<usize>) -> io::Error {
+    match max {
+        Some(max) if bytes > max && err.raw_os_error() == Some(libc::EPERM) => {
+            PipeError::BufferTooLarge {
+                requested: bytes,
+                max,
+                source: std::sync::Arc::new(err),
+            }
+            .into()
+        }
+        _ => err,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_pipe_capacity(_fd: RawFd, _bytes: usize) -> io::Result<usize> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "pipe buffers cannot be resized on this system",
+    ))
+}
+
+fn pipe_buf(fd: RawFd) -> io::Result<usize> {
+    // -1 without a change to errno means that there is no limit.
+    errno::set_errno(errno::Errno(0));
+    let size = unsafe { libc::fpathconf(fd, libc::_PC_PIPE_BUF) };
+    if size == -1 {
+        return match errno::errno().0 {
+            0 => Ok(PIPE_BUF),
+            errno => Err(io::Error::from_raw_os_error(errno)),
+        };
+    }
+
+    Ok(size as usize)
+}
+
+/// Writes some of `buf` to `fd`, waiting until `deadline` on `clock` for
+/// room. The `timeout` the deadline was derived from is reported when it
+/// passes.
+fn write_before(
+    fd: BorrowedFd<'_>,
+    buf: &[u8],
+    clock: &dyn Clock,
+    deadline: Instant,
+    timeout: Duration,
+) -> io::Result<usize> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+
+    loop {
+        let left = deadline.saturating_duration_since(clock.now());
+        match fd.poll_writable(Some(left))? {
+            Readiness::TimedOut => {
+                return Err(PipeError::TimedOut {
+                    path: None,
+                    timeout,
+                }
+                .into())
+            }
+            Readiness::HangUp => return Err(PipeError::ReaderGone.into()),
+            Readiness::Ready => {}
+        }
+
+        // Another writer may have filled the pipe since the poll.
+        match with_nonblocking(fd, || fd.try_write(buf))? {
+            WriteStatus::Written(written) => return Ok(written),
+            WriteStatus::NoReader => return Err(PipeError::ReaderGone.into()),
+            WriteStatus::Full => {}
+        }
     }
 }
 
+/// Runs `f` with `O_NONBLOCK` set on `fd`, clearing it again afterwards if
+/// the descriptor was blocking, so that a read or write following a `poll`
+/// cannot block when another process gets to the pipe first.
+fn with_nonblocking<T, F>(fd: BorrowedFd<'_>, f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T>,
+{
+    if fd.is_nonblocking()? {
+        return f();
+    }
+
+    fd.set_nonblocking(true)?;
+    let result = f();
+    let restored = fd.set_nonblocking(false);
+    let value = result?;
+    restored?;
+
+    Ok(value)
+}
+
+/// Refuses a message of `len` bytes which would not be written atomically.
+fn check_atomic(len: usize) -> io::Result<()> {
+    if len > PIPE_BUF {
+        return Err(PipeError::NotAtomic { len, max: PIPE_BUF }.into());
+    }
+
+    Ok(())
+}
+
+/// Checks that an atomic write of `len` bytes was not cut short, which the
+/// kernel never does for pipes.
+fn check_written(written: usize, len: usize) -> io::Result<()> {
+    if written != len {
+        return Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            format!("atomic write of {} bytes wrote only {}", len, written),
+        ));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn status_flags(fd: RawFd) -> io::Result<c_int> {
+    let flags = unsafe { fcntl(fd, F_GETFL) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(flags)
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use super::super::{create, open_read};
+    use super::super::{create, open_read, open_write, MockClock};
     use super::*;
     use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::OwnedFd;
 
     #[test]
     fn is_fifo() {
@@ -61,4 +1414,597 @@ mod tests {
         fs::remove_file(file_name).expect("could not remove file");
     }
 
+    #[test]
+    fn set_nonblocking() {
+        let file_name = "/tmp/b-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        file.set_nonblocking(false)
+            .expect("could not clear O_NONBLOCK");
+        let flags = unsafe { fcntl(file.as_raw_fd(), F_GETFL) };
+        assert_eq!(flags & O_NONBLOCK, 0);
+
+        file.set_nonblocking(true)
+            .expect("could not set O_NONBLOCK");
+        let flags = unsafe { fcntl(file.as_raw_fd(), F_GETFL) };
+        assert_eq!(flags & O_NONBLOCK, O_NONBLOCK);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn set_nonblocking_keeps_buffered_data() {
+        let file_name = "/tmp/w-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        writer
+            .write_all(b"still here")
+            .expect("could not write to fifo");
+
+        reader
+            .set_nonblocking(false)
+            .expect("could not clear O_NONBLOCK");
+        reader
+            .set_nonblocking(true)
+            .expect("could not set O_NONBLOCK");
+
+        let mut buf = [0u8; 10];
+        reader
+            .read_exact(&mut buf)
+            .expect("could not read buffered data");
+        assert_eq!(&buf, b"still here");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn set_mode() {
+        let file_name = "/tmp/d-fifo";
+        create(file_name, Some(0o600)).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        file.set_mode(Mode::owner_rw().group_write().other_write())
+            .expect("could not change mode");
+        let mode = fs::metadata(file_name).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o622);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn duplicate() {
+        let file_name = "/tmp/e-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let copy = file.duplicate().expect("could not duplicate fifo");
+        assert_ne!(copy.as_raw_fd(), file.as_raw_fd());
+        copy.set_nonblocking(false).unwrap();
+        assert!(!file.is_nonblocking().unwrap());
+
+        let regular = fs::File::open("/dev/null").unwrap();
+        let err = regular.duplicate().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn is_nonblocking() {
+        let file_name = "/tmp/c-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        assert!(file.is_nonblocking().unwrap());
+
+        file.set_nonblocking(false)
+            .expect("could not clear O_NONBLOCK");
+        assert!(!file.is_nonblocking().unwrap());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn works_on_any_fd() {
+        let file_name = "/tmp/f-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let fd = OwnedFd::from(open_read(file_name).expect("could not open fifo for reading"));
+        assert!(fd.is_fifo().unwrap());
+        assert!(fd.as_fd().is_nonblocking().unwrap());
+        let copy = fd.as_fd().duplicate().expect("could not duplicate fifo");
+        assert!(copy.is_fifo().unwrap());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn read_status() {
+        let file_name = "/tmp/g-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let mut buf = [0; 4];
+        assert_eq!(
+            file.read_status(&mut buf).unwrap(),
+            ReadStatus::WriterClosed
+        );
+
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        assert_eq!(file.read_status(&mut buf).unwrap(), ReadStatus::Empty);
+        writer.write_all(b"abcdef").unwrap();
+        assert_eq!(file.read_status(&mut buf).unwrap(), ReadStatus::Data(4));
+        drop(writer);
+        assert_eq!(file.read_status(&mut buf).unwrap(), ReadStatus::Data(2));
+        assert_eq!(&buf[..2], b"ef");
+        assert_eq!(
+            file.read_status(&mut buf).unwrap(),
+            ReadStatus::WriterClosed
+        );
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn try_write() {
+        let file_name = "/tmp/h-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let file = open_write(file_name).expect("could not open fifo for writing");
+        let chunk = [0; 4096];
+        let mut filled = 0;
+        loop {
+            match file.try_write(&chunk).unwrap() {
+                WriteStatus::Written(written) => filled += written,
+                WriteStatus::Full => break,
+                WriteStatus::NoReader => panic!("reader is still attached"),
+            }
+        }
+        assert!(filled > 0);
+
+        drop(reader);
+        assert_eq!(file.try_write(&chunk).unwrap(), WriteStatus::NoReader);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn buffer_size() {
+        let file_name = "/tmp/i-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let size = reader.buffer_size().expect("could not query buffer size");
+        assert_eq!(writer.buffer_size().unwrap(), size);
+
+        // Elsewhere the size is only a lower bound on the capacity.
+        #[cfg(target_os = "linux")]
+        {
+            let chunk = vec![0; size];
+            assert_eq!(
+                writer.try_write(&chunk).unwrap(),
+                WriteStatus::Written(size)
+            );
+            assert_eq!(writer.try_write(b"x").unwrap(), WriteStatus::Full);
+        }
+
+        let regular = fs::File::open("/dev/null").unwrap();
+        assert!(regular.buffer_size().is_err());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn set_buffer_size() {
+        let file_name = "/tmp/j-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let size = writer
+            .set_buffer_size(1 << 20)
+            .expect("could not resize fifo");
+        assert!(size >= 1 << 20);
+        assert_eq!(reader.buffer_size().unwrap(), size);
+
+        let small = reader.set_buffer_size(1).expect("could not shrink fifo");
+        assert!(small > 0 && small < size);
+
+        let err = reader.set_buffer_size(usize::MAX).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // Without CAP_SYS_RESOURCE, the limit cannot be exceeded.
+        let max = pipe_max_size().expect("could not read pipe-max-size");
+        if let Err(err) = reader.set_buffer_size(max + 1) {
+            assert!(matches!(
+                PipeError::of(&err),
+                Some(PipeError::BufferTooLarge { .. })
+            ));
+        }
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn explains_buffer_limit() {
+        let eperm = || io::Error::from_raw_os_error(libc::EPERM);
+        let err = capacity_error(eperm(), 2 << 20, Some(1 << 20));
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        match PipeError::of(&err) {
+            Some(PipeError::BufferTooLarge { requested, max, .. }) => {
+                assert_eq!((requested, max), (2 << 20, 1 << 20))
+            }
+            other => panic!("expected BufferTooLarge, got {:?}", other),
+        }
+        let source = err.get_ref().and_then(|err| err.source()).unwrap();
+        let source = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.raw_os_error(), Some(libc::EPERM));
+
+        let err = capacity_error(eperm(), 1 << 20, Some(1 << 20));
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+        let err = capacity_error(eperm(), 2 << 20, None);
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+    }
+
+    #[test]
+    fn bytes_available() {
+        let file_name = "/tmp/k-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        assert_eq!(reader.bytes_available().unwrap(), 0);
+
+        writer.write_all(b"abcdef").unwrap();
+        assert_eq!(reader.bytes_available().unwrap(), 6);
+        assert_eq!(writer.bytes_available().unwrap(), 6);
+
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.bytes_available().unwrap(), 2);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn backlog() {
+        let file_name = "/tmp/l-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let _reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let capacity = writer.buffer_size().unwrap();
+        assert_eq!(writer.backlog().unwrap().fill_level(), 0.0);
+
+        let half = vec![0; capacity / 2];
+        assert_eq!(
+            writer.try_write(&half).unwrap(),
+            WriteStatus::Written(half.len())
+        );
+        let backlog = writer.backlog().unwrap();
+        assert_eq!(backlog.used, half.len());
+        assert_eq!(backlog.capacity, capacity);
+        assert_eq!(backlog.fill_level(), 0.5);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn max_atomic_write() {
+        let file_name = "/tmp/m-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        assert_eq!(file.max_atomic_write().unwrap(), PIPE_BUF);
+        #[cfg(target_os = "linux")]
+        assert_eq!(PIPE_BUF, 4096);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn write_atomic() {
+        let file_name = "/tmp/n-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let file = open_write(file_name).expect("could not open fifo for writing");
+        file.write_atomic(&[1; PIPE_BUF]).unwrap();
+        assert_eq!(reader.bytes_available().unwrap(), PIPE_BUF);
+
+        let err = file.write_atomic(&[1; PIPE_BUF + 1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(reader.bytes_available().unwrap(), PIPE_BUF);
+
+        while file.try_write(&[0; 1024]).unwrap() != WriteStatus::Full {}
+        while file.try_write(&[0]).unwrap() != WriteStatus::Full {}
+        let err = file.write_atomic(b"late").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        drop(reader);
+        let err = file.write_atomic(b"gone").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn write_vectored_atomic() {
+        let file_name = "/tmp/o-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let file = open_write(file_name).expect("could not open fifo for writing");
+        file.write_vectored_atomic(&[
+            IoSlice::new(b"head:"),
+            IoSlice::new(b""),
+            IoSlice::new(b"body"),
+        ])
+        .unwrap();
+        let mut buf = [0; 9];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"head:body");
+
+        let half = [0; PIPE_BUF / 2 + 1];
+        let err = file
+            .write_vectored_atomic(&[IoSlice::new(&half), IoSlice::new(&half)])
+            .unwrap_err();
+        match PipeError::of(&err) {
+            Some(PipeError::NotAtomic { len, max }) => {
+                assert_eq!(len, PIPE_BUF + 2);
+                assert_eq!(max, PIPE_BUF);
+            }
+            other => panic!("expected NotAtomic, got {:?}", other),
+        }
+        assert_eq!(reader.bytes_available().unwrap(), 0);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn drain() {
+        let file_name = "/tmp/p-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        assert_eq!(reader.drain().unwrap(), 0);
+
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        writer.write_all(&[1; 65536]).unwrap();
+        reader.set_nonblocking(false).unwrap();
+        assert_eq!(reader.drain().unwrap(), 65536);
+        assert_eq!(reader.drain().unwrap(), 0);
+
+        writer.write_all(b"fresh").unwrap();
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"fresh");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn read_available() {
+        let file_name = "/tmp/q-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut received = b"kept:".to_vec();
+        assert_eq!(reader.read_available(&mut received).unwrap(), 0);
+
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        assert_eq!(reader.read_available(&mut received).unwrap(), 0);
+        writer.write_all(&[7; 60_000]).unwrap();
+        reader.set_nonblocking(false).unwrap();
+        assert_eq!(reader.read_available(&mut received).unwrap(), 60_000);
+        assert_eq!(received.len(), 60_005);
+        assert_eq!(&received[..5], b"kept:");
+        assert!(received[5..].iter().all(|&byte| byte == 7));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn poll_readable() {
+        let file_name = "/tmp/r-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        let timeout = Some(Duration::from_millis(20));
+        assert_eq!(reader.poll_readable(timeout).unwrap(), Readiness::TimedOut);
+
+        writer.write_all(b"ab").unwrap();
+        assert_eq!(reader.poll_readable(None).unwrap(), Readiness::Ready);
+        drop(writer);
+        assert_eq!(reader.poll_readable(timeout).unwrap(), Readiness::Ready);
+
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.poll_readable(timeout).unwrap(), Readiness::HangUp);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn poll_writable() {
+        let file_name = "/tmp/s-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let timeout = Some(Duration::from_millis(20));
+        assert_eq!(writer.poll_writable(timeout).unwrap(), Readiness::Ready);
+
+        while writer.try_write(&[0; 4096]).unwrap() != WriteStatus::Full {}
+        assert_eq!(writer.poll_writable(timeout).unwrap(), Readiness::TimedOut);
+
+        let mut buf = vec![0; 8192];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(writer.poll_writable(None).unwrap(), Readiness::Ready);
+
+        drop(reader);
+        assert_eq!(writer.poll_writable(None).unwrap(), Readiness::HangUp);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn read_timeout() {
+        let file_name = "/tmp/t-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        let mut buf = [0; 8];
+        let start = Instant::now();
+        let err = reader
+            .read_timeout(&mut buf, Duration::from_millis(30))
+            .unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        reader.set_nonblocking(false).unwrap();
+        writer.write_all(b"ok").unwrap();
+        assert_eq!(
+            reader
+                .read_timeout(&mut buf, Duration::from_secs(5))
+                .unwrap(),
+            2
+        );
+        drop(writer);
+        assert_eq!(
+            reader
+                .read_timeout(&mut buf, Duration::from_secs(5))
+                .unwrap(),
+            0
+        );
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn write_all_timeout() {
+        let file_name = "/tmp/u-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let capacity = writer.buffer_size().unwrap();
+        writer
+            .write_all_timeout(&vec![1; capacity], Duration::from_secs(5))
+            .expect("could not fill fifo");
+
+        let start = Instant::now();
+        let err = writer
+            .write_timeout(b"more", Duration::from_millis(30))
+            .unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let consumer = std::thread::spawn(move || {
+            let mut reader = reader;
+            reader.set_nonblocking(false).unwrap();
+            let mut received = vec![0; 2 * capacity];
+            reader.read_exact(&mut received).map(|_| received)
+        });
+        writer.set_nonblocking(false).unwrap();
+        writer
+            .write_all_timeout(&vec![2; capacity], Duration::from_secs(5))
+            .expect("could not write while the reader catches up");
+        let received = consumer.join().unwrap().unwrap();
+        assert!(received[capacity..].iter().all(|&byte| byte == 2));
+
+        let err = writer
+            .write_timeout(b"gone", Duration::from_secs(5))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn write_timeout_never_blocks_blocking_descriptor() {
+        let file_name = "/tmp/v-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let capacity = writer.buffer_size().unwrap();
+        writer
+            .write_all_timeout(&vec![1; capacity], Duration::from_secs(5))
+            .expect("could not fill fifo");
+
+        // Room for a single page makes poll report the pipe writable, but
+        // a blocking write of more than that would wait for the reader.
+        let mut page = vec![0; PIPE_BUF];
+        reader.set_nonblocking(false).unwrap();
+        (&reader).read_exact(&mut page).unwrap();
+        writer.set_nonblocking(false).unwrap();
+
+        let big = vec![2; capacity];
+        let written = writer
+            .write_timeout(&big, Duration::from_secs(5))
+            .expect("could not write into the free page");
+        assert!(written > 0 && written <= PIPE_BUF);
+        let err = writer
+            .write_timeout(&big, Duration::from_millis(30))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(!writer.is_nonblocking().unwrap());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    /// A clock which moves on by `step` every time it is read.
+    #[derive(Debug)]
+    struct SteppingClock {
+        clock: MockClock,
+        step: Duration,
+    }
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> Instant {
+            self.clock.advance(self.step);
+            self.clock.now()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.clock.sleep(duration)
+        }
+    }
+
+    #[test]
+    fn timeouts_measured_with_clock() {
+        let file_name = "/tmp/x-fifo";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let clock = SteppingClock {
+            clock: MockClock::new(),
+            step: Duration::from_secs(60),
+        };
+        let start = Instant::now();
+        let mut buf = [0; 16];
+        let err = reader
+            .read_timeout_with_clock(&mut buf, Duration::from_secs(60), &clock)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        let err = writer
+            .write_all_timeout_with_clock(&vec![0; 1 << 20], Duration::from_secs(60), &clock)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(60));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
 }
@@ -0,0 +1,268 @@
+//! Provides a writer which spools data to a regular file while no reader is
+//! attached to a named pipe, and replays it once one appears.
+
+use super::error::is_no_reader;
+use super::{open_write, Clock, PipeError, SystemClock};
+use libc::EPIPE;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A writer for a named pipe that tolerates the reader being absent.
+///
+/// While the pipe has no reader, writes fail with `PipeError::NoReader` as
+/// `open_write` does. Once that has persisted for longer than the configured threshold,
+/// writes are appended to a fallback file instead. The next time the pipe
+/// can be opened, the fallback contents are replayed into it ahead of any
+/// new data, so the reader sees everything in the order it was written.
+pub struct FallbackWriter {
+    path: PathBuf,
+    fallback_path: PathBuf,
+    threshold: Duration,
+    pipe: Option<File>,
+    fallback: Option<File>,
+    replayed: u64,
+    no_reader_since: Option<Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+impl FallbackWriter {
+    /// Creates a writer for the pipe at `path`, spooling to `fallback_path`
+    /// once there has been no reader for longer than `threshold`.
+    /// The pipe is not opened until the first write.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use std::time::Duration;
+    /// use unix_named_pipe::FallbackWriter;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # let spool_name = &unix_named_pipe::unique_path("spool");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let mut writer = FallbackWriter::new(file_name, spool_name, Duration::from_secs(0));
+    /// writer.write_all(b"queued until a reader attaches\n").expect("could not spool data");
+    /// assert!(writer.spooled_bytes() > 0);
+    /// # fs::remove_file(file_name).unwrap();
+    /// # fs::remove_file(spool_name).unwrap();
+    /// ```
+    pub fn new<P: AsRef<Path>, F: AsRef<Path>>(
+        path: P,
+        fallback_path: F,
+        threshold: Duration,
+    ) -> FallbackWriter {
+        FallbackWriter {
+            path: path.as_ref().to_path_buf(),
+            fallback_path: fallback_path.as_ref().to_path_buf(),
+            threshold,
+            pipe: None,
+            fallback: None,
+            replayed: 0,
+            no_reader_since: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Measures how long the reader has been absent with `clock` instead of
+    /// the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> FallbackWriter {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the number of bytes held in the fallback file which have not
+    /// yet been replayed into the pipe.
+    pub fn spooled_bytes(&self) -> u64 {
+        match self.fallback {
+            Some(ref file) => file
+                .metadata()
+                .map(|m| m.len().saturating_sub(self.replayed))
+                .unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// Opens the pipe if it is not open yet. Returns `false` if there is
+    /// still no reader.
+    fn connect(&mut self) -> io::Result<bool> {
+        if self.pipe.is_some() {
+            return Ok(true);
+        }
+
+        match open_write(&self.path) {
+            Ok(file) => {
+                self.pipe = Some(file);
+                self.no_reader_since = None;
+                Ok(true)
+            }
+            Err(ref err) if is_no_reader(err) => {
+                let now = self.clock.now();
+                self.no_reader_since.get_or_insert(now);
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes to the open pipe, forgetting it if the reader has gone away.
+    fn write_pipe(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = match self.pipe {
+            Some(ref mut pipe) => pipe.write(buf),
+            None => return Err(PipeError::NoReader.into()),
+        };
+
+        if let Err(ref err) = result {
+            if err.raw_os_error() == Some(EPIPE) {
+                self.pipe = None;
+                self.no_reader_since = Some(self.clock.now());
+            }
+        }
+
+        result
+    }
+
+    /// Replays as much of the fallback file into the pipe as it will take.
+    /// Returns `true` once nothing is left to replay.
+    fn replay(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        loop {
+            let count = match self.fallback {
+                Some(ref file) => file.read_at(&mut chunk, self.replayed)?,
+                None => return Ok(true),
+            };
+            if count == 0 {
+                break;
+            }
+
+            match self.write_pipe(&chunk[..count]) {
+                Ok(written) => self.replayed += written as u64,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(ref err) if err.raw_os_error() == Some(EPIPE) => return Ok(false),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Some(ref file) = self.fallback {
+            file.set_len(0)?;
+        }
+        self.replayed = 0;
+
+        Ok(true)
+    }
+
+    fn spool(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let file = match self.fallback.take() {
+            Some(file) => file,
+            None => OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(&self.fallback_path)?,
+        };
+
+        self.fallback.insert(file).write_all(buf)?;
+        Ok(buf.len())
+    }
+}
+
+impl Write for FallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.connect()? && self.replay()? {
+            match self.write_pipe(buf) {
+                Err(ref err) if err.raw_os_error() == Some(EPIPE) => {}
+                result => return result,
+            }
+        }
+
+        let now = self.clock.now();
+        let waited = self
+            .no_reader_since
+            .map_or(Duration::from_secs(0), |since| now - since);
+        if self.spooled_bytes() > 0 || waited >= self.threshold {
+            return self.spool(buf);
+        }
+
+        Err(PipeError::NoReader.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.connect()? {
+            self.replay()?;
+        }
+
+        match self.pipe {
+            Some(ref mut pipe) => pipe.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, MockClock};
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn waits_for_threshold() {
+        let file_name = "/tmp/fallback-wait.pipe";
+        let spool_name = "/tmp/fallback-wait.spool";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut writer = FallbackWriter::new(file_name, spool_name, Duration::from_secs(3600));
+        let err = writer.write(b"data").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENXIO));
+        assert_eq!(writer.spooled_bytes(), 0);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn spools_once_threshold_passes() {
+        let file_name = "/tmp/fallback-clock.pipe";
+        let spool_name = "/tmp/fallback-clock.spool";
+        create(file_name, None).expect("could not create fifo");
+
+        let clock = Arc::new(MockClock::new());
+        let mut writer = FallbackWriter::new(file_name, spool_name, Duration::from_secs(60))
+            .with_clock(clock.clone());
+        assert!(writer.write(b"data").is_err());
+
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(writer.write(b"data").unwrap(), 4);
+        assert_eq!(writer.spooled_bytes(), 4);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+        fs::remove_file(spool_name).expect("could not remove spool");
+    }
+
+    #[test]
+    fn replays_spool_once_reader_attaches() {
+        let file_name = "/tmp/fallback-replay.pipe";
+        let spool_name = "/tmp/fallback-replay.spool";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut writer = FallbackWriter::new(file_name, spool_name, Duration::from_secs(0));
+        writer.write_all(b"first,").expect("could not spool data");
+        writer.write_all(b"second,").expect("could not spool data");
+        assert_eq!(writer.spooled_bytes(), 13);
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        writer.write_all(b"third").expect("could not write data");
+        assert_eq!(writer.spooled_bytes(), 0);
+
+        let mut actual = [0; 18];
+        reader.read_exact(&mut actual).expect("could not read data");
+        assert_eq!(&actual, b"first,second,third");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+        fs::remove_file(spool_name).expect("could not remove spool");
+    }
+}
@@ -0,0 +1,240 @@
+//! Provides `Follower`, which reads a named pipe like `tail -f` reads a
+//! file, carrying on through writers coming and going and the pipe being
+//! replaced.
+
+use super::{Clock, Direction, FileFIFOExt, PipeOptions, Readiness, SystemClock};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often a `Follower` checks for a new pipe while there is no data.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A reader for a named pipe which never reaches the end of its data.
+///
+/// When every writer has hung up, reads wait for the next one to attach
+/// instead of returning `Ok(0)`. While there is nothing to read, the path
+/// is checked every poll interval, and if the pipe has been removed and
+/// created again, the new pipe is opened and read from then on, once the
+/// old one is empty. A path which has disappeared is waited for.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::{Read, Write};
+/// use unix_named_pipe::Follower;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let mut follower = Follower::open(file_name).expect("could not open fifo for reading");
+/// for client in &["first", "second"] {
+///     let mut writer = unix_named_pipe::open_write(file_name).unwrap();
+///     writer.write_all(client.as_bytes()).unwrap();
+/// }
+///
+/// let mut received = [0; 11];
+/// follower.read_exact(&mut received).unwrap();
+/// assert_eq!(&received, b"firstsecond");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Follower {
+    path: PathBuf,
+    pipe: File,
+    id: (u64, u64),
+    poll_interval: Duration,
+    reopens: u64,
+    clock: Arc<dyn Clock>,
+}
+
+impl Follower {
+    /// Opens the FIFO at `path` for following.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by opening the pipe, including
+    /// `PipeError::NotAFifo` if `path` is not a FIFO.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Follower> {
+        let path = path.as_ref().to_path_buf();
+        let (pipe, id) = open_fifo(&path)?;
+
+        Ok(Follower {
+            path,
+            pipe,
+            id,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            reopens: 0,
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Sets how often the path is checked for a new pipe while there is
+    /// nothing to read, which defaults to `DEFAULT_POLL_INTERVAL`.
+    pub fn poll_interval(mut self, interval: Duration) -> Follower {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sleeps between checks while no writer is attached with `clock`
+    /// instead of the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Follower {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the path of the pipe.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns how many times a new pipe has been opened at the path.
+    pub fn reopens(&self) -> u64 {
+        self.reopens
+    }
+
+    /// Opens the pipe at the path if it is a different FIFO from the one
+    /// being read.
+    fn follow_replacement(&mut self) -> io::Result<()> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if !metadata.file_type().is_fifo() || (metadata.dev(), metadata.ino()) == self.id {
+            return Ok(());
+        }
+
+        let (pipe, id) = open_fifo(&self.path)?;
+        self.pipe = pipe;
+        self.id = id;
+        self.reopens += 1;
+
+        Ok(())
+    }
+}
+
+impl Read for Follower {
+    /// Reads into `buf`, waiting for data for as long as it takes.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by `read`, `poll`, or by opening a new pipe at
+    /// the path.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match self.pipe.read(buf) {
+                Ok(0) => {
+                    // The pipe reports a hang-up until a writer attaches,
+                    // so sleep instead of polling.
+                    self.follow_replacement()?;
+                    self.clock.sleep(self.poll_interval);
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    if self.pipe.poll_readable(Some(self.poll_interval))? == Readiness::TimedOut {
+                        self.follow_replacement()?;
+                    }
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                result => return result,
+            }
+        }
+    }
+}
+
+impl AsFd for Follower {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.pipe.as_fd()
+    }
+}
+
+impl AsRawFd for Follower {
+    fn as_raw_fd(&self) -> RawFd {
+        self.pipe.as_raw_fd()
+    }
+}
+
+/// Opens the read end of the FIFO at `path`, returning it along with its
+/// device and inode numbers.
+fn open_fifo(path: &Path) -> io::Result<(File, (u64, u64))> {
+    let pipe = PipeOptions::new(Direction::Read)
+        .verify_fifo(true)
+        .open(path)?;
+    let metadata = pipe.metadata()?;
+
+    Ok((pipe, (metadata.dev(), metadata.ino())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_write, open_write_retry, MockClock, RetryPolicy};
+    use super::*;
+    use std::io::Write;
+    use std::thread;
+
+    #[test]
+    fn follows_recreated_pipe() {
+        let file_name = "/tmp/follower.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut follower = Follower::open(file_name)
+            .expect("could not open fifo for reading")
+            .poll_interval(Duration::from_millis(5));
+        let feeder = thread::spawn(move || {
+            let policy = RetryPolicy::new().deadline(Duration::from_secs(5));
+            let mut writer = open_write_retry(file_name, &policy).unwrap();
+            writer.write_all(b"old").unwrap();
+            drop(writer);
+
+            fs::remove_file(file_name).unwrap();
+            thread::sleep(Duration::from_millis(20));
+            create(file_name, None).unwrap();
+            let mut writer = open_write_retry(file_name, &policy).unwrap();
+            writer.write_all(b"new").unwrap();
+        });
+
+        let mut buf = [0; 6];
+        follower.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"oldnew");
+        assert_eq!(follower.reopens(), 1);
+        feeder.join().unwrap();
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn sleeps_with_clock() {
+        let file_name = "/tmp/follower-clock.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let clock = Arc::new(MockClock::new());
+        let start = clock.now();
+        let mut follower = Follower::open(file_name)
+            .expect("could not open fifo for reading")
+            .poll_interval(Duration::from_secs(60))
+            .clock(clock.clone());
+        let feeder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let mut writer = open_write(file_name).unwrap();
+            writer.write_all(b"x").unwrap();
+        });
+
+        let mut buf = [0; 1];
+        follower.read_exact(&mut buf).unwrap();
+        feeder.join().unwrap();
+        let slept = clock.now() - start;
+        assert!(slept >= Duration::from_secs(60));
+        assert_eq!(slept.as_secs() % 60, 0);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
@@ -0,0 +1,203 @@
+//! Provides `PipeGroup`, which manages a set of related FIFOs as one unit.
+
+use super::error::not_a_fifo;
+use super::{create_exact, remove_if_fifo, IntoMode, Mode, PipeError};
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// A set of named FIFOs created side by side in one directory, such as the
+/// command, response and event pipes of a single service.
+///
+/// Every pipe in the group is given the same permissions. All of the pipes
+/// are removed when the group is dropped, skipping any path which has since
+/// been replaced by something other than a FIFO.
+#[derive(Debug)]
+pub struct PipeGroup {
+    mode: u32,
+    pipes: Vec<(String, PathBuf)>,
+}
+
+impl PipeGroup {
+    /// Creates one FIFO per entry of `names` inside `dir`, each with mode
+    /// `mode` if given, else `0o644`. The mode is applied after creation so
+    /// it is not narrowed by the process umask.
+    ///
+    /// If any pipe cannot be created, the ones created so far are removed
+    /// again before the error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// use unix_named_pipe::PipeGroup;
+    ///
+    /// # let dir = &unix_named_pipe::unique_path("fifo-dir");
+    /// # std::fs::create_dir_all(dir).unwrap();
+    /// let group = PipeGroup::create(dir, &["cmd", "resp", "events"], Some(0o660))
+    ///     .expect("could not create pipe group");
+    /// let cmd = unix_named_pipe::open_read(group.path("cmd").unwrap())
+    ///     .expect("could not open fifo for reading");
+    /// group.check().expect("pipe group is unhealthy");
+    /// # drop(group);
+    /// # std::fs::remove_dir(dir).unwrap();
+    /// ```
+    pub fn create<D: AsRef<Path>, M: IntoMode>(
+        dir: D,
+        names: &[&str],
+        mode: M,
+    ) -> io::Result<PipeGroup> {
+        let mut group = PipeGroup {
+            mode: mode.into_mode()?.map_or(0o644, Mode::bits),
+            pipes: Vec::with_capacity(names.len()),
+        };
+
+        for name in names {
+            let path = dir.as_ref().join(name);
+            create_exact(&path, Some(group.mode))?;
+            group.pipes.push((name.to_string(), path));
+        }
+
+        Ok(group)
+    }
+
+    /// Returns the path of the pipe called `name`, if it is part of this group.
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        self.pipes
+            .iter()
+            .find(|(pipe_name, _)| pipe_name == name)
+            .map(|(_, path)| path.as_path())
+    }
+
+    /// Returns the paths of every pipe in the group, in creation order.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.pipes.iter().map(|(_, path)| path.as_path())
+    }
+
+    /// Checks that every pipe in the group still exists, is a FIFO, and
+    /// carries the group's permissions.
+    ///
+    /// # Errors
+    ///
+    /// - If a pipe is missing, the error from `stat` is returned.
+    /// - If a pipe is not a FIFO, `Err(io::ErrorKind::InvalidInput)` will be
+    ///   returned, carrying `PipeError::NotAFifo`.
+    /// - If a pipe has different permissions, `Err(io::ErrorKind::Other)`
+    ///   will be returned, carrying `PipeError::ModeMismatch`.
+    pub fn check(&self) -> io::Result<()> {
+        for path in self.paths() {
+            let metadata = fs::metadata(path)?;
+            if !metadata.file_type().is_fifo() {
+                return Err(not_a_fifo("check", Some(path), metadata.file_type()));
+            }
+
+            let mode = metadata.permissions().mode() & 0o7777;
+            if mode != self.mode {
+                return Err(PipeError::ModeMismatch {
+                    path: path.to_path_buf(),
+                    expected: self.mode,
+                    actual: mode,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for PipeGroup {
+    fn drop(&mut self) {
+        for path in self.paths() {
+            let _ = remove_if_fifo(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_check_and_drop() {
+        let dir = "/tmp/pipe-group";
+        fs::create_dir_all(dir).expect("could not create test dir");
+
+        let group = PipeGroup::create(dir, &["cmd", "resp"], Some(0o666))
+            .expect("could not create pipe group");
+        group.check().expect("pipe group should be healthy");
+
+        let cmd = group.path("cmd").unwrap().to_path_buf();
+        assert_eq!(cmd, Path::new(dir).join("cmd"));
+        assert!(group.path("events").is_none());
+
+        let mode = fs::metadata(&cmd).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o666);
+
+        drop(group);
+        assert!(!cmd.exists());
+        fs::remove_dir(dir).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn check_detects_missing_pipe() {
+        let dir = "/tmp/pipe-group-missing";
+        fs::create_dir_all(dir).expect("could not create test dir");
+
+        let group =
+            PipeGroup::create(dir, &["cmd", "resp"], None).expect("could not create pipe group");
+        fs::remove_file(group.path("resp").unwrap()).expect("could not remove pipe");
+
+        let err = group.check().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        drop(group);
+        fs::remove_dir(dir).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn check_detects_mode_change() {
+        let dir = "/tmp/pipe-group-mode";
+        fs::create_dir_all(dir).expect("could not create test dir");
+
+        let group =
+            PipeGroup::create(dir, &["cmd"], Some(0o600)).expect("could not create pipe group");
+        let cmd = group.path("cmd").unwrap();
+        fs::set_permissions(cmd, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let err = group.check().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "expected fifo at {} to have mode 600, has 640",
+                cmd.display()
+            )
+        );
+        match PipeError::of(&err) {
+            Some(pipe_err @ PipeError::ModeMismatch { .. }) => {
+                assert_eq!(pipe_err.path(), Some(cmd))
+            }
+            other => panic!("expected ModeMismatch, got {:?}", other),
+        }
+
+        drop(group);
+        fs::remove_dir(dir).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn create_rolls_back_on_failure() {
+        let dir = "/tmp/pipe-group-rollback";
+        fs::create_dir_all(dir).expect("could not create test dir");
+        let blocker = Path::new(dir).join("resp");
+        fs::write(&blocker, "").expect("could not write test file");
+
+        let err = PipeGroup::create(dir, &["cmd", "resp"], None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(!Path::new(dir).join("cmd").exists());
+
+        fs::remove_file(&blocker).expect("could not remove test file");
+        fs::remove_dir(dir).expect("could not remove test dir");
+    }
+}
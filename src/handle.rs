@@ -0,0 +1,144 @@
+//! Provides `PathHandle`, an `O_PATH` descriptor for a named pipe on Linux,
+//! so one component can check a pipe and another can open it later.
+
+use super::audit::{self, AuditAction};
+use super::{check_path, PipeOptions};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::path::Path;
+
+/// A handle to a named pipe opened with `O_PATH`.
+///
+/// Opening the handle never blocks and neither reads from nor writes to the
+/// pipe, so it can be taken by a component which only validates the pipe's
+/// ownership and permissions. The handle can then be passed on and reopened
+/// for reading or writing through `/proc/self/fd`, which opens exactly the
+/// file that was checked even if the path has since been replaced.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::{Direction, PathHandle, PipeOptions};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let handle = PathHandle::open(file_name).expect("could not open fifo handle");
+/// assert!(handle.is_fifo().unwrap());
+///
+/// let file = handle
+///     .reopen(&PipeOptions::new(Direction::Read))
+///     .expect("could not reopen fifo for reading");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct PathHandle {
+    file: File,
+}
+
+impl PathHandle {
+    /// Opens an `O_PATH` handle to the file at `path`. The handle is closed
+    /// on `exec`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PathHandle> {
+        let result = check_path(path.as_ref()).and_then(|()| {
+            OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_PATH)
+                .open(path.as_ref())
+        });
+        audit::record(AuditAction::Open, path.as_ref(), &result);
+
+        Ok(PathHandle { file: result? })
+    }
+
+    /// Returns the metadata of the file the handle refers to, for checking
+    /// its owner and permissions.
+    pub fn metadata(&self) -> io::Result<fs::Metadata> {
+        self.file.metadata()
+    }
+
+    /// Returns a wrapped boolean to designate if the handle refers to a FIFO.
+    pub fn is_fifo(&self) -> io::Result<bool> {
+        Ok(self.metadata()?.file_type().is_fifo())
+    }
+
+    /// Opens the file the handle refers to with `options`.
+    ///
+    /// # Errors
+    ///
+    /// - Any error returned by `PipeOptions::open`. The handle itself stays
+    ///   usable, so a failed non-blocking write open can be retried.
+    pub fn reopen(&self, options: &PipeOptions) -> io::Result<File> {
+        options.open(format!("/proc/self/fd/{}", self.file.as_raw_fd()))
+    }
+}
+
+impl AsFd for PathHandle {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+impl AsRawFd for PathHandle {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, Direction, FileFIFOExt, PipeError};
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn reopen_fifo_handle() {
+        let file_name = "/tmp/handle.pipe";
+        create(file_name, Some(0o600)).expect("could not create fifo");
+
+        let handle = PathHandle::open(file_name).expect("could not open fifo handle");
+        assert!(handle.is_fifo().unwrap());
+        assert_eq!(
+            handle.metadata().unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        let err = handle
+            .reopen(&PipeOptions::new(Direction::Write))
+            .unwrap_err();
+        assert!(matches!(PipeError::of(&err), Some(PipeError::NoReader)));
+
+        let mut read_file = handle
+            .reopen(&PipeOptions::new(Direction::Read))
+            .expect("could not reopen fifo for reading");
+        fs::remove_file(file_name).expect("could not remove fifo");
+
+        let mut write_file = handle
+            .reopen(&PipeOptions::new(Direction::Write))
+            .expect("could not reopen fifo for writing");
+        assert!(write_file.is_fifo().unwrap());
+        write_file
+            .write_all(b"ok")
+            .expect("could not write to fifo");
+        let mut actual = [0; 2];
+        read_file
+            .read_exact(&mut actual)
+            .expect("could not read from fifo");
+        assert_eq!(&actual, b"ok");
+    }
+
+    #[test]
+    fn handle_to_regular_file() {
+        let file_name = "/tmp/handle.txt";
+        fs::write(file_name, "").expect("could not write test file");
+
+        let handle = PathHandle::open(file_name).expect("could not open file handle");
+        assert!(!handle.is_fifo().unwrap());
+
+        fs::remove_file(file_name).expect("could not remove test file");
+    }
+}
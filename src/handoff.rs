@@ -0,0 +1,265 @@
+//! Provides a handover of a pipe's read end from one process to another, so
+//! a consuming daemon can be replaced without writers ever seeing the pipe
+//! lose its reader.
+//!
+//! The current reader binds a `Listener` on a Unix socket next to the pipe
+//! and checks it between reads. Its successor calls `take_over`, which
+//! receives the already-open read descriptor over the socket with
+//! `SCM_RIGHTS`. Because the same open file is passed along, the read end
+//! is never closed and no buffered data is lost; the old reader simply
+//! stops reading once `try_hand_over` returns `true`.
+//!
+//! ```
+//! # extern crate unix_named_pipe;
+//! # use std::fs;
+//! # use std::thread;
+//! use unix_named_pipe::handoff::{self, Listener};
+//!
+//! # let file_name = &unix_named_pipe::unique_path("fifo");
+//! # let socket_name = unix_named_pipe::unique_path("handoff");
+//! # unix_named_pipe::create(file_name, None).unwrap();
+//! let pipe = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+//! let listener = Listener::bind(&socket_name).expect("could not bind handoff socket");
+//!
+//! // In the successor process:
+//! let successor = thread::spawn(move || handoff::take_over(socket_name));
+//!
+//! // In the current reader's loop, between messages:
+//! while !listener.try_hand_over(&pipe).expect("could not hand over pipe") {
+//!     // ... keep reading from `pipe` ...
+//! }
+//! drop(pipe);
+//!
+//! let pipe = successor.join().unwrap().expect("could not take over pipe");
+//! # fs::remove_file(file_name).unwrap();
+//! ```
+
+use super::FileFIFOExt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+/// Sent alongside the descriptor, and echoed back by the successor once it
+/// holds the descriptor.
+const HANDOFF_TAG: u8 = 0x48;
+
+/// The current reader's side of a handover.
+#[derive(Debug)]
+pub struct Listener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Listener {
+    /// Binds a Unix socket at `path` on which a successor can ask for the
+    /// read end. The socket file is removed again when the listener drops.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Listener> {
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Listener {
+            listener,
+            path: path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Hands `pipe` to a waiting successor, if there is one. Returns `false`
+    /// without blocking when no successor has connected.
+    ///
+    /// Once this returns `true` the successor holds the read end and the
+    /// caller must stop reading from `pipe`; it can then be dropped without
+    /// writers noticing.
+    pub fn try_hand_over(&self, pipe: &File) -> io::Result<bool> {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        stream.set_nonblocking(false)?;
+
+        send_fd(&stream, pipe.as_raw_fd())?;
+
+        let mut ack = [0; 1];
+        (&stream).read_exact(&mut ack)?;
+        if ack[0] != HANDOFF_TAG {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected handoff acknowledgement on {:?}", self.path),
+            ));
+        }
+
+        Ok(true)
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Connects to the `Listener` bound at `path` and takes over the pipe it
+/// hands out, blocking until the current reader passes it on.
+///
+/// # Errors
+///
+/// - Any error from connecting to or reading from the socket.
+/// - If the received descriptor is not a FIFO, `Err(io::ErrorKind::InvalidData)`
+///   will be returned.
+pub fn take_over<P: AsRef<Path>>(path: P) -> io::Result<File> {
+    let mut stream = UnixStream::connect(&path)?;
+    let file = recv_fd(&stream)?;
+    if !file.is_fifo()? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("descriptor received on {:?} is not a fifo", path.as_ref()),
+        ));
+    }
+
+    stream.write_all(&[HANDOFF_TAG])?;
+    Ok(file)
+}
+
+/// Large and aligned enough for a control message carrying one descriptor.
+#[repr(C)]
+struct ControlBuffer {
+    _align: [libc::cmsghdr; 0],
+    bytes: [u8; 64],
+}
+
+fn send_fd(stream: &UnixStream, fd: RawFd) -> io::Result<()> {
+    let mut tag = [HANDOFF_TAG];
+    let mut iov = libc::iovec {
+        iov_base: tag.as_mut_ptr() as *mut libc::c_void,
+        iov_len: tag.len(),
+    };
+    let mut control = ControlBuffer {
+        _align: [],
+        bytes: [0; 64],
+    };
+
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.bytes.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+
+        if libc::sendmsg(stream.as_raw_fd(), &msg, 0) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+fn recv_fd(stream: &UnixStream) -> io::Result<File> {
+    let mut tag = [0];
+    let mut iov = libc::iovec {
+        iov_base: tag.as_mut_ptr() as *mut libc::c_void,
+        iov_len: tag.len(),
+    };
+    let mut control = ControlBuffer {
+        _align: [],
+        bytes: [0; 64],
+    };
+
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.bytes.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.bytes.len() as _;
+
+        if libc::recvmsg(stream.as_raw_fd(), &mut msg, recv_flags()) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        let has_fd = !cmsg.is_null()
+            && (*cmsg).cmsg_level == libc::SOL_SOCKET
+            && (*cmsg).cmsg_type == libc::SCM_RIGHTS;
+        if tag[0] != HANDOFF_TAG || !has_fd {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "handoff message did not carry a descriptor",
+            ));
+        }
+
+        let fd = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+        Ok(File::from_raw_fd(fd))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn recv_flags() -> libc::c_int {
+    libc::MSG_CMSG_CLOEXEC
+}
+
+#[cfg(not(target_os = "linux"))]
+fn recv_flags() -> libc::c_int {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write};
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn try_hand_over_without_successor() {
+        let file_name = "/tmp/handoff-idle.pipe";
+        let socket_name = "/tmp/handoff-idle.sock";
+        create(file_name, None).expect("could not create fifo");
+        let pipe = open_read(file_name).expect("could not open fifo for reading");
+
+        let listener = Listener::bind(socket_name).expect("could not bind socket");
+        assert!(!listener.try_hand_over(&pipe).unwrap());
+
+        drop(listener);
+        assert!(!Path::new(socket_name).exists());
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn hand_over_keeps_buffered_data() {
+        let file_name = "/tmp/handoff.pipe";
+        let socket_name = "/tmp/handoff.sock";
+        create(file_name, None).expect("could not create fifo");
+        let pipe = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        writer
+            .write_all(b"before,")
+            .expect("could not write to fifo");
+
+        let listener = Listener::bind(socket_name).expect("could not bind socket");
+        let successor = thread::spawn(move || take_over(socket_name));
+        while !listener
+            .try_hand_over(&pipe)
+            .expect("could not hand over pipe")
+        {
+            thread::yield_now();
+        }
+        drop(pipe);
+
+        writer.write_all(b"after").expect("writer lost its reader");
+        let mut pipe = successor.join().unwrap().expect("could not take over pipe");
+        let mut actual = [0; 12];
+        pipe.read_exact(&mut actual)
+            .expect("could not read from fifo");
+        assert_eq!(&actual, b"before,after");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
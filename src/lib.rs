@@ -2,18 +2,106 @@
 extern crate errno;
 extern crate libc;
 
-use libc::{c_int, mkfifo, mode_t, EACCES, EEXIST, ENOENT};
+use libc::{c_int, mkfifo, mkfifoat, mode_t};
 use std::ffi::CString;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File};
 use std::io;
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
 use std::path::Path;
 
+#[cfg(all(feature = "acl", target_os = "linux"))]
+mod acl;
+mod audit;
+mod backpressure;
+mod batch;
+mod beneath;
+mod broadcast;
+mod clock;
+mod duplex;
+mod error;
 mod ext;
+mod fallback;
+mod follow;
+mod group;
+#[cfg(target_os = "linux")]
+mod handle;
+pub mod handoff;
+pub mod loadgen;
+mod lock;
+mod message;
+mod mode;
+mod mux;
+mod named;
+mod options;
+mod owner;
+mod poll;
+mod rate;
+mod reader;
+mod retry;
+#[cfg(all(feature = "selinux", target_os = "linux"))]
+mod selinux;
+mod sendfile;
+mod sigpipe;
+#[cfg(all(feature = "splice", target_os = "linux"))]
+mod splice;
+mod spool;
+mod stat;
+mod status;
+mod symlink;
+mod temp;
+mod writer;
+#[cfg(all(feature = "acl", target_os = "linux"))]
+pub use self::acl::*;
+pub use self::audit::*;
+pub use self::backpressure::*;
+pub use self::batch::*;
+pub use self::beneath::*;
+pub use self::broadcast::*;
+pub use self::clock::*;
+pub use self::duplex::*;
+pub use self::error::*;
 pub use self::ext::*;
+pub use self::fallback::*;
+pub use self::follow::*;
+pub use self::group::*;
+#[cfg(target_os = "linux")]
+pub use self::handle::*;
+pub use self::lock::{LockScope, RecordLock};
+pub use self::message::*;
+pub use self::mode::*;
+pub use self::mux::*;
+pub use self::named::*;
+pub use self::options::*;
+pub use self::owner::*;
+pub use self::rate::*;
+pub use self::reader::*;
+pub use self::retry::*;
+#[cfg(all(feature = "selinux", target_os = "linux"))]
+pub use self::selinux::*;
+pub use self::sendfile::*;
+pub use self::sigpipe::*;
+pub use self::spool::*;
+pub use self::stat::*;
+pub use self::status::*;
+pub use self::symlink::*;
+pub use self::temp::*;
+pub use self::writer::*;
+
+/// Selects which end of a named pipe should be opened.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// Open the pipe for reading, as `open_read` does.
+    Read,
+    /// Open the pipe for writing, as `open_write` does.
+    Write,
+}
 
 /// Creates a new named pipe at the path given as `path`.
 /// Pipe will be created with mode `mode` if given, else `0o644` will be used.
+/// The mode can be raw bits, a `Mode` or a `std::fs::Permissions`, and is
+/// narrowed by the process umask; use `create_exact` to avoid that.
 ///
 /// # Examples
 ///
@@ -22,7 +110,7 @@ pub use self::ext::*;
 /// ```
 /// # extern crate unix_named_pipe;
 /// # use std::fs;
-/// # let file_name = "/tmp/fifo.0";
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
 /// unix_named_pipe::create(file_name, None).expect("could not create fifo");
 /// # fs::remove_file(file_name).expect("could not remove fifo");
 /// ```
@@ -32,78 +120,336 @@ pub use self::ext::*;
 /// ```
 /// # extern crate unix_named_pipe;
 /// # use std::fs;
-/// # let file_name = "/tmp/fifo.1";
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
 /// unix_named_pipe::create(file_name, Some(0o740)).expect("could not create fifo");
 /// # fs::remove_file(file_name).unwrap();
 /// ```
-pub fn create<P: AsRef<Path>>(path: P, mode: Option<u32>) -> io::Result<()> {
-    let path = CString::new(path.as_ref().to_str().unwrap())?;
-    let mode = mode.unwrap_or(0o644);
-    let result: c_int = unsafe { mkfifo(path.as_ptr(), mode as mode_t) };
+///
+/// # Errors
+///
+/// - If a raw mode has bits set outside `0o777`, as a decimal `644` does,
+///   `Err(io::ErrorKind::InvalidInput)` will be returned.
+/// - If `path` contains a NUL byte, `Err(io::ErrorKind::InvalidInput)` will
+///   be returned, carrying `PipeError::InvalidPath`.
+/// - If `mkfifo` fails, the raw OS error is returned, so its code is still
+///   available from `io::Error::raw_os_error`.
+pub fn create<P: AsRef<Path>, M: IntoMode>(path: P, mode: M) -> io::Result<()> {
+    let mode = mode_bits(mode)?;
+    let result = make_fifo(path.as_ref(), mode);
+    audit::record(AuditAction::Create { mode }, path.as_ref(), &result);
 
-    let result: i32 = result.into();
-    if result == 0 {
-        return Ok(());
+    result
+}
+
+/// Creates a new named pipe at `path` like `create`, then sets its
+/// permissions to exactly `mode` (or `0o644`). Plain `create` leaves the mode
+/// narrowed by the process umask, so `0o666` usually ends up as `0o644`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::os::unix::fs::PermissionsExt;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// unix_named_pipe::create_exact(file_name, Some(0o666)).expect("could not create fifo");
+/// let mode = fs::metadata(file_name).unwrap().permissions().mode();
+/// assert_eq!(mode & 0o777, 0o666);
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - Any error returned by `create`.
+/// - If the permissions cannot be changed, the error from `chmod` is
+///   returned and the new pipe is removed again.
+pub fn create_exact<P: AsRef<Path>, M: IntoMode>(path: P, mode: M) -> io::Result<()> {
+    let path = path.as_ref();
+    let mode = mode_bits(mode)?;
+    create(path, Some(mode))?;
+
+    let result = fs::set_permissions(path, fs::Permissions::from_mode(mode));
+    audit::record(AuditAction::SetMode { mode }, path, &result);
+    if let Err(err) = result {
+        let _ = remove_if_fifo(path);
+        return Err(err);
     }
 
-    let error = errno::errno();
-    match error.0 {
-        EACCES => {
-            return Err(io::Error::new(
-                io::ErrorKind::PermissionDenied,
-                format!("could not open {:?}: {}", path, error),
-            ));
-        }
-        EEXIST => {
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                format!("could not open {:?}: {}", path, error),
-            ));
-        }
-        ENOENT => {
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("could not open {:?}: {}", path, error),
-            ));
+    Ok(())
+}
+
+/// Creates a new named pipe at `path` like `create`, first creating any
+/// missing parent directories as `fs::create_dir_all` would.
+/// Directories will be created with mode `dir_mode` if given, else `0o755`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # let root = unix_named_pipe::unique_path("fifo-dir");
+/// # let file_name = root.join("run/app.pipe");
+/// unix_named_pipe::create_all(&file_name, Some(0o660), Some(0o750))
+///     .expect("could not create fifo");
+/// # fs::remove_dir_all(&root).expect("could not remove fifo");
+/// ```
+pub fn create_all<P: AsRef<Path>, M: IntoMode>(
+    path: P,
+    mode: M,
+    dir_mode: Option<u32>,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(dir_mode.unwrap_or(0o755))
+            .create(parent)?;
+    }
+
+    create(path, mode)
+}
+
+/// Creates a new named pipe at `path`, relative to the directory open as
+/// `dir`, using `mkfifoat`. This lets a process holding only a directory
+/// descriptor create pipes inside it.
+/// Pipe will be created with mode `mode` if given, else `0o644` will be used.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # let dir_name = &unix_named_pipe::unique_path("fifo-dir");
+/// # fs::create_dir_all(dir_name).unwrap();
+/// let dir = fs::File::open(dir_name).expect("could not open directory");
+/// unix_named_pipe::create_at(&dir, "app.pipe", None).expect("could not create fifo");
+/// # fs::remove_dir_all(dir_name).expect("could not remove fifo");
+/// ```
+pub fn create_at<D: AsRawFd, P: AsRef<Path>, M: IntoMode>(
+    dir: &D,
+    path: P,
+    mode: M,
+) -> io::Result<()> {
+    let mode = mode_bits(mode)?;
+    let result = make_fifo_at(dir.as_raw_fd(), path.as_ref(), mode);
+    audit::record(AuditAction::Create { mode }, path.as_ref(), &result);
+
+    result
+}
+
+fn mode_bits<M: IntoMode>(mode: M) -> io::Result<u32> {
+    Ok(mode_or_default(mode)?.bits())
+}
+
+/// Validates `mode`, falling back to `0o644` only when no mode was given.
+fn mode_or_default<M: IntoMode>(mode: M) -> io::Result<Mode> {
+    Ok(mode
+        .into_mode()?
+        .unwrap_or_else(|| Mode::owner_rw().group_read().other_read()))
+}
+
+fn make_fifo(path: &Path, mode: u32) -> io::Result<()> {
+    let c_path = path_to_cstring(path)?;
+    let result: c_int = retry_interrupted(|| unsafe { mkfifo(c_path.as_ptr(), mode as mode_t) });
+
+    fifo_result(result)
+}
+
+fn make_fifo_at(dirfd: RawFd, path: &Path, mode: u32) -> io::Result<()> {
+    let c_path = path_to_cstring(path)?;
+    let result: c_int =
+        retry_interrupted(|| unsafe { mkfifoat(dirfd, c_path.as_ptr(), mode as mode_t) });
+
+    fifo_result(result)
+}
+
+/// Repeats a system call for as long as it fails with `EINTR`, so a signal
+/// arriving mid-call is never reported as an error. `call` returns `-1` on
+/// failure and leaves the reason in `errno`, which is kept for the caller.
+///
+/// Opens done through `std::fs::OpenOptions` are retried by std already.
+fn retry_interrupted<T, F>(mut call: F) -> T
+where
+    T: Copy + PartialEq + From<i8>,
+    F: FnMut() -> T,
+{
+    loop {
+        let result = call();
+        if result != T::from(-1) || errno::errno().0 != libc::EINTR {
+            return result;
         }
-        _ => {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("could not open {:?}: {}", path, error),
-            ));
+    }
+}
+
+fn path_to_cstring(path: &Path) -> io::Result<CString> {
+    check_path(path)?;
+
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// Refuses a path with an interior NUL byte, which the operating system
+/// would otherwise see truncated.
+fn check_path(path: &Path) -> io::Result<()> {
+    if path.as_os_str().as_bytes().contains(&0) {
+        return Err(PipeError::InvalidPath {
+            path: path.to_path_buf(),
         }
+        .into());
+    }
+
+    Ok(())
+}
+
+fn fifo_result(result: c_int) -> io::Result<()> {
+    if result == 0 {
+        return Ok(());
+    }
+
+    Err(io::Error::last_os_error())
+}
+
+/// Ensures a named pipe exists at `path`, creating it with mode `mode` (or
+/// `0o644`) if nothing is there yet. An existing FIFO is left untouched.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// unix_named_pipe::ensure_exists(file_name, None).expect("could not create fifo");
+/// unix_named_pipe::ensure_exists(file_name, None).expect("fifo should be reused");
+/// # fs::remove_file(file_name).expect("could not remove fifo");
+/// ```
+///
+/// # Errors
+///
+/// - If a file which is not a FIFO already exists at `path`,
+///   `Err(io::ErrorKind::InvalidInput)` will be returned, carrying
+///   `PipeError::NotAFifo`.
+/// - Any other error returned by `create`.
+pub fn ensure_exists<P: AsRef<Path>, M: IntoMode>(path: P, mode: M) -> io::Result<()> {
+    let path = path.as_ref();
+    match create(path, mode) {
+        Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+
+    let file_type = fs::metadata(path)?.file_type();
+    if file_type.is_fifo() {
+        return Ok(());
+    }
+
+    Err(error::not_a_fifo("create", Some(path), file_type))
+}
+
+/// What `create_or_reuse` does when a FIFO already exists at the path.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Existing {
+    /// Reuse the FIFO if its permissions grant nothing beyond the requested
+    /// mode, as is the case for a pipe created with that mode under a umask.
+    ReuseCompatible,
+    /// Reuse the FIFO whatever its permissions, changing them to exactly the
+    /// requested mode.
+    ReuseAndChmod,
+}
+
+/// Creates a named pipe at `path` like `create`, or reuses the FIFO which
+/// already exists there as described by `existing`. This is the usual
+/// startup of a server whose previous run left its pipe behind.
+/// Pipe will be created with mode `mode` if given, else `0o644` will be used.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::Existing;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// unix_named_pipe::create(file_name, Some(0o600)).expect("could not create fifo");
+/// unix_named_pipe::create_or_reuse(file_name, Some(0o660), Existing::ReuseCompatible)
+///     .expect("fifo should be reused");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - If a file which is not a FIFO already exists at `path`,
+///   `Err(io::ErrorKind::InvalidInput)` will be returned, carrying
+///   `PipeError::NotAFifo`.
+/// - With `Existing::ReuseCompatible`, if the existing FIFO grants
+///   permissions outside `mode`, `Err(io::ErrorKind::AlreadyExists)` will be
+///   returned, carrying `PipeError::ModeTooWide`, and the FIFO is left
+///   unchanged.
+/// - Any other error returned by `create` or `set_mode`.
+pub fn create_or_reuse<P: AsRef<Path>, M: IntoMode>(
+    path: P,
+    mode: M,
+    existing: Existing,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let mode = mode_or_default(mode)?;
+    match create(path, mode) {
+        Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+
+    let metadata = fs::metadata(path)?;
+    if !metadata.file_type().is_fifo() {
+        return Err(error::not_a_fifo(
+            "create",
+            Some(path),
+            metadata.file_type(),
+        ));
+    }
+
+    let current = metadata.permissions().mode() & 0o777;
+    match existing {
+        Existing::ReuseCompatible if current & !mode.bits() != 0 => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            PipeError::ModeTooWide {
+                path: path.to_path_buf(),
+                allowed: mode.bits(),
+                actual: current,
+            },
+        )),
+        Existing::ReuseCompatible => Ok(()),
+        Existing::ReuseAndChmod if current == mode.bits() => Ok(()),
+        Existing::ReuseAndChmod => set_mode(path, mode),
     }
 }
 
 /// Opens a named pipe for reading. The file is opened for non-blocking reads
-/// a la `libc`'s `O_NONBLOCK`.
+/// a la `libc`'s `O_NONBLOCK`, and is closed on `exec`.
+/// Use `PipeOptions` to open it with other flags.
 ///
 /// # Examples
 ///
 /// ```
 /// # extern crate unix_named_pipe;
 /// # use std::fs;
-/// # let file_name = "/tmp/fifo.2";
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
 /// # unix_named_pipe::create(file_name, None).unwrap();
 /// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
 /// # fs::remove_file(file_name).unwrap();
 /// ```
 pub fn open_read<P: AsRef<Path>>(path: P) -> io::Result<File> {
-    OpenOptions::new()
-        .read(true)
-        .custom_flags(libc::O_NONBLOCK)
-        .open(path)
+    PipeOptions::new(Direction::Read).open(path)
 }
 
 /// Opens a named pipe for writing. The file is opened for non-blocking writes
-/// a la `libc`'s `O_NONBLOCK`.
+/// a la `libc`'s `O_NONBLOCK`, and is closed on `exec`.
+/// Use `PipeOptions` to open it with other flags, including the `O_APPEND`
+/// which earlier versions set.
 ///
 /// # Examples
 ///
 /// ```
 /// # extern crate unix_named_pipe;
 /// # use std::fs;
-/// # let file_name = "/tmp/fifo.3";
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
 /// # unix_named_pipe::create(file_name, Some(0o777)).unwrap();;
 /// # let read = unix_named_pipe::open_read(file_name).unwrap();
 /// let file = unix_named_pipe::open_write(file_name).expect("could not open fifo for writing");
@@ -113,24 +459,354 @@ pub fn open_read<P: AsRef<Path>>(path: P) -> io::Result<File> {
 /// # Errors
 ///
 /// - If there is no pipe receiver configured when `open_write` is called,
-///   `Err(io::ErrorKind::Other)` will be returned with
-///   `code = 6, message = "Device not configured"`.
+///   the raw `ENXIO` error will be returned, which `PipeError::of` reports
+///   as `PipeError::NoReader`.
 pub fn open_write<P: AsRef<Path>>(path: P) -> io::Result<File> {
-    OpenOptions::new()
-        .write(true)
-        .append(true)
-        .custom_flags(libc::O_NONBLOCK)
-        .open(path)
+    PipeOptions::new(Direction::Write).open(path)
+}
+
+/// Opens a named pipe for reading like `open_read`, returning the bare
+/// descriptor for use with lower-level event loops.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::os::unix::io::AsRawFd;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let fd = unix_named_pipe::open_read_fd(file_name).expect("could not open fifo for reading");
+/// assert!(fd.as_raw_fd() >= 0);
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+pub fn open_read_fd<P: AsRef<Path>>(path: P) -> io::Result<OwnedFd> {
+    PipeOptions::new(Direction::Read).open_fd(path)
+}
+
+/// Opens a named pipe for writing like `open_write`, returning the bare
+/// descriptor for use with lower-level event loops.
+pub fn open_write_fd<P: AsRef<Path>>(path: P) -> io::Result<OwnedFd> {
+    PipeOptions::new(Direction::Write).open_fd(path)
+}
+
+/// Opens a named pipe for reading, relative to the directory open as `dir`,
+/// using `openat`. The file is opened for non-blocking reads like `open_read`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # let dir_name = &unix_named_pipe::unique_path("fifo-dir");
+/// # fs::create_dir_all(dir_name).unwrap();
+/// let dir = fs::File::open(dir_name).expect("could not open directory");
+/// unix_named_pipe::create_at(&dir, "app.pipe", None).expect("could not create fifo");
+/// let file = unix_named_pipe::open_read_at(&dir, "app.pipe")
+///     .expect("could not open fifo for reading");
+/// # fs::remove_dir_all(dir_name).expect("could not remove fifo");
+/// ```
+pub fn open_read_at<D: AsRawFd, P: AsRef<Path>>(dir: &D, path: P) -> io::Result<File> {
+    let result = open_at(dir.as_raw_fd(), path.as_ref(), libc::O_RDONLY);
+    audit::record(AuditAction::Open, path.as_ref(), &result);
+
+    result
+}
+
+/// Opens a named pipe for writing, relative to the directory open as `dir`,
+/// using `openat`. The file is opened for non-blocking writes like
+/// `open_write`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # let dir_name = &unix_named_pipe::unique_path("fifo-dir");
+/// # fs::create_dir_all(dir_name).unwrap();
+/// let dir = fs::File::open(dir_name).expect("could not open directory");
+/// # unix_named_pipe::create_at(&dir, "app.pipe", None).unwrap();
+/// # let read = unix_named_pipe::open_read_at(&dir, "app.pipe").unwrap();
+/// let file = unix_named_pipe::open_write_at(&dir, "app.pipe")
+///     .expect("could not open fifo for writing");
+/// # fs::remove_dir_all(dir_name).expect("could not remove fifo");
+/// ```
+///
+/// # Errors
+///
+/// - As with `open_write`, if there is no pipe receiver configured the raw
+///   `ENXIO` error will be returned, which `PipeError::of` reports as
+///   `PipeError::NoReader`.
+pub fn open_write_at<D: AsRawFd, P: AsRef<Path>>(dir: &D, path: P) -> io::Result<File> {
+    let result = open_at(dir.as_raw_fd(), path.as_ref(), libc::O_WRONLY);
+    audit::record(AuditAction::Open, path.as_ref(), &result);
+
+    result
+}
+
+fn open_at(dirfd: RawFd, path: &Path, flags: c_int) -> io::Result<File> {
+    open_at_exact(dirfd, path, flags | libc::O_NONBLOCK | libc::O_CLOEXEC)
+}
+
+/// Opens `path` relative to `dirfd` with exactly `flags`, without the
+/// non-blocking and close-on-exec defaults of `open_at`.
+fn open_at_exact(dirfd: RawFd, path: &Path, flags: c_int) -> io::Result<File> {
+    let c_path = path_to_cstring(path)?;
+    let fd = retry_interrupted(|| unsafe { libc::openat(dirfd, c_path.as_ptr(), flags) });
+    if fd == -1 {
+        return Err(error::open_error(io::Error::last_os_error(), path));
+    }
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Creates a new named pipe at `path` and immediately opens it in the given
+/// `direction`, returning the opened file.
+/// Pipe will be created with mode `mode` if given, else `0o644` will be used.
+///
+/// Between creating and opening the pipe another process could replace the
+/// path, so the device and inode of the opened file are compared against
+/// those of the freshly created FIFO before it is handed back.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::Direction;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// let file = unix_named_pipe::create_open(file_name, None, Direction::Read)
+///     .expect("could not create and open fifo");
+/// # fs::remove_file(file_name).expect("could not remove fifo");
+/// ```
+///
+/// # Errors
+///
+/// - Any error returned by `create`, `open_read` or `open_write`. If the
+///   open fails, for instance with `ENXIO` when opening for writing with no
+///   reader, the FIFO just created is removed again.
+/// - If the path no longer refers to the created FIFO once opened,
+///   `Err(io::ErrorKind::Other)` will be returned, carrying
+///   `PipeError::Replaced`.
+pub fn create_open<P: AsRef<Path>, M: IntoMode>(
+    path: P,
+    mode: M,
+    direction: Direction,
+) -> io::Result<File> {
+    create_and_check(path.as_ref(), mode, &PipeOptions::new(direction))
+}
+
+/// Creates a new named pipe at `path` and opens it in the given `direction`
+/// like `create_open`, for daemons which create pipes in directories other
+/// users can write to.
+///
+/// The device and inode of the new FIFO are recorded with `lstat` straight
+/// after `mkfifo`, and the pipe is then opened with `O_NOFOLLOW`, so a
+/// symbolic link swapped in at `path` fails the open and any other file is
+/// caught by comparing the opened descriptor against the recorded identity.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::Direction;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// let file = unix_named_pipe::create_verified(file_name, Some(0o600), Direction::Read)
+///     .expect("could not create and open fifo");
+/// # fs::remove_file(file_name).expect("could not remove fifo");
+/// ```
+///
+/// # Errors
+///
+/// - Any error returned by `create` or by opening the pipe, including the
+///   `ELOOP` error if `path` has been replaced by a symbolic link. If the
+///   open fails while `path` still names the new FIFO, the FIFO is removed.
+/// - If the path no longer refers to the created FIFO once opened,
+///   `Err(io::ErrorKind::Other)` will be returned, carrying
+///   `PipeError::Replaced`. The path is left alone, as it no longer belongs
+///   to this process.
+pub fn create_verified<P: AsRef<Path>, M: IntoMode>(
+    path: P,
+    mode: M,
+    direction: Direction,
+) -> io::Result<File> {
+    create_and_check(
+        path.as_ref(),
+        mode,
+        PipeOptions::new(direction).nofollow(true),
+    )
+}
+
+fn create_and_check<M: IntoMode>(path: &Path, mode: M, options: &PipeOptions) -> io::Result<File> {
+    create(path, mode)?;
+
+    let created = fs::symlink_metadata(path)?;
+    let file = match options.open(path) {
+        Ok(file) => file,
+        Err(err) => {
+            // Only unlink the path while it still names the FIFO made above.
+            if let Ok(current) = fs::symlink_metadata(path) {
+                if current.dev() == created.dev() && current.ino() == created.ino() {
+                    let _ = fs::remove_file(path);
+                }
+            }
+            return Err(err);
+        }
+    };
+    let opened = file.metadata()?;
+
+    let is_same_fifo = created.file_type().is_fifo()
+        && opened.file_type().is_fifo()
+        && created.dev() == opened.dev()
+        && created.ino() == opened.ino();
+    if !is_same_fifo {
+        return Err(PipeError::Replaced {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+
+    Ok(file)
+}
+
+/// Returns a wrapped boolean to designate if the file at `path` is a FIFO,
+/// following symbolic links. Unlike `FileFIFOExt::is_fifo`, the pipe is not
+/// opened, so a peer blocked in its own open is not released by the check.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// assert!(unix_named_pipe::path_is_fifo(file_name).unwrap());
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - Any error returned by `stat`, such as `Err(io::ErrorKind::NotFound)`
+///   if nothing exists at `path`.
+pub fn path_is_fifo<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    Ok(fs::metadata(path)?.file_type().is_fifo())
+}
+
+/// Returns a wrapped boolean to designate if `fd` refers to a FIFO, as
+/// reported by `fstat`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::os::unix::io::AsFd;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+/// assert!(unix_named_pipe::fd_is_fifo(file.as_fd()).unwrap());
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+pub fn fd_is_fifo(fd: BorrowedFd<'_>) -> io::Result<bool> {
+    fd.is_fifo()
+}
+
+/// Removes the named pipe at `path`, refusing to unlink anything which is
+/// not a FIFO. Symbolic links are not followed, so a link pointing at a
+/// FIFO is refused as well.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// unix_named_pipe::remove_if_fifo(file_name).expect("could not remove fifo");
+/// ```
+///
+/// # Errors
+///
+/// - If the file at `path` is not a FIFO, `Err(io::ErrorKind::InvalidInput)`
+///   will be returned and the file is left in place.
+/// - Any error returned by `lstat` or `unlink`.
+pub fn remove_if_fifo<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let result = unlink_fifo(path);
+    audit::record(AuditAction::Unlink, path, &result);
+
+    result
+}
+
+fn unlink_fifo(path: &Path) -> io::Result<()> {
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    if !file_type.is_fifo() {
+        return Err(error::not_a_fifo("remove", Some(path), file_type));
+    }
+
+    fs::remove_file(path)
+}
+
+/// Changes the permissions of the named pipe at `path` to exactly `mode`,
+/// refusing to touch anything which is not a FIFO. Symbolic links are not
+/// followed, so a link pointing at a FIFO is refused as well.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::Mode;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// unix_named_pipe::set_mode(file_name, Mode::owner_rw().group_read())
+///     .expect("could not change fifo mode");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - If the file at `path` is not a FIFO, `Err(io::ErrorKind::InvalidInput)`
+///   will be returned and the file is left unchanged.
+/// - Any error returned by `lstat` or `chmod`.
+pub fn set_mode<P: AsRef<Path>>(path: P, mode: Mode) -> io::Result<()> {
+    let path = path.as_ref();
+    let result = chmod_fifo(path, mode);
+    audit::record(AuditAction::SetMode { mode: mode.bits() }, path, &result);
+
+    result
+}
+
+fn chmod_fifo(path: &Path, mode: Mode) -> io::Result<()> {
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    if !file_type.is_fifo() {
+        return Err(error::not_a_fifo("change mode of", Some(path), file_type));
+    }
+
+    fs::set_permissions(path, mode.into())
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::bool_assert_comparison,
+    clippy::let_unit_value,
+    clippy::unused_io_amount
+)]
 mod tests {
     extern crate fs2;
 
     use super::*;
     use fs2::FileExt;
+    use std::ffi::OsStr;
     use std::fs;
     use std::io::{self, Error, ErrorKind, Read, Write};
+    use std::os::unix::io::AsFd;
+    use std::time::Duration;
 
     fn lock_active_test() -> io::Result<fs::File> {
         let file = File::create("/tmp/unix-named-pipe_tests.lock")?;
@@ -139,6 +815,137 @@ mod tests {
         Ok(file)
     }
 
+    #[test]
+    fn path_is_fifo_without_opening() {
+        let filename = "/tmp/pipe-predicate";
+        create(filename, None).expect("could not create pipe");
+        assert!(path_is_fifo(filename).unwrap());
+
+        // Opening the pipe to read would leave a reader for this open, and
+        // one to write would leave the hang-up of a writer for the reader.
+        let err = open_write(filename).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENXIO));
+        let reader = open_read(filename).expect("could not open pipe for reading");
+        assert!(path_is_fifo(filename).unwrap());
+        assert_eq!(
+            reader.poll_readable(Some(Duration::from_secs(0))).unwrap(),
+            Readiness::TimedOut
+        );
+        assert!(fd_is_fifo(reader.as_fd()).unwrap());
+
+        assert!(!path_is_fifo("/dev/null").unwrap());
+        fs::remove_file(filename).expect("could not remove test pipe");
+    }
+
+    #[test]
+    fn create_or_reuse_checks_mode() {
+        let filename = "/tmp/pipe-reuse";
+        create_exact(filename, Some(0o640)).expect("could not create pipe");
+
+        create_or_reuse(filename, Some(0o660), Existing::ReuseCompatible)
+            .expect("pipe should be reused");
+        let err = create_or_reuse(filename, Some(0o600), Existing::ReuseCompatible).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(
+            err.to_string(),
+            "expected fifo at /tmp/pipe-reuse to have mode at most 600, has 640"
+        );
+        match PipeError::of(&err) {
+            Some(PipeError::ModeTooWide { path, actual, .. }) => {
+                assert_eq!((path.as_path(), actual), (Path::new(filename), 0o640))
+            }
+            other => panic!("expected ModeTooWide, got {:?}", other),
+        }
+
+        create_or_reuse(filename, Some(0o600), Existing::ReuseAndChmod)
+            .expect("pipe should be reused");
+        let mode = fs::metadata(filename).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::remove_file(filename).expect("could not remove test pipe");
+        fs::write(filename, "").expect("could not write test file");
+        let err = create_or_reuse(filename, None, Existing::ReuseAndChmod).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        fs::remove_file(filename).expect("could not remove test file");
+    }
+
+    #[test]
+    fn nul_byte_is_invalid_path() {
+        let filename = OsStr::from_bytes(b"/tmp/pipe\0nul");
+        let errors = vec![
+            create(filename, None).unwrap_err(),
+            open_read(filename).unwrap_err(),
+            open_write(filename).unwrap_err(),
+            open_read_at(&File::open("/tmp").unwrap(), filename).unwrap_err(),
+        ];
+        for err in errors {
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            match PipeError::of(&err) {
+                Some(PipeError::InvalidPath { path }) => assert_eq!(path, Path::new(filename)),
+                other => panic!("expected InvalidPath, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn create_keeps_raw_os_error() {
+        let err = create("/notadir/raw-errno.pipe", None).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+        assert!(PipeError::of(&err).is_none());
+    }
+
+    #[test]
+    fn create_verified_opens_new_fifo() {
+        let filename = "/tmp/pipe-verified";
+        let file = create_verified(filename, Some(0o600), Direction::Read)
+            .expect("could not create and open pipe");
+        let opened = file.metadata().unwrap();
+        let created = fs::symlink_metadata(filename).unwrap();
+        assert_eq!((opened.dev(), opened.ino()), (created.dev(), created.ino()));
+
+        let err = create_verified(filename, None, Direction::Read).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        fs::remove_file(filename).expect("could not remove test pipe");
+    }
+
+    #[test]
+    fn retries_interrupted_calls() {
+        let mut calls = 0;
+        let result = retry_interrupted(|| {
+            calls += 1;
+            if calls < 3 {
+                errno::set_errno(errno::Errno(libc::EINTR));
+                return -1;
+            }
+            errno::set_errno(errno::Errno(libc::EACCES));
+            -1
+        });
+        assert_eq!((result, calls), (-1, 3));
+        assert_eq!(errno::errno().0, libc::EACCES);
+    }
+
+    #[test]
+    fn directories_are_not_fifos() {
+        let dirname = "/tmp/pipe-is-dir";
+        fs::create_dir_all(dirname).expect("could not create test dir");
+
+        let errors = vec![
+            open_write(dirname).unwrap_err(),
+            remove_if_fifo(dirname).unwrap_err(),
+            ensure_exists(dirname, None).unwrap_err(),
+        ];
+        for err in errors {
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            match PipeError::of(&err) {
+                Some(PipeError::NotAFifo { actual, .. }) => assert!(actual.is_dir()),
+                other => panic!("expected NotAFifo, got {:?}", other),
+            }
+        }
+
+        fs::remove_dir(dirname).expect("could not remove test dir");
+    }
+
     #[test]
     fn create_new_pipe() {
         let lock = lock_active_test().unwrap();
@@ -177,6 +984,143 @@ mod tests {
         assert_eq!(err.kind(), ErrorKind::NotFound);
     }
 
+    #[test]
+    fn create_all_makes_parents() {
+        let root = "/tmp/create-all";
+        let filename = "/tmp/create-all/a/b/pipe";
+        create_all(filename, None, Some(0o700)).expect("could not create pipe");
+
+        assert!(fs::metadata(filename).unwrap().file_type().is_fifo());
+        let dir_mode = fs::metadata("/tmp/create-all/a").unwrap().mode() & 0o777;
+        assert_eq!(dir_mode, 0o700);
+
+        fs::remove_dir_all(root).expect("could not remove test dirs");
+    }
+
+    #[test]
+    fn create_exact_sets_full_mode() {
+        let filename = "/tmp/create-exact.pipe";
+        create_exact(filename, Mode::owner_rw().group_write().other_write())
+            .expect("could not create pipe");
+
+        let mode = fs::metadata(filename).unwrap().mode() & 0o777;
+        assert_eq!(mode, 0o622);
+
+        fs::remove_file(filename).expect("could not remove test pipe");
+    }
+
+    #[test]
+    fn create_at_dirfd() {
+        let dirname = "/tmp/create-at";
+        fs::create_dir_all(dirname).expect("could not create test dir");
+        let dir = File::open(dirname).expect("could not open test dir");
+
+        create_at(&dir, "pipe", None).expect("could not create pipe");
+        let file_type = fs::metadata("/tmp/create-at/pipe").unwrap().file_type();
+        assert!(file_type.is_fifo());
+
+        let err = create_at(&dir, "pipe", None).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        fs::remove_dir_all(dirname).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn open_at_dirfd() {
+        let dirname = "/tmp/open-at";
+        fs::create_dir_all(dirname).expect("could not create test dir");
+        let dir = File::open(dirname).expect("could not open test dir");
+        create_at(&dir, "pipe", None).expect("could not create pipe");
+
+        let mut read_file = open_read_at(&dir, "pipe").expect("could not open pipe for reading");
+        let mut write_file = open_write_at(&dir, "pipe").expect("could not open pipe for writing");
+        assert!(write_file.is_nonblocking().unwrap());
+
+        write_file
+            .write_all(b"relative")
+            .expect("could not write test data to pipe");
+        let mut actual = [0; 8];
+        read_file
+            .read_exact(&mut actual)
+            .expect("could not read test data from pipe");
+        assert_eq!(&actual, b"relative");
+
+        fs::remove_dir_all(dirname).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn open_write_at_without_reader() {
+        let dirname = "/tmp/open-write-at";
+        fs::create_dir_all(dirname).expect("could not create test dir");
+        let dir = File::open(dirname).expect("could not open test dir");
+        create_at(&dir, "pipe", None).expect("could not create pipe");
+
+        let err = open_write_at(&dir, "pipe").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENXIO));
+        assert!(matches!(PipeError::of(&err), Some(PipeError::NoReader)));
+
+        fs::remove_dir_all(dirname).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn ensure_exists_creates_and_reuses() {
+        let filename = "/tmp/ensure.pipe";
+        ensure_exists(filename, None).expect("could not create pipe");
+        ensure_exists(filename, None).expect("could not reuse pipe");
+        assert!(fs::metadata(filename).unwrap().file_type().is_fifo());
+
+        fs::remove_file(filename).expect("could not remove test pipe");
+    }
+
+    #[test]
+    fn ensure_exists_rejects_regular_file() {
+        let filename = "/tmp/ensure.txt";
+        fs::write(filename, "").expect("could not write test file");
+
+        let err = ensure_exists(filename, None).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        match PipeError::of(&err) {
+            Some(PipeError::NotAFifo { actual, .. }) => assert!(actual.is_file()),
+            other => panic!("expected NotAFifo, got {:?}", other),
+        }
+
+        fs::remove_file(filename).expect("could not remove test file");
+    }
+
+    #[test]
+    fn remove_if_fifo_removes_pipe() {
+        let filename = "/tmp/remove.pipe";
+        create(filename, None).expect("could not create pipe");
+
+        remove_if_fifo(filename).expect("could not remove pipe");
+        assert!(fs::symlink_metadata(filename).is_err());
+    }
+
+    #[test]
+    fn remove_if_fifo_keeps_regular_file() {
+        let filename = "/tmp/remove.txt";
+        fs::write(filename, "").expect("could not write test file");
+
+        let err = remove_if_fifo(filename).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(fs::metadata(filename).unwrap().is_file());
+
+        fs::remove_file(filename).expect("could not remove test file");
+    }
+
+    #[test]
+    fn set_mode_refuses_regular_file() {
+        let filename = "/tmp/set-mode.txt";
+        fs::write(filename, "").expect("could not write test file");
+        fs::set_permissions(filename, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let err = set_mode(filename, Mode::owner_rw().other_write()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(fs::metadata(filename).unwrap().mode() & 0o777, 0o600);
+
+        fs::remove_file(filename).expect("could not remove test file");
+    }
+
     #[test]
     fn open_pipe_read() {
         let lock = lock_active_test().unwrap();
@@ -209,4 +1153,58 @@ mod tests {
         fs::remove_file(filename).expect("could not remove test file");
         lock.unlock().unwrap();
     }
+
+    #[test]
+    fn create_non_utf8_path() {
+        let filename = Path::new(OsStr::from_bytes(b"/tmp/pipe-\xff\xfe"));
+        create(filename, None).expect("could not create pipe");
+        assert!(fs::metadata(filename).unwrap().file_type().is_fifo());
+
+        let read_file = open_read(filename).expect("could not open pipe for reading");
+        assert!(read_file.is_fifo().unwrap());
+
+        remove_if_fifo(filename).expect("could not remove pipe");
+    }
+
+    #[test]
+    fn create_at_non_utf8_path() {
+        let dirname = "/tmp/create-at-non-utf8";
+        fs::create_dir_all(dirname).expect("could not create test dir");
+        let dir = File::open(dirname).expect("could not open test dir");
+
+        let filename = Path::new(OsStr::from_bytes(b"pipe-\xc3\x28"));
+        create_at(&dir, filename, None).expect("could not create pipe");
+        let read_file = open_read_at(&dir, filename).expect("could not open pipe for reading");
+        assert!(read_file.is_fifo().unwrap());
+
+        fs::remove_dir_all(dirname).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn create_open_read() {
+        let filename = "/tmp/create-open.pipe";
+        let file = create_open(filename, None, Direction::Read).expect("could not create pipe");
+        assert!(file.is_fifo().unwrap());
+
+        fs::remove_file(filename).expect("could not remove test pipe");
+    }
+
+    #[test]
+    fn create_open_write_without_reader_removes_fifo() {
+        let filename = "/tmp/create-open-write.pipe";
+        let err = create_open(filename, None, Direction::Write).unwrap_err();
+        assert!(error::is_no_reader(&err));
+        assert!(!Path::new(filename).exists());
+    }
+
+    #[test]
+    fn create_open_eexists() {
+        let filename = "/tmp/create-open.txt";
+        fs::write(filename, "").expect("could not write test file");
+
+        let err = create_open(filename, None, Direction::Read).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        fs::remove_file(filename).expect("could not remove test file");
+    }
 }
@@ -0,0 +1,303 @@
+//! Provides a synthetic traffic generator for soak-testing pipe consumers.
+//!
+//! ```
+//! # extern crate unix_named_pipe;
+//! # use std::fs;
+//! use std::time::Duration;
+//! use unix_named_pipe::loadgen::{self, Profile};
+//!
+//! # let file_name = &unix_named_pipe::unique_path("fifo");
+//! # unix_named_pipe::create(file_name, None).unwrap();
+//! let reader = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+//! let profile = Profile::new()
+//!     .message_size(8, 32)
+//!     .rate(50.0)
+//!     .duration(Duration::from_millis(50));
+//! let report = loadgen::run(file_name, &profile).expect("could not generate load");
+//! assert!(report.messages > 0);
+//! # fs::remove_file(file_name).unwrap();
+//! ```
+
+use super::{open_write, Clock, SystemClock};
+use std::cmp;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Describes the traffic `run` should produce.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    writers: usize,
+    min_size: usize,
+    max_size: usize,
+    rate: f64,
+    burst: usize,
+    duration: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl Profile {
+    /// Creates a profile of one writer sending 64 byte messages at 100
+    /// messages per second, one at a time, for one second.
+    pub fn new() -> Profile {
+        Profile {
+            writers: 1,
+            min_size: 64,
+            max_size: 64,
+            rate: 100.0,
+            burst: 1,
+            duration: Duration::from_secs(1),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the number of concurrent writers, each with its own descriptor.
+    pub fn writers(mut self, writers: usize) -> Profile {
+        self.writers = cmp::max(writers, 1);
+        self
+    }
+
+    /// Sets the range of message sizes in bytes. Sizes are picked uniformly
+    /// between `min` and `max`, inclusive. Every message ends in a newline.
+    pub fn message_size(mut self, min: usize, max: usize) -> Profile {
+        self.min_size = cmp::max(min, 1);
+        self.max_size = cmp::max(max, self.min_size);
+        self
+    }
+
+    /// Sets the average number of messages per second sent by each writer.
+    pub fn rate(mut self, messages_per_sec: f64) -> Profile {
+        self.rate = messages_per_sec;
+        self
+    }
+
+    /// Sets how many messages are sent back to back on every tick. The tick
+    /// interval grows with the burst size so the average rate is unchanged.
+    pub fn burst(mut self, messages: usize) -> Profile {
+        self.burst = cmp::max(messages, 1);
+        self
+    }
+
+    /// Sets how long traffic is generated for.
+    pub fn duration(mut self, duration: Duration) -> Profile {
+        self.duration = duration;
+        self
+    }
+
+    /// Paces the writers with `clock` instead of the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Profile {
+        self.clock = clock;
+        self
+    }
+
+    fn tick(&self) -> Duration {
+        if self.rate <= 0.0 {
+            return self.duration;
+        }
+
+        Duration::from_secs_f64(self.burst as f64 / self.rate)
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile::new()
+    }
+}
+
+/// Totals gathered while generating traffic, summed over all writers.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Report {
+    /// Messages written in full.
+    pub messages: u64,
+    /// Bytes written.
+    pub bytes: u64,
+    /// Writes which failed with `WouldBlock` because the pipe was full.
+    pub would_block: u64,
+}
+
+/// Writes synthetic traffic described by `profile` into the pipe at `path`
+/// and returns what was sent. A reader must already be attached.
+///
+/// # Errors
+///
+/// - Any error returned by `open_write`, or by a write other than
+///   `WouldBlock`. All writers are stopped on the first error.
+pub fn run<P: AsRef<Path>>(path: P, profile: &Profile) -> io::Result<Report> {
+    let mut pipes = Vec::with_capacity(profile.writers);
+    for _ in 0..profile.writers {
+        pipes.push(open_write(&path)?);
+    }
+
+    let deadline = profile.clock.now() + profile.duration;
+    let handles: Vec<_> = pipes
+        .into_iter()
+        .enumerate()
+        .map(|(index, pipe)| {
+            let profile = profile.clone();
+            thread::spawn(move || generate(pipe, &profile, index as u64 + 1, deadline))
+        })
+        .collect();
+
+    let mut report = Report::default();
+    let mut first_error = None;
+    for handle in handles {
+        match handle.join().expect("load generator thread panicked") {
+            Ok(partial) => {
+                report.messages += partial.messages;
+                report.bytes += partial.bytes;
+                report.would_block += partial.would_block;
+            }
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    match first_error {
+        Some(err) => Err(err),
+        None => Ok(report),
+    }
+}
+
+fn generate<W: Write>(
+    mut pipe: W,
+    profile: &Profile,
+    seed: u64,
+    deadline: Instant,
+) -> io::Result<Report> {
+    let mut report = Report::default();
+    let mut rng = XorShift(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+    let mut message = vec![b'x'; profile.max_size];
+    let tick = profile.tick();
+    let clock = &profile.clock;
+
+    while clock.now() < deadline {
+        let tick_started = clock.now();
+        for _ in 0..profile.burst {
+            let spread = (profile.max_size - profile.min_size + 1) as u64;
+            let size = profile.min_size + (rng.next() % spread) as usize;
+            message[size - 1] = b'\n';
+            write_message(&mut pipe, &message[..size], &mut report, clock, deadline)?;
+            message[size - 1] = b'x';
+        }
+
+        let now = clock.now();
+        let elapsed = now - tick_started;
+        if elapsed < tick {
+            clock.sleep(cmp::min(
+                tick - elapsed,
+                deadline.saturating_duration_since(now),
+            ));
+        }
+    }
+
+    Ok(report)
+}
+
+fn write_message<W: Write>(
+    pipe: &mut W,
+    mut message: &[u8],
+    report: &mut Report,
+    clock: &Arc<dyn Clock>,
+    deadline: Instant,
+) -> io::Result<()> {
+    while !message.is_empty() {
+        match pipe.write(message) {
+            Ok(count) => {
+                report.bytes += count as u64;
+                message = &message[count..];
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                report.would_block += 1;
+                if clock.now() >= deadline {
+                    return Ok(());
+                }
+                clock.sleep(Duration::from_millis(1));
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    report.messages += 1;
+    Ok(())
+}
+
+/// Small deterministic generator for message sizes, so the library does not
+/// need a dependency on `rand`.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, MockClock};
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn run_reports_all_bytes_written() {
+        let file_name = "/tmp/loadgen.pipe";
+        create(file_name, None).expect("could not create fifo");
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+
+        let profile = Profile::new()
+            .writers(2)
+            .message_size(4, 16)
+            .rate(200.0)
+            .burst(4)
+            .duration(Duration::from_millis(100));
+        let report = run(file_name, &profile).expect("could not generate load");
+        assert!(report.messages > 0);
+
+        let mut received = Vec::new();
+        reader
+            .read_to_end(&mut received)
+            .expect("could not read generated load");
+        assert_eq!(received.len() as u64, report.bytes);
+        let lines = received.iter().filter(|&&b| b == b'\n').count();
+        assert_eq!(lines as u64, report.messages);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn run_with_mock_clock_is_deterministic() {
+        let file_name = "/tmp/loadgen-clock.pipe";
+        create(file_name, None).expect("could not create fifo");
+        let _reader = open_read(file_name).expect("could not open fifo for reading");
+
+        let profile = Profile::new()
+            .message_size(8, 8)
+            .rate(10.0)
+            .duration(Duration::from_secs(10))
+            .clock(Arc::new(MockClock::new()));
+        let report = run(file_name, &profile).expect("could not generate load");
+        assert_eq!(report.messages, 100);
+        assert_eq!(report.bytes, 800);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn run_requires_reader() {
+        let file_name = "/tmp/loadgen-noreader.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let profile = Profile::new().duration(Duration::from_millis(10));
+        assert!(run(file_name, &profile).is_err());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
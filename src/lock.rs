@@ -0,0 +1,224 @@
+//! Provides the advisory locks behind `FileFIFOExt::flock_exclusive` and
+//! `FileFIFOExt::lock_record`, so cooperating processes can agree on a
+//! single reader or writer for a shared FIFO.
+
+use super::{Clock, PipeError};
+use libc::{c_int, EWOULDBLOCK};
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+use std::time::Duration;
+
+/// How long `lock_record` sleeps between attempts when it has a deadline.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The kind of `fcntl` record lock to take.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecordLock {
+    /// A read lock, which any number of holders may share. The descriptor
+    /// must be open for reading.
+    Shared,
+    /// A write lock, which keeps out every other lock. The descriptor must be
+    /// open for writing.
+    Exclusive,
+}
+
+/// Who owns an `fcntl` record lock.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LockScope {
+    /// A classic POSIX lock, taken with `F_SETLK`, which belongs to the
+    /// process. It is released when the process closes any descriptor for
+    /// the FIFO, and is not inherited by children. This is the kind of lock
+    /// which works over NFS.
+    Process,
+    /// An open file description lock, taken with `F_OFD_SETLK`, which
+    /// belongs to the open file like a `flock` lock does. Only available on
+    /// Linux.
+    OpenFile,
+}
+
+/// Applies the `flock` operation `operation` to `fd`, retrying interrupted
+/// calls. With `LOCK_NB`, returns `false` instead of waiting if the lock is
+/// held elsewhere.
+pub(crate) fn flock(fd: BorrowedFd<'_>, operation: c_int) -> io::Result<bool> {
+    loop {
+        if unsafe { libc::flock(fd.as_raw_fd(), operation) } == 0 {
+            return Ok(true);
+        }
+
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) => {}
+            Some(EWOULDBLOCK) => return Ok(false),
+            _ => return Err(err),
+        }
+    }
+}
+
+/// Takes `lock` on all of `fd` with `fcntl`, or releases it if `lock` is
+/// `None`, returning `false` if another owner holds a conflicting lock.
+pub(crate) fn try_lock_record(
+    fd: BorrowedFd<'_>,
+    lock: Option<RecordLock>,
+    scope: LockScope,
+) -> io::Result<bool> {
+    let (command, _) = lock_commands(scope)?;
+    set_lock(fd, lock, command)
+}
+
+/// Takes `lock` on all of `fd`, waiting for at most `timeout` for it as
+/// measured by `clock`, or forever with `F_SETLKW` if it is `None`.
+pub(crate) fn lock_record(
+    fd: BorrowedFd<'_>,
+    lock: RecordLock,
+    scope: LockScope,
+    timeout: Option<Duration>,
+    clock: &dyn Clock,
+) -> io::Result<()> {
+    let (command, wait_command) = lock_commands(scope)?;
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return set_lock(fd, Some(lock), wait_command).map(drop),
+    };
+
+    let deadline = clock.now() + timeout;
+    loop {
+        if set_lock(fd, Some(lock), command)? {
+            return Ok(());
+        }
+
+        let left = deadline.saturating_duration_since(clock.now());
+        if left == Duration::from_secs(0) {
+            return Err(PipeError::TimedOut {
+                path: None,
+                timeout,
+            }
+            .into());
+        }
+        clock.sleep(left.min(RETRY_INTERVAL));
+    }
+}
+
+fn set_lock(fd: BorrowedFd<'_>, lock: Option<RecordLock>, command: c_int) -> io::Result<bool> {
+    let mut record: libc::flock = unsafe { mem::zeroed() };
+    record.l_type = match lock {
+        Some(RecordLock::Shared) => libc::F_RDLCK,
+        Some(RecordLock::Exclusive) => libc::F_WRLCK,
+        None => libc::F_UNLCK,
+    } as _;
+    record.l_whence = libc::SEEK_SET as _;
+
+    loop {
+        if unsafe { libc::fcntl(fd.as_raw_fd(), command, &record) } == 0 {
+            return Ok(true);
+        }
+
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) => {}
+            Some(libc::EACCES) | Some(libc::EAGAIN) => return Ok(false),
+            _ => return Err(err),
+        }
+    }
+}
+
+/// Returns the `fcntl` commands which set a lock of `scope`, without and
+/// with waiting.
+fn lock_commands(scope: LockScope) -> io::Result<(c_int, c_int)> {
+    match scope {
+        LockScope::Process => Ok((libc::F_SETLK, libc::F_SETLKW)),
+        #[cfg(target_os = "linux")]
+        LockScope::OpenFile => Ok((libc::F_OFD_SETLK, libc::F_OFD_SETLKW)),
+        #[cfg(not(target_os = "linux"))]
+        LockScope::OpenFile => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "open file description locks are only available on Linux",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write, FileFIFOExt, MockClock};
+    use super::*;
+    use std::fs;
+    use std::time::Instant;
+
+    #[test]
+    fn elects_one_reader() {
+        let file_name = "/tmp/flock.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let first = open_read(file_name).expect("could not open fifo for reading");
+        let second = open_read(file_name).expect("could not open fifo for reading");
+        assert!(first.try_flock_exclusive().unwrap());
+        assert!(!second.try_flock_exclusive().unwrap());
+        assert!(!second.try_flock_shared().unwrap());
+
+        first.flock_unlock().unwrap();
+        assert!(second.try_flock_shared().unwrap());
+        assert!(first.try_flock_shared().unwrap());
+        assert!(!first.try_flock_exclusive().unwrap());
+
+        drop(second);
+        first.flock_exclusive().unwrap();
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn record_locks_between_open_files() {
+        let file_name = "/tmp/record-lock.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let first = open_write(file_name).expect("could not open fifo for writing");
+        let second = open_write(file_name).expect("could not open fifo for writing");
+        assert!(first
+            .try_lock_record(RecordLock::Exclusive, LockScope::OpenFile)
+            .unwrap());
+        assert!(!second
+            .try_lock_record(RecordLock::Exclusive, LockScope::OpenFile)
+            .unwrap());
+        assert!(!reader
+            .try_lock_record(RecordLock::Shared, LockScope::OpenFile)
+            .unwrap());
+
+        let start = Instant::now();
+        let err = second
+            .lock_record(
+                RecordLock::Exclusive,
+                LockScope::OpenFile,
+                Some(Duration::from_millis(30)),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        let clock = MockClock::new();
+        let start = clock.now();
+        let err = second
+            .lock_record_with_clock(
+                RecordLock::Exclusive,
+                LockScope::OpenFile,
+                Some(Duration::from_secs(1)),
+                &clock,
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+
+        first.unlock_record(LockScope::OpenFile).unwrap();
+        second
+            .lock_record(RecordLock::Exclusive, LockScope::OpenFile, None)
+            .unwrap();
+
+        let err = reader
+            .try_lock_record(RecordLock::Exclusive, LockScope::Process)
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
@@ -0,0 +1,348 @@
+//! Provides `Messages`, which splits the data read from a pipe into framed
+//! messages, waiting for each one to arrive in full.
+
+use super::{FifoBufReader, FileFIFOExt};
+use std::io::{self, BufRead, Read};
+use std::mem;
+use std::os::unix::io::{AsFd, BorrowedFd};
+
+/// The size of the header of a `Framing::LengthPrefixed` message.
+const HEADER_SIZE: usize = 4;
+
+/// How the messages written to a pipe are told apart.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Framing {
+    /// Every message ends with the given byte, which is not part of the
+    /// message. An unterminated last message is returned as it is.
+    Delimited(u8),
+    /// Every message is exactly this many bytes long.
+    FixedSize(usize),
+    /// Every message starts with its length as a big-endian `u32`, which is
+    /// not part of the message, as written by `FileFIFOExt::write_vectored_atomic`
+    /// in its example.
+    LengthPrefixed,
+}
+
+/// What reading towards the next message led to.
+enum Step {
+    Message(Vec<u8>),
+    Pending,
+    End,
+}
+
+/// An iterator over the framed messages of a pipe, made by
+/// `FifoBufReader::messages`.
+///
+/// `next` sleeps in `poll` until a whole message has arrived, however many
+/// reads that takes, and the iterator ends once every writer has hung up.
+/// A message cut short by the hang-up ends the iterator with
+/// `io::ErrorKind::UnexpectedEof`, unless it is delimited.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::Write;
+/// use unix_named_pipe::{FifoBufReader, Framing};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+/// let mut writer = unix_named_pipe::open_write(file_name).unwrap();
+/// writer.write_all(b"first\0second\0").unwrap();
+/// drop(writer);
+///
+/// let reader = FifoBufReader::new(file).unwrap();
+/// for message in reader.messages(Framing::Delimited(0)) {
+///     println!("got {:?}", message.expect("could not read from fifo"));
+/// }
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Messages<R> {
+    reader: FifoBufReader<R>,
+    framing: Framing,
+    partial: Vec<u8>,
+}
+
+impl<R: Read + AsFd> Messages<R> {
+    pub(crate) fn new(reader: FifoBufReader<R>, framing: Framing) -> Messages<R> {
+        assert!(
+            framing != Framing::FixedSize(0),
+            "fixed size messages cannot be empty"
+        );
+
+        Messages {
+            reader,
+            framing,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Returns how the messages are told apart.
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Returns the wrapped reader.
+    pub fn get_ref(&self) -> &FifoBufReader<R> {
+        &self.reader
+    }
+
+    /// Returns the wrapped reader mutably.
+    pub fn get_mut(&mut self) -> &mut FifoBufReader<R> {
+        &mut self.reader
+    }
+
+    /// Consumes the iterator, returning the wrapped reader. A partly read
+    /// message is lost.
+    pub fn into_inner(self) -> FifoBufReader<R> {
+        self.reader
+    }
+
+    /// Returns the next message if it has arrived in full, without waiting,
+    /// in the manner of `std::sync::mpsc::Receiver::try_recv`.
+    ///
+    /// `Ok(None)` is returned both while the message is incomplete and once
+    /// every writer has hung up, as a new writer may still come along.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use unix_named_pipe::{FifoBufReader, Framing};
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+    /// let mut messages = FifoBufReader::new(file).unwrap().messages(Framing::Delimited(b'\n'));
+    /// let mut writer = unix_named_pipe::open_write(file_name).unwrap();
+    ///
+    /// writer.write_all(b"tick").unwrap();
+    /// assert_eq!(messages.try_read_message().unwrap(), None);
+    /// writer.write_all(b"\n").unwrap();
+    /// assert_eq!(messages.try_read_message().unwrap(), Some(b"tick".to_vec()));
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the writers hang up in the middle of a message which is not
+    ///   delimited, `Err(io::ErrorKind::UnexpectedEof)` will be returned.
+    /// - Any other error returned by `read`.
+    pub fn try_read_message(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.step()? {
+            Step::Message(message) => Ok(Some(message)),
+            Step::Pending | Step::End => Ok(None),
+        }
+    }
+
+    /// Reads whatever has arrived, stopping at the end of the next message.
+    fn step(&mut self) -> io::Result<Step> {
+        loop {
+            if let Some(len) = self.complete_len() {
+                let mut message = mem::take(&mut self.partial);
+                if let Framing::LengthPrefixed = self.framing {
+                    message.drain(..HEADER_SIZE);
+                }
+                message.truncate(len);
+                return Ok(Step::Message(message));
+            }
+
+            let wanted = self.wanted();
+            let buf = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(Step::Pending)
+                }
+                Err(err) => return Err(err),
+            };
+
+            if buf.is_empty() {
+                return match self.framing {
+                    _ if self.partial.is_empty() => Ok(Step::End),
+                    Framing::Delimited(_) => Ok(Step::Message(mem::take(&mut self.partial))),
+                    _ => {
+                        let len = mem::take(&mut self.partial).len();
+                        Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            format!("writers hung up {} bytes into a message", len),
+                        ))
+                    }
+                };
+            }
+
+            let used = match self.framing {
+                Framing::Delimited(delimiter) => buf
+                    .iter()
+                    .position(|&byte| byte == delimiter)
+                    .map_or(buf.len(), |end| end + 1),
+                _ => buf.len().min(wanted),
+            };
+            self.partial.extend_from_slice(&buf[..used]);
+            self.reader.consume(used);
+        }
+    }
+
+    /// Returns how many bytes the current message needs in all, including
+    /// its header, once that is known.
+    fn frame_len(&self) -> Option<usize> {
+        match self.framing {
+            Framing::Delimited(_) => None,
+            Framing::FixedSize(size) => Some(size),
+            Framing::LengthPrefixed if self.partial.len() < HEADER_SIZE => None,
+            Framing::LengthPrefixed => {
+                let mut header = [0; HEADER_SIZE];
+                header.copy_from_slice(&self.partial[..HEADER_SIZE]);
+                Some(HEADER_SIZE + u32::from_be_bytes(header) as usize)
+            }
+        }
+    }
+
+    /// Returns how many more bytes to read at most, stopping at the end of
+    /// the header if the length of the message is not known yet.
+    fn wanted(&self) -> usize {
+        match self.framing {
+            Framing::Delimited(_) => usize::MAX,
+            _ => self.frame_len().unwrap_or(HEADER_SIZE) - self.partial.len(),
+        }
+    }
+
+    /// Returns the length of the message without its framing, if the
+    /// message is complete.
+    fn complete_len(&self) -> Option<usize> {
+        match self.framing {
+            Framing::Delimited(delimiter) => match self.partial.last() {
+                Some(&last) if last == delimiter => Some(self.partial.len() - 1),
+                _ => None,
+            },
+            Framing::FixedSize(size) if self.partial.len() == size => Some(size),
+            Framing::LengthPrefixed => match self.frame_len() {
+                Some(len) if self.partial.len() == len => Some(len - HEADER_SIZE),
+                _ => None,
+            },
+            Framing::FixedSize(_) => None,
+        }
+    }
+}
+
+impl<R: Read + AsFd> Iterator for Messages<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        loop {
+            match self.step() {
+                Ok(Step::Message(message)) => return Some(Ok(message)),
+                Ok(Step::End) => return None,
+                Ok(Step::Pending) => {}
+                Err(err) => return Some(Err(err)),
+            }
+
+            // A hang-up is seen by the next read, which returns `Ok(0)`.
+            if let Err(err) = self.reader.poll_readable(None) {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+impl<R: AsFd> AsFd for Messages<R> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.reader.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write};
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn waits_for_whole_messages() {
+        let file_name = "/tmp/messages.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        let feeder = thread::spawn(move || {
+            for chunk in [&b"\0\0"[..], b"\0\x05hel", b"lo\0\0\0\0", b"\0\0\0\x09cut"] {
+                writer.write_all(chunk).unwrap();
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+
+        let reader = FifoBufReader::with_capacity(4, file);
+        let mut messages = reader.messages(Framing::LengthPrefixed);
+        assert_eq!(messages.next().unwrap().unwrap(), b"hello");
+        assert_eq!(messages.next().unwrap().unwrap(), b"");
+        let err = messages.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(messages.next().is_none());
+        feeder.join().unwrap();
+
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        writer.write_all(b"one\ntwo\nthr").unwrap();
+        drop(writer);
+        let reader = messages.into_inner();
+        let lines: Vec<Vec<u8>> = reader
+            .messages(Framing::Delimited(b'\n'))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines, [&b"one"[..], b"two", b"thr"]);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn fixed_size_messages() {
+        let file_name = "/tmp/messages-fixed.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        writer.write_all(b"aabbc").unwrap();
+        drop(writer);
+
+        let mut messages = FifoBufReader::new(file)
+            .unwrap()
+            .messages(Framing::FixedSize(2));
+        assert_eq!(messages.next().unwrap().unwrap(), b"aa");
+        assert_eq!(messages.next().unwrap().unwrap(), b"bb");
+        assert!(messages.next().unwrap().is_err());
+        assert!(messages.next().is_none());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn try_read_message_never_waits() {
+        let file_name = "/tmp/messages-try.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let mut messages = FifoBufReader::new(file)
+            .unwrap()
+            .messages(Framing::FixedSize(3));
+        assert_eq!(messages.try_read_message().unwrap(), None);
+
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        writer.write_all(b"abcd").unwrap();
+        assert_eq!(messages.try_read_message().unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(messages.try_read_message().unwrap(), None);
+        writer.write_all(b"ef").unwrap();
+        assert_eq!(messages.try_read_message().unwrap(), Some(b"def".to_vec()));
+
+        drop(writer);
+        assert_eq!(messages.try_read_message().unwrap(), None);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
@@ -0,0 +1,196 @@
+//! Provides `Mode`, a typed set of permission bits for new pipes, so modes
+//! can be spelled out instead of written as easily mistyped octal numbers.
+
+use std::fs::Permissions;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+
+/// The permission bits a named pipe is created with.
+///
+/// The execute bits have no meaning for a FIFO, so only read and write
+/// permissions can be built up.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::Mode;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// let mode = Mode::owner_rw().group_read();
+/// assert_eq!(mode.bits(), 0o640);
+/// unix_named_pipe::create(file_name, mode).expect("could not create fifo");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Mode {
+    bits: u32,
+}
+
+impl Mode {
+    /// A mode with no permissions at all.
+    pub fn empty() -> Mode {
+        Mode { bits: 0 }
+    }
+
+    /// A mode which lets only the owner read and write, `0o600`.
+    pub fn owner_rw() -> Mode {
+        Mode::empty().owner_read().owner_write()
+    }
+
+    /// Converts raw permission bits into a mode. Returns `None` if `bits` has
+    /// anything set outside `0o777`, which is what a decimal `644` looks like.
+    pub fn from_bits(bits: u32) -> Option<Mode> {
+        if bits & !0o777 != 0 {
+            return None;
+        }
+
+        Some(Mode { bits })
+    }
+
+    /// Returns the raw permission bits of this mode.
+    pub fn bits(self) -> u32 {
+        self.bits
+    }
+
+    /// Adds read permission for the owner.
+    pub fn owner_read(self) -> Mode {
+        self.with(0o400)
+    }
+
+    /// Adds write permission for the owner.
+    pub fn owner_write(self) -> Mode {
+        self.with(0o200)
+    }
+
+    /// Adds read permission for the group.
+    pub fn group_read(self) -> Mode {
+        self.with(0o040)
+    }
+
+    /// Adds write permission for the group.
+    pub fn group_write(self) -> Mode {
+        self.with(0o020)
+    }
+
+    /// Adds read permission for everyone else.
+    pub fn other_read(self) -> Mode {
+        self.with(0o004)
+    }
+
+    /// Adds write permission for everyone else.
+    pub fn other_write(self) -> Mode {
+        self.with(0o002)
+    }
+
+    fn with(self, bits: u32) -> Mode {
+        Mode {
+            bits: self.bits | bits,
+        }
+    }
+}
+
+impl From<Mode> for u32 {
+    fn from(mode: Mode) -> u32 {
+        mode.bits
+    }
+}
+
+impl From<Mode> for Permissions {
+    fn from(mode: Mode) -> Permissions {
+        Permissions::from_mode(mode.bits)
+    }
+}
+
+/// Anything which can be passed as the mode of a new pipe: a `Mode`, a
+/// `std::fs::Permissions`, or an `Option<u32>` of raw bits where `None`
+/// picks the default.
+pub trait IntoMode {
+    /// Converts into a mode, or `None` for the default.
+    ///
+    /// # Errors
+    ///
+    /// - If raw bits have anything set outside `0o777`,
+    ///   `Err(io::ErrorKind::InvalidInput)` will be returned.
+    fn into_mode(self) -> io::Result<Option<Mode>>;
+}
+
+impl IntoMode for Mode {
+    fn into_mode(self) -> io::Result<Option<Mode>> {
+        Ok(Some(self))
+    }
+}
+
+impl IntoMode for Permissions {
+    fn into_mode(self) -> io::Result<Option<Mode>> {
+        // Permissions read back from a file carry its type bits as well.
+        Ok(Some(Mode {
+            bits: self.mode() & 0o777,
+        }))
+    }
+}
+
+impl IntoMode for Option<u32> {
+    fn into_mode(self) -> io::Result<Option<Mode>> {
+        let bits = match self {
+            Some(bits) => bits,
+            None => return Ok(None),
+        };
+
+        match Mode::from_bits(bits) {
+            Some(mode) => Ok(Some(mode)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid fifo mode {} ({:#o}): only permission bits are allowed, \
+                     was it meant to be written in octal?",
+                    bits, bits
+                ),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::create;
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn builds_modes() {
+        assert_eq!(Mode::owner_rw().bits(), 0o600);
+        assert_eq!(Mode::owner_rw().group_read().other_read().bits(), 0o644);
+        assert_eq!(Mode::empty().group_write().other_write().bits(), 0o022);
+        assert_eq!(
+            Mode::from_bits(0o660),
+            Some(Mode::owner_rw().group_read().group_write())
+        );
+        assert_eq!(u32::from(Mode::owner_rw()), 0o600);
+    }
+
+    #[test]
+    fn rejects_decimal_mode() {
+        assert_eq!(Mode::from_bits(644), None);
+        assert_eq!(None.into_mode().unwrap(), None);
+        assert!(Some(0o644).into_mode().is_ok());
+
+        let file_name = "/tmp/mode-decimal.pipe";
+        let err = create(file_name, Some(644)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(fs::symlink_metadata(file_name).is_err());
+    }
+
+    #[test]
+    fn create_with_permissions() {
+        let file_name = "/tmp/mode-permissions.pipe";
+        let permissions = Permissions::from_mode(0o10600);
+        create(file_name, permissions).expect("could not create fifo");
+
+        let mode = fs::metadata(file_name).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
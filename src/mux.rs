@@ -0,0 +1,796 @@
+//! Provides `Mux`, which lets several logical streams share one named pipe
+//! by tagging each frame written to it with a channel id, and `Demux`, which
+//! hands the messages read from such a pipe to each channel's receiver.
+
+use super::{Backpressure, FifoBufReader, FileFIFOExt, PipeError, Readiness, PIPE_BUF};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, IoSlice, Read, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// How many bytes the header in front of every frame takes: a big-endian
+/// `u32` channel id, a big-endian `u16` payload length, and `u16` flags.
+pub const FRAME_HEADER_SIZE: usize = 8;
+
+/// The most bytes of payload one frame carries, so that the whole frame
+/// fits in `PIPE_BUF` and is written atomically.
+pub const MAX_FRAME_PAYLOAD: usize = PIPE_BUF - FRAME_HEADER_SIZE;
+
+/// Set in the flags of every frame of a message but the last.
+const FLAG_MORE: u16 = 1;
+
+/// The header of a frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct FrameHeader {
+    channel: u32,
+    len: u16,
+    more: bool,
+}
+
+impl FrameHeader {
+    fn encode(&self) -> [u8; FRAME_HEADER_SIZE] {
+        let flags = if self.more { FLAG_MORE } else { 0 };
+        let mut header = [0; FRAME_HEADER_SIZE];
+        header[..4].copy_from_slice(&self.channel.to_be_bytes());
+        header[4..6].copy_from_slice(&self.len.to_be_bytes());
+        header[6..].copy_from_slice(&flags.to_be_bytes());
+        header
+    }
+
+    fn decode(header: &[u8]) -> FrameHeader {
+        let flags = u16::from_be_bytes([header[6], header[7]]);
+        FrameHeader {
+            channel: u32::from_be_bytes([header[0], header[1], header[2], header[3]]),
+            len: u16::from_be_bytes([header[4], header[5]]),
+            more: flags & FLAG_MORE != 0,
+        }
+    }
+}
+
+/// A writer which sends messages on numbered channels over one FIFO.
+///
+/// Every message is sent as one or more frames, each of which starts with
+/// a `FRAME_HEADER_SIZE` byte header and is at most `PIPE_BUF` bytes long,
+/// so frames are written atomically and never interleave with those of
+/// other writers. A message longer than `MAX_FRAME_PAYLOAD` is split over
+/// several frames, which may be interleaved with frames on other channels;
+/// only one writer should send such messages on any one channel.
+///
+/// While the pipe is full, sending waits for room.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::Write;
+/// use unix_named_pipe::Mux;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// # let reader = unix_named_pipe::open_read(file_name).unwrap();
+/// let file = unix_named_pipe::open_write(file_name).expect("could not open fifo for writing");
+/// let mux = Mux::new(file);
+/// mux.send(1, b"plugin one is ready").expect("could not send message");
+/// writeln!(mux.channel(2), "plugin two is ready").expect("could not send message");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Mux<W> {
+    inner: W,
+}
+
+impl<W: AsFd> Mux<W> {
+    /// Wraps the write end `inner`.
+    pub fn new(inner: W) -> Mux<W> {
+        Mux { inner }
+    }
+
+    /// Sends `message` on `channel`.
+    ///
+    /// # Errors
+    ///
+    /// - If the reader has gone away, `Err(io::ErrorKind::BrokenPipe)` will
+    ///   be returned, with the raw `EPIPE` code. Some frames of a
+    ///   message longer than `MAX_FRAME_PAYLOAD` may have been sent.
+    /// - Any other error returned by `write` or `poll`.
+    pub fn send(&self, channel: u32, message: &[u8]) -> io::Result<()> {
+        let mut chunks = message.chunks(MAX_FRAME_PAYLOAD).peekable();
+        if chunks.peek().is_none() {
+            return self.send_frame(channel, &[], false);
+        }
+
+        while let Some(chunk) = chunks.next() {
+            self.send_frame(channel, chunk, chunks.peek().is_some())?;
+        }
+
+        Ok(())
+    }
+
+    fn send_frame(&self, channel: u32, payload: &[u8], more: bool) -> io::Result<()> {
+        let header = FrameHeader {
+            channel,
+            len: payload.len() as u16,
+            more,
+        }
+        .encode();
+        let frame = [IoSlice::new(&header), IoSlice::new(payload)];
+
+        loop {
+            match self.inner.write_vectored_atomic(&frame) {
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    // A hang-up shows up as `EPIPE` on the next write.
+                    self.inner.poll_writable(None)?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Returns a writer which sends every write as one message on
+    /// `channel`.
+    pub fn channel(&self, channel: u32) -> Channel<'_, W> {
+        Channel { mux: self, channel }
+    }
+
+    /// Returns the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the wrapped writer mutably.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsFd> AsFd for Mux<W> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+impl<W: AsRawFd> AsRawFd for Mux<W> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// A writer for one channel of a `Mux`, returned by `Mux::channel`.
+#[derive(Debug)]
+pub struct Channel<'a, W> {
+    mux: &'a Mux<W>,
+    channel: u32,
+}
+
+impl<W> Channel<'_, W> {
+    /// Returns the id of the channel.
+    pub fn id(&self) -> u32 {
+        self.channel
+    }
+}
+
+impl<W: AsFd> Write for Channel<'_, W> {
+    /// Sends all of `buf` as one message, as `Mux::send` does.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.mux.send(self.channel, buf).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+type Handler = Box<dyn FnMut(Vec<u8>) + Send>;
+type UnknownHandler = Box<dyn FnMut(u32, Vec<u8>) + Send>;
+
+/// Where the messages of a channel go.
+enum Route {
+    Handler(Handler),
+    Queue(Arc<Queue>),
+}
+
+/// What reading towards the next message led to.
+enum Step {
+    Message(u32, Vec<u8>),
+    Pending,
+    End,
+}
+
+/// A reader which takes apart the channels a `Mux` sends over one FIFO,
+/// made by `FifoBufReader::demux`.
+///
+/// The messages of each channel go to a callback registered with
+/// `on_channel`, or to a bounded queue made by `queue` which another thread
+/// receives from. A full queue applies its `Backpressure`: with
+/// `Backpressure::Block`, the demultiplexer stops reading the pipe until
+/// there is room, which in turn blocks the writers once the pipe is full.
+/// Messages for channels without either are unknown, and are counted and
+/// handed to the `on_unknown` callback if there is one.
+///
+/// A message split over several frames is held in memory until its last
+/// frame arrives, so a writer which keeps sending frames on a channel
+/// without ever finishing the message makes it grow without bound.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::{Backpressure, FifoBufReader, Mux};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+/// let mux = Mux::new(unix_named_pipe::open_write(file_name).unwrap());
+/// mux.send(1, b"log line").unwrap();
+/// mux.send(2, b"metric").unwrap();
+/// drop(mux);
+///
+/// let mut demux = FifoBufReader::new(file).unwrap().demux();
+/// demux.on_channel(1, |message| println!("log: {:?}", message));
+/// let metrics = demux.queue(2, 64, Backpressure::Block);
+/// demux.run().expect("could not read from fifo");
+/// assert_eq!(metrics.try_recv().unwrap(), b"metric");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+pub struct Demux<R> {
+    reader: FifoBufReader<R>,
+    frame: Vec<u8>,
+    assembling: HashMap<u32, Vec<u8>>,
+    channels: HashMap<u32, Route>,
+    on_unknown: Option<UnknownHandler>,
+    unknown: u64,
+    stalled: Option<(u32, Vec<u8>)>,
+}
+
+impl<R: Read + AsFd> Demux<R> {
+    pub(crate) fn new(reader: FifoBufReader<R>) -> Demux<R> {
+        Demux {
+            reader,
+            frame: Vec::new(),
+            assembling: HashMap::new(),
+            channels: HashMap::new(),
+            on_unknown: None,
+            unknown: 0,
+            stalled: None,
+        }
+    }
+
+    /// Calls `handler` with every message on `channel`, replacing whatever
+    /// the channel had.
+    pub fn on_channel<F>(&mut self, channel: u32, handler: F)
+    where
+        F: FnMut(Vec<u8>) + Send + 'static,
+    {
+        self.route(channel, Route::Handler(Box::new(handler)));
+    }
+
+    /// Queues the messages on `channel` for the returned receiver, holding
+    /// at most `capacity` of them, and replacing whatever the channel had.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn queue(
+        &mut self,
+        channel: u32,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> ChannelReceiver {
+        assert!(capacity > 0, "channel queues cannot be empty");
+
+        let queue = Arc::new(Queue {
+            channel,
+            capacity,
+            backpressure,
+            state: Mutex::new(QueueState::default()),
+            changed: Condvar::new(),
+        });
+        self.route(channel, Route::Queue(queue.clone()));
+
+        ChannelReceiver { queue }
+    }
+
+    /// Calls `handler` with the channel and message of every message on a
+    /// channel which has neither a callback nor a queue.
+    pub fn on_unknown<F>(&mut self, handler: F)
+    where
+        F: FnMut(u32, Vec<u8>) + Send + 'static,
+    {
+        self.on_unknown = Some(Box::new(handler));
+    }
+
+    /// Removes the callback or queue of `channel`, after which its messages
+    /// are unknown. Returns whether there was one.
+    pub fn remove(&mut self, channel: u32) -> bool {
+        close_route(self.channels.remove(&channel))
+    }
+
+    /// Returns how many messages on unknown channels have been read.
+    pub fn unknown_messages(&self) -> u64 {
+        self.unknown
+    }
+
+    /// Returns the wrapped reader.
+    pub fn get_ref(&self) -> &FifoBufReader<R> {
+        &self.reader
+    }
+
+    /// Returns the wrapped reader mutably.
+    pub fn get_mut(&mut self) -> &mut FifoBufReader<R> {
+        &mut self.reader
+    }
+
+    fn route(&mut self, channel: u32, route: Route) {
+        close_route(self.channels.insert(channel, route));
+    }
+
+    /// Hands every message which has arrived in full to its channel,
+    /// without waiting for more. Returns how many were handed over, or
+    /// `None` if no writer is attached and nothing is left, which is the
+    /// case both before the first writer has opened the FIFO and after the
+    /// last has hung up.
+    ///
+    /// # Errors
+    ///
+    /// - If the queue for a message is full and its backpressure is
+    ///   `Backpressure::Error`, `Err(io::ErrorKind::WouldBlock)` will be
+    ///   returned, carrying `PipeError::QueueFull`. The message is kept and
+    ///   handed over first on the next call.
+    /// - If the writers hung up in the middle of a frame,
+    ///   `Err(io::ErrorKind::UnexpectedEof)` will be returned.
+    /// - Any other error returned by `read`.
+    pub fn dispatch(&mut self) -> io::Result<Option<usize>> {
+        let mut dispatched = 0;
+        if let Some((channel, message)) = self.stalled.take() {
+            self.deliver(channel, message)?;
+            dispatched += 1;
+        }
+
+        loop {
+            match self.step()? {
+                Step::Message(channel, message) => {
+                    self.deliver(channel, message)?;
+                    dispatched += 1;
+                }
+                Step::Pending => return Ok(Some(dispatched)),
+                Step::End if dispatched == 0 => return Ok(None),
+                Step::End => return Ok(Some(dispatched)),
+            }
+        }
+    }
+
+    /// Hands messages to their channels until every writer has hung up,
+    /// sleeping in `poll` while there are none. A demultiplexer started
+    /// before any writer has opened the FIFO waits for one.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by `dispatch` or `poll`.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            let idle = self.dispatch()?.is_none();
+            // `dispatch` finds no writer both before the first one has
+            // opened the FIFO and after the last has hung up. Only the
+            // latter is reported by `poll`.
+            if self.reader.poll_readable(None)? == Readiness::HangUp && idle {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads towards the next whole message.
+    fn step(&mut self) -> io::Result<Step> {
+        loop {
+            if self.frame.len() >= FRAME_HEADER_SIZE {
+                let header = FrameHeader::decode(&self.frame);
+                if self.frame.len() == FRAME_HEADER_SIZE + header.len as usize {
+                    let payload = self.frame.split_off(FRAME_HEADER_SIZE);
+                    self.frame.clear();
+                    if header.more {
+                        self.assembling
+                            .entry(header.channel)
+                            .or_default()
+                            .extend_from_slice(&payload);
+                        continue;
+                    }
+
+                    let message = match self.assembling.remove(&header.channel) {
+                        Some(mut message) => {
+                            message.extend_from_slice(&payload);
+                            message
+                        }
+                        None => payload,
+                    };
+                    return Ok(Step::Message(header.channel, message));
+                }
+            }
+
+            let wanted = match self.frame.len() {
+                len if len < FRAME_HEADER_SIZE => FRAME_HEADER_SIZE - len,
+                len => FRAME_HEADER_SIZE + FrameHeader::decode(&self.frame).len as usize - len,
+            };
+            let buf = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(Step::Pending)
+                }
+                Err(err) => return Err(err),
+            };
+
+            if buf.is_empty() {
+                if self.frame.is_empty() {
+                    return Ok(Step::End);
+                }
+
+                let len = self.frame.len();
+                self.frame.clear();
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("writers hung up {} bytes into a frame", len),
+                ));
+            }
+
+            let len = wanted.min(buf.len());
+            self.frame.extend_from_slice(&buf[..len]);
+            self.reader.consume(len);
+        }
+    }
+
+    fn deliver(&mut self, channel: u32, message: Vec<u8>) -> io::Result<()> {
+        match self.channels.get_mut(&channel) {
+            Some(Route::Handler(handler)) => handler(message),
+            Some(Route::Queue(queue)) => {
+                if let Err(message) = queue.push(message) {
+                    self.stalled = Some((channel, message));
+                    return Err(PipeError::QueueFull {
+                        channel,
+                        capacity: queue.capacity,
+                    }
+                    .into());
+                }
+            }
+            None => {
+                self.unknown += 1;
+                if let Some(ref mut handler) = self.on_unknown {
+                    handler(channel, message);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Closes the queue of a route which has been taken off its channel.
+/// Returns whether there was a route.
+fn close_route(route: Option<Route>) -> bool {
+    match route {
+        Some(Route::Queue(queue)) => {
+            queue.close();
+            true
+        }
+        Some(Route::Handler(_)) => true,
+        None => false,
+    }
+}
+
+impl<R> Drop for Demux<R> {
+    /// Closes every queue, so their receivers see the end once they are
+    /// empty.
+    fn drop(&mut self) {
+        for route in self.channels.values() {
+            if let Route::Queue(ref queue) = *route {
+                queue.close();
+            }
+        }
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for Demux<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut channels: Vec<_> = self.channels.keys().collect();
+        channels.sort();
+        f.debug_struct("Demux")
+            .field("reader", &self.reader)
+            .field("channels", &channels)
+            .field("unknown", &self.unknown)
+            .finish()
+    }
+}
+
+impl<R: AsFd> AsFd for Demux<R> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.reader.as_fd()
+    }
+}
+
+impl<R: AsRawFd> AsRawFd for Demux<R> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.reader.as_raw_fd()
+    }
+}
+
+/// The bounded queue of one channel of a `Demux`.
+#[derive(Debug)]
+struct Queue {
+    channel: u32,
+    capacity: usize,
+    backpressure: Backpressure,
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    messages: VecDeque<Vec<u8>>,
+    dropped: u64,
+    /// The demultiplexer will not push any more messages.
+    closed: bool,
+    /// The receiver will not take any more messages.
+    abandoned: bool,
+}
+
+impl Queue {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Pushes `message` as the backpressure says, handing it back if the
+    /// queue is full and the backpressure is `Backpressure::Error`.
+    fn push(&self, message: Vec<u8>) -> Result<(), Vec<u8>> {
+        let mut state = self.lock();
+        while state.messages.len() >= self.capacity && !state.abandoned {
+            match self.backpressure {
+                Backpressure::Block => {
+                    state = self
+                        .changed
+                        .wait(state)
+                        .unwrap_or_else(|err| err.into_inner());
+                }
+                Backpressure::DropNewest => {
+                    state.dropped += 1;
+                    return Ok(());
+                }
+                Backpressure::DropOldest => {
+                    state.messages.pop_front();
+                    state.dropped += 1;
+                }
+                Backpressure::Error => return Err(message),
+            }
+        }
+
+        if state.abandoned {
+            state.dropped += 1;
+        } else {
+            state.messages.push_back(message);
+            self.changed.notify_all();
+        }
+
+        Ok(())
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// The receiving end of a channel queue, made by `Demux::queue`.
+///
+/// It can be moved to another thread, which takes the channel's messages
+/// while the demultiplexer reads the pipe.
+#[derive(Debug)]
+pub struct ChannelReceiver {
+    queue: Arc<Queue>,
+}
+
+impl ChannelReceiver {
+    /// Returns the id of the channel.
+    pub fn channel(&self) -> u32 {
+        self.queue.channel
+    }
+
+    /// Takes the next message if there is one, without waiting.
+    pub fn try_recv(&self) -> Option<Vec<u8>> {
+        let message = self.queue.lock().messages.pop_front();
+        if message.is_some() {
+            self.queue.changed.notify_all();
+        }
+
+        message
+    }
+
+    /// Takes the next message, waiting for one to be queued. Returns `None`
+    /// once the queue is empty and the demultiplexer has been dropped, or
+    /// the channel removed.
+    pub fn recv(&self) -> Option<Vec<u8>> {
+        let mut state = self.queue.lock();
+        loop {
+            if let Some(message) = state.messages.pop_front() {
+                self.queue.changed.notify_all();
+                return Some(message);
+            }
+            if state.closed {
+                return None;
+            }
+
+            state = self
+                .queue
+                .changed
+                .wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    /// Returns how many messages are queued.
+    pub fn len(&self) -> usize {
+        self.queue.lock().messages.len()
+    }
+
+    /// Returns whether no messages are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns how many messages the backpressure has dropped.
+    pub fn dropped_messages(&self) -> u64 {
+        self.queue.lock().dropped
+    }
+}
+
+impl Iterator for ChannelReceiver {
+    type Item = Vec<u8>;
+
+    /// Takes the next message as `recv` does.
+    fn next(&mut self) -> Option<Vec<u8>> {
+        self.recv()
+    }
+}
+
+impl Drop for ChannelReceiver {
+    /// Lets the demultiplexer drop the messages of the channel from now on,
+    /// instead of waiting for room.
+    fn drop(&mut self) {
+        self.queue.lock().abandoned = true;
+        self.queue.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write};
+    use super::*;
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn frames_fit_in_pipe_buf() {
+        let file_name = "/tmp/mux.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mux = Mux::new(open_write(file_name).expect("could not open fifo for writing"));
+        mux.send(7, b"hi").unwrap();
+        mux.channel(3)
+            .write_all(&[b'x'; MAX_FRAME_PAYLOAD + 1])
+            .unwrap();
+        mux.send(7, b"").unwrap();
+
+        let mut received = Vec::new();
+        reader.read_available(&mut received).unwrap();
+        assert_eq!(
+            received.len(),
+            4 * FRAME_HEADER_SIZE + 2 + MAX_FRAME_PAYLOAD + 1
+        );
+        assert_eq!(&received[..10], b"\0\0\0\x07\0\x02\0\0hi");
+        let second = &received[10..];
+        assert_eq!(&second[..8], &[0, 0, 0, 3, 0x0f, 0xf8, 0, 1]);
+        let third = &second[PIPE_BUF..];
+        assert_eq!(third, b"\0\0\0\x03\0\x01\0\0x\0\0\0\x07\0\0\0\0");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn dispatches_channels() {
+        let file_name = "/tmp/demux.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mux = Mux::new(open_write(file_name).expect("could not open fifo for writing"));
+        let mut demux = FifoBufReader::new(reader).unwrap().demux();
+        let (sender, logs) = std::sync::mpsc::channel();
+        demux.on_channel(1, move |message| sender.send(message).unwrap());
+        let unknown = Arc::new(Mutex::new(Vec::new()));
+        let seen = unknown.clone();
+        demux.on_unknown(move |channel, _| seen.lock().unwrap().push(channel));
+        let dropping = demux.queue(2, 1, Backpressure::DropOldest);
+        let failing = demux.queue(3, 1, Backpressure::Error);
+
+        let long = vec![b'l'; 2 * MAX_FRAME_PAYLOAD + 1];
+        mux.send(1, &long).unwrap();
+        for message in &[&b"first"[..], b"second"] {
+            mux.send(2, message).unwrap();
+            mux.send(3, message).unwrap();
+        }
+        mux.send(9, b"stray").unwrap();
+        let err = demux.dispatch().unwrap_err();
+        match PipeError::of(&err) {
+            Some(PipeError::QueueFull { channel, capacity }) => {
+                assert_eq!((channel, capacity), (3, 1))
+            }
+            other => panic!("expected QueueFull, got {:?}", other),
+        }
+        assert_eq!(logs.try_recv().unwrap(), long);
+        assert_eq!(dropping.try_recv().unwrap(), b"second");
+        assert_eq!(dropping.dropped_messages(), 1);
+        assert_eq!(failing.try_recv().unwrap(), b"first");
+        assert_eq!(demux.dispatch().unwrap(), Some(2));
+        assert_eq!(failing.try_recv().unwrap(), b"second");
+        assert_eq!(*unknown.lock().unwrap(), [9]);
+        assert_eq!(demux.unknown_messages(), 1);
+
+        drop(mux);
+        assert_eq!(demux.dispatch().unwrap(), None);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn run_waits_for_the_first_writer() {
+        let file_name = "/tmp/demux-wait.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut demux = FifoBufReader::new(reader).unwrap().demux();
+        let receiver = demux.queue(4, 8, Backpressure::Block);
+        let dispatcher = thread::spawn(move || demux.run());
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(!dispatcher.is_finished());
+        let mux = Mux::new(open_write(file_name).expect("could not open fifo for writing"));
+        mux.send(4, b"late").unwrap();
+        drop(mux);
+        dispatcher.join().unwrap().unwrap();
+        assert_eq!(receiver.try_recv().unwrap(), b"late");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn blocking_queue_holds_back_the_pipe() {
+        let file_name = "/tmp/demux-block.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mux = Mux::new(open_write(file_name).expect("could not open fifo for writing"));
+        let mut demux = FifoBufReader::new(reader).unwrap().demux();
+        let receiver = demux.queue(5, 2, Backpressure::Block);
+        let consumer = thread::spawn(move || receiver.collect::<Vec<_>>());
+        let dispatcher = thread::spawn(move || demux.run());
+
+        for n in 0..100u32 {
+            mux.send(5, &n.to_be_bytes()).unwrap();
+        }
+        drop(mux);
+        dispatcher.join().unwrap().unwrap();
+        let received = consumer.join().unwrap();
+        assert_eq!(received.len(), 100);
+        assert!(received
+            .iter()
+            .enumerate()
+            .all(|(n, message)| message[..] == (n as u32).to_be_bytes()));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
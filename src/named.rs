@@ -0,0 +1,580 @@
+//! Provides `NamedPipe`, an open end of a FIFO which remembers where it
+//! lives, so it can be reopened, and can clean the FIFO up when it is
+//! dropped.
+
+use super::audit::{self, AuditAction};
+use super::error::not_a_fifo;
+use super::ext::status_flags;
+use super::sigpipe::write_blocking_sigpipe;
+use super::{create_open, remove_if_fifo, Direction, FileFIFOExt, IntoMode, PipeOptions};
+use libc::{O_ACCMODE, O_WRONLY};
+use std::convert::TryFrom;
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// An open end of a named pipe, together with the path it was opened at.
+///
+/// A `NamedPipe` can also be made from a descriptor opened elsewhere, in
+/// which case it has no path.
+///
+/// With `unlink_on_drop(true)` the FIFO is removed when the value is
+/// dropped, including while unwinding from a panic, so a server does not
+/// leave a stale pipe behind on shutdown. Only a FIFO is ever removed; if
+/// the path has been replaced by something else it is left alone.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::path::Path;
+/// use unix_named_pipe::{Direction, NamedPipe};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// let pipe = NamedPipe::create(file_name, Some(0o660), Direction::Read)
+///     .expect("could not create fifo")
+///     .unlink_on_drop(true);
+/// drop(pipe);
+/// assert!(!Path::new(file_name).exists());
+/// ```
+#[derive(Debug)]
+pub struct NamedPipe {
+    file: File,
+    location: Location,
+    direction: Direction,
+    suppress_sigpipe: bool,
+}
+
+/// The path a `NamedPipe` was opened at, which audits the close of the
+/// file and removes the FIFO when it is dropped if `unlink_on_drop` is
+/// set. Keeping this apart from the file lets `into_file` move the file
+/// out while the cleanup still runs.
+#[derive(Debug)]
+struct Location {
+    path: Option<PathBuf>,
+    unlink_on_drop: bool,
+    owns_file: bool,
+}
+
+impl Location {
+    fn new(path: Option<PathBuf>) -> Location {
+        Location {
+            path,
+            unlink_on_drop: false,
+            owns_file: true,
+        }
+    }
+
+    fn record_close(&self) {
+        if let Some(ref path) = self.path {
+            audit::record(AuditAction::Close, path, &Ok::<(), ()>(()));
+        }
+    }
+}
+
+impl NamedPipe {
+    /// Creates a new FIFO at `path` and opens it in the given `direction`,
+    /// as `create_open` does.
+    pub fn create<P: AsRef<Path>, M: IntoMode>(
+        path: P,
+        mode: M,
+        direction: Direction,
+    ) -> io::Result<NamedPipe> {
+        let file = create_open(&path, mode, direction)?;
+
+        Ok(NamedPipe::new(file, path.as_ref(), direction))
+    }
+
+    /// Opens the existing FIFO at `path` in the given `direction` with the
+    /// default options of `open_read` and `open_write`.
+    ///
+    /// # Errors
+    ///
+    /// - If the file at `path` is not a FIFO, `Err(io::ErrorKind::InvalidInput)`
+    ///   will be returned.
+    /// - Any other error returned by `open_read` or `open_write`.
+    pub fn open<P: AsRef<Path>>(path: P, direction: Direction) -> io::Result<NamedPipe> {
+        let file = PipeOptions::new(direction).verify_fifo(true).open(&path)?;
+
+        Ok(NamedPipe::new(file, path.as_ref(), direction))
+    }
+
+    fn new(file: File, path: &Path, direction: Direction) -> NamedPipe {
+        NamedPipe {
+            file,
+            location: Location::new(Some(path.to_path_buf())),
+            direction,
+            suppress_sigpipe: false,
+        }
+    }
+
+    /// Sets whether the FIFO is removed when this value is dropped.
+    /// Defaults to `false`. Nothing is removed if the path is not known.
+    pub fn unlink_on_drop(mut self, unlink_on_drop: bool) -> NamedPipe {
+        self.location.unlink_on_drop = unlink_on_drop;
+        self
+    }
+
+    /// Sets whether writes block `SIGPIPE` while they run, as
+    /// `write_without_sigpipe` does, so the process cannot be killed by the
+    /// reader going away. Defaults to `false`.
+    ///
+    /// With suppression on, a write after the reader has gone away fails
+    /// with `PipeError::ReaderGone`. With it off, the same only holds while
+    /// the process ignores or blocks `SIGPIPE`; under the default
+    /// disposition the signal kills the process before the write returns.
+    pub fn suppress_sigpipe(mut self, suppress_sigpipe: bool) -> NamedPipe {
+        self.suppress_sigpipe = suppress_sigpipe;
+        self
+    }
+
+    /// Returns the path the pipe was opened at, if it is known.
+    pub fn path(&self) -> Option<&Path> {
+        self.location.path.as_deref()
+    }
+
+    /// Returns which end of the pipe is open.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns the open file.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Queries the metadata of the open FIFO with `fstat`. Unlike
+    /// `fs::metadata` on the path, this describes the pipe actually open,
+    /// even if the path has since been removed or replaced.
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        self.file.metadata()
+    }
+
+    /// Closes the open end and opens the FIFO at the same path again, in
+    /// the same direction and blocking mode, for reconnecting after the
+    /// other end went away. The other settings of this value are kept.
+    ///
+    /// Reopening the write end fails with `PipeError::NoReader` on a
+    /// non-blocking pipe until a reader is back, and leaves the old
+    /// descriptor in place.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use unix_named_pipe::{Direction, NamedPipe};
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let reader = NamedPipe::open(file_name, Direction::Read).unwrap();
+    /// let mut writer = NamedPipe::open(file_name, Direction::Write).unwrap();
+    ///
+    /// // The consumer restarts
+    /// drop(reader);
+    /// let reader = NamedPipe::open(file_name, Direction::Read).unwrap();
+    /// writer.reopen().expect("could not reconnect");
+    /// writer.write_all(b"hello again").unwrap();
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the pipe was made from a descriptor and has no path,
+    ///   `Err(io::ErrorKind::InvalidInput)` will be returned.
+    /// - Any error returned by `NamedPipe::open`.
+    pub fn reopen(&mut self) -> io::Result<()> {
+        let path = match self.location.path {
+            Some(ref path) => path,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "pipe was not opened at a path, so it cannot be reopened",
+                ))
+            }
+        };
+
+        self.file = PipeOptions::new(self.direction)
+            .nonblocking(self.file.is_nonblocking()?)
+            .verify_fifo(true)
+            .open(path)?;
+        self.location.record_close();
+
+        Ok(())
+    }
+
+    /// Consumes the pipe, returning the open file. If `unlink_on_drop` is
+    /// set, the FIFO is removed now, as it would have been on drop.
+    pub fn into_file(mut self) -> File {
+        self.location.owns_file = false;
+        self.file
+    }
+
+    /// Consumes the pipe, returning it as a `Stdio` for wiring into a
+    /// `std::process::Command`. The descriptor is switched to blocking mode
+    /// first, since most programs do not expect a non-blocking stdin or
+    /// stdout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::process::Command;
+    /// use unix_named_pipe::{Direction, NamedPipe};
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// # let reader = unix_named_pipe::open_read(file_name).unwrap();
+    /// let pipe = NamedPipe::open(file_name, Direction::Write).expect("could not open fifo");
+    /// let status = Command::new("echo")
+    ///     .arg("hello")
+    ///     .stdout(pipe.into_stdio().expect("could not convert fifo"))
+    ///     .status()
+    ///     .expect("could not run echo");
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    pub fn into_stdio(self) -> io::Result<Stdio> {
+        let file = self.into_file();
+        file.set_nonblocking(false)?;
+
+        Ok(Stdio::from(file))
+    }
+
+    /// Duplicates the pipe handle so one end can be shared with a thread
+    /// or a child process, as `FileFIFOExt::duplicate` does. The copy has
+    /// the same path, direction and `SIGPIPE` handling but never unlinks the
+    /// FIFO on drop, which stays the job of the original.
+    pub fn try_clone(&self) -> io::Result<NamedPipe> {
+        Ok(NamedPipe {
+            file: self.file.duplicate()?,
+            location: Location::new(self.location.path.clone()),
+            direction: self.direction,
+            suppress_sigpipe: self.suppress_sigpipe,
+        })
+    }
+}
+
+impl Read for NamedPipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for NamedPipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.suppress_sigpipe {
+            write_blocking_sigpipe(self.file.as_fd(), buf)
+        } else {
+            self.file.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl AsFd for NamedPipe {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+impl AsRawFd for NamedPipe {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for NamedPipe {
+    /// Wraps a descriptor opened elsewhere. The direction is taken from the
+    /// descriptor's access mode: `O_WRONLY` is the write end, and both
+    /// `O_RDONLY` and `O_RDWR` are the read end, since a FIFO is only opened
+    /// read-write to hold it open for a reader, as `PipeOptions::hold_open`
+    /// does.
+    fn from(fd: OwnedFd) -> NamedPipe {
+        let direction = match status_flags(fd.as_raw_fd()) {
+            Ok(flags) if flags & O_ACCMODE == O_WRONLY => Direction::Write,
+            _ => Direction::Read,
+        };
+
+        NamedPipe {
+            file: File::from(fd),
+            location: Location::new(None),
+            direction,
+            suppress_sigpipe: false,
+        }
+    }
+}
+
+impl TryFrom<File> for NamedPipe {
+    type Error = io::Error;
+
+    /// Wraps a file opened elsewhere, such as a descriptor inherited from a
+    /// parent process, after checking with `fstat` that it is a FIFO.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::convert::TryFrom;
+    /// use unix_named_pipe::NamedPipe;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+    /// let pipe = NamedPipe::try_from(file).expect("file is not a fifo");
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the file is not a FIFO, `Err(io::ErrorKind::InvalidInput)` will
+    ///   be returned and the file is closed.
+    fn try_from(file: File) -> io::Result<NamedPipe> {
+        let file_type = file.metadata()?.file_type();
+        if !file_type.is_fifo() {
+            return Err(not_a_fifo("wrap", None, file_type));
+        }
+
+        Ok(NamedPipe::from(OwnedFd::from(file)))
+    }
+}
+
+impl Drop for Location {
+    fn drop(&mut self) {
+        // The file is dropped first, as it comes before the location.
+        if self.owns_file {
+            self.record_close();
+        }
+        if let (true, Some(path)) = (self.unlink_on_drop, &self.path) {
+            let _ = remove_if_fifo(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read_fd, open_write_fd, PipeError};
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::panic;
+    use std::process::Command;
+
+    #[test]
+    fn keeps_pipe_by_default() {
+        let file_name = "/tmp/named-keep.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = NamedPipe::open(file_name, Direction::Read).expect("could not open fifo");
+        let mut writer = NamedPipe::open(file_name, Direction::Write).expect("could not open fifo");
+        assert_eq!(writer.path(), Some(Path::new(file_name)));
+        assert_eq!(writer.direction(), Direction::Write);
+
+        writer.write_all(b"ok").expect("could not write to fifo");
+        let mut actual = [0; 2];
+        reader
+            .read_exact(&mut actual)
+            .expect("could not read from fifo");
+        assert_eq!(&actual, b"ok");
+
+        drop(reader);
+        drop(writer);
+        assert!(Path::new(file_name).exists());
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn unlinks_on_panic() {
+        let file_name = "/tmp/named-panic.pipe";
+        let result = panic::catch_unwind(|| {
+            let _pipe = NamedPipe::create(file_name, None, Direction::Read)
+                .expect("could not create fifo")
+                .unlink_on_drop(true);
+            panic!("server crashed");
+        });
+
+        assert!(result.is_err());
+        assert!(fs::symlink_metadata(file_name).is_err());
+    }
+
+    #[test]
+    fn from_owned_fd() {
+        let file_name = "/tmp/named-fd.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = NamedPipe::from(open_read_fd(file_name).unwrap()).unlink_on_drop(true);
+        let writer = NamedPipe::from(open_write_fd(file_name).unwrap());
+        assert_eq!(reader.direction(), Direction::Read);
+        assert_eq!(writer.direction(), Direction::Write);
+        assert_eq!(reader.path(), None);
+
+        drop(reader);
+        assert!(Path::new(file_name).exists());
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn from_hold_open_fd_is_reader() {
+        let file_name = "/tmp/named-hold-open.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = PipeOptions::new(Direction::Read)
+            .hold_open(true)
+            .open(file_name)
+            .expect("could not open fifo");
+        let mut pipe = NamedPipe::from(OwnedFd::from(file));
+        assert_eq!(pipe.direction(), Direction::Read);
+
+        let mut writer = NamedPipe::open(file_name, Direction::Write).unwrap();
+        writer.write_all(b"held").expect("could not write to fifo");
+        let mut actual = [0; 4];
+        pipe.read_exact(&mut actual)
+            .expect("could not read from fifo");
+        assert_eq!(&actual, b"held");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn try_clone_leaves_unlinking_to_original() {
+        let file_name = "/tmp/named-clone.pipe";
+        let pipe = NamedPipe::create(file_name, None, Direction::Read)
+            .expect("could not create fifo")
+            .unlink_on_drop(true);
+
+        let copy = pipe.try_clone().expect("could not clone pipe");
+        assert_eq!(copy.path(), pipe.path());
+        assert_eq!(copy.direction(), Direction::Read);
+        drop(copy);
+        assert!(Path::new(file_name).exists());
+
+        drop(pipe);
+        assert!(!Path::new(file_name).exists());
+    }
+
+    thread_local! {
+        static SIGPIPES: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+    }
+
+    extern "C" fn count_sigpipe(_: libc::c_int) {
+        SIGPIPES.with(|count| count.set(count.get() + 1));
+    }
+
+    #[test]
+    fn try_clone_keeps_sigpipe_suppression() {
+        let file_name = "/tmp/named-clone-sigpipe.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = NamedPipe::open(file_name, Direction::Read).unwrap();
+        let writer = NamedPipe::open(file_name, Direction::Write)
+            .unwrap()
+            .suppress_sigpipe(true);
+        let mut copy = writer.try_clone().expect("could not clone pipe");
+        drop(reader);
+
+        // The test harness ignores SIGPIPE, so count deliveries to this
+        // thread instead of relying on the signal killing the process.
+        let handler = count_sigpipe as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        let previous = unsafe { libc::signal(libc::SIGPIPE, handler) };
+        let err = copy.write(b"lost").unwrap_err();
+        unsafe { libc::signal(libc::SIGPIPE, previous) };
+
+        assert_eq!(err.raw_os_error(), Some(libc::EPIPE));
+        assert_eq!(SIGPIPES.with(|count| count.get()), 0);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn into_stdio_feeds_child() {
+        let file_name = "/tmp/named-stdio.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = NamedPipe::open(file_name, Direction::Read).unwrap();
+        let writer = NamedPipe::open(file_name, Direction::Write)
+            .unwrap()
+            .unlink_on_drop(true);
+        let status = Command::new("echo")
+            .arg("hello")
+            .stdout(writer.into_stdio().expect("could not convert pipe"))
+            .status()
+            .expect("could not run echo");
+        assert!(status.success());
+        assert!(!Path::new(file_name).exists());
+
+        let mut actual = String::new();
+        reader
+            .read_to_string(&mut actual)
+            .expect("could not read from fifo");
+        assert_eq!(actual, "hello\n");
+    }
+
+    #[test]
+    fn reader_gone_keeps_epipe() {
+        let file_name = "/tmp/named-reader-gone.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = NamedPipe::open(file_name, Direction::Read).unwrap();
+        let mut writer = NamedPipe::open(file_name, Direction::Write)
+            .unwrap()
+            .suppress_sigpipe(true);
+        drop(reader);
+
+        let err = writer.write(b"lost").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EPIPE));
+        assert!(matches!(PipeError::of(&err), Some(PipeError::ReaderGone)));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn open_refuses_regular_file() {
+        let file_name = "/tmp/named-regular.txt";
+        fs::write(file_name, "").expect("could not write test file");
+
+        let err = NamedPipe::open(file_name, Direction::Read).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = NamedPipe::try_from(File::open(file_name).unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_file(file_name).expect("could not remove test file");
+    }
+
+    #[test]
+    fn reopen_keeps_blocking_mode() {
+        let file_name = "/tmp/named-reopen.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = NamedPipe::open(file_name, Direction::Read).unwrap();
+        let original = reader.metadata().unwrap();
+        reader.file().set_nonblocking(false).unwrap();
+        let mut writer = NamedPipe::open(file_name, Direction::Write).unwrap();
+
+        reader.reopen().expect("could not reopen fifo");
+        assert!(!reader.is_nonblocking().unwrap());
+        assert_eq!(reader.metadata().unwrap().ino(), original.ino());
+        writer.write_all(b"again").unwrap();
+        let mut actual = [0; 5];
+        reader.read_exact(&mut actual).unwrap();
+        assert_eq!(&actual, b"again");
+
+        drop(reader);
+        let err = writer.reopen().unwrap_err();
+        assert!(matches!(PipeError::of(&err), Some(PipeError::NoReader)));
+        assert!(writer.is_nonblocking().unwrap());
+
+        let mut unnamed = NamedPipe::from(open_read_fd(file_name).unwrap());
+        let err = unnamed.reopen().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
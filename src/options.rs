@@ -0,0 +1,620 @@
+//! Provides `PipeOptions`, a builder for opening named pipes with flags other
+//! than the defaults used by `open_read` and `open_write`.
+
+use super::audit::{self, AuditAction};
+use super::error::{not_a_fifo, open_error};
+#[cfg(target_os = "linux")]
+use super::ext::status_flags;
+use super::symlink::open_no_symlinks;
+use super::{check_path, Direction, Mode, PipeError, SymlinkPolicy};
+use libc::{c_int, fcntl, FD_CLOEXEC, F_GETFD, F_SETFD};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, OwnedFd};
+use std::path::Path;
+
+/// Options which control how a named pipe is opened.
+///
+/// By default a pipe is opened non-blocking and close-on-exec, which is what
+/// `open_read` and `open_write` do.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::{Direction, PipeOptions};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// // Let a spawned child inherit the read end
+/// let file = PipeOptions::new(Direction::Read)
+///     .cloexec(false)
+///     .open(file_name)
+///     .expect("could not open fifo for reading");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct PipeOptions {
+    direction: Direction,
+    nonblocking: bool,
+    cloexec: bool,
+    symlinks: SymlinkPolicy,
+    verify_fifo: bool,
+    hold_open: bool,
+    append: bool,
+    expect_owner: Option<u32>,
+    max_mode: Option<Mode>,
+    #[cfg(target_os = "linux")]
+    packet_mode: bool,
+}
+
+impl PipeOptions {
+    /// Creates options for opening the given end of a pipe.
+    pub fn new(direction: Direction) -> PipeOptions {
+        PipeOptions {
+            direction,
+            nonblocking: true,
+            cloexec: true,
+            symlinks: SymlinkPolicy::FollowAll,
+            verify_fifo: false,
+            hold_open: false,
+            append: false,
+            expect_owner: None,
+            max_mode: None,
+            #[cfg(target_os = "linux")]
+            packet_mode: false,
+        }
+    }
+
+    /// Sets whether the pipe is opened with `O_NONBLOCK`. Defaults to `true`.
+    ///
+    /// A blocking open waits until the other end of the pipe is opened too.
+    pub fn nonblocking(&mut self, nonblocking: bool) -> &mut PipeOptions {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Sets whether the descriptor is closed when the process calls `exec`.
+    /// Defaults to `true`, so pipe descriptors do not leak into children.
+    pub fn cloexec(&mut self, cloexec: bool) -> &mut PipeOptions {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Sets whether the open fails with `ELOOP` when the final component of
+    /// the path is a symbolic link, by passing `O_NOFOLLOW`.
+    /// Defaults to `false`.
+    ///
+    /// This is shorthand for `symlinks(SymlinkPolicy::RejectFinalSymlink)`,
+    /// or for `SymlinkPolicy::FollowAll` when `nofollow` is `false`.
+    pub fn nofollow(&mut self, nofollow: bool) -> &mut PipeOptions {
+        self.symlinks = if nofollow {
+            SymlinkPolicy::RejectFinalSymlink
+        } else {
+            SymlinkPolicy::FollowAll
+        };
+        self
+    }
+
+    /// Sets which symbolic links the path may contain. Defaults to
+    /// `SymlinkPolicy::FollowAll`.
+    ///
+    /// # Errors
+    ///
+    /// - With `SymlinkPolicy::RejectAnySymlink`, if a directory in the path
+    ///   is a symbolic link, the `ENOTDIR` or `ELOOP` error will be returned.
+    pub fn symlinks(&mut self, policy: SymlinkPolicy) -> &mut PipeOptions {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Sets whether the opened descriptor is checked with `fstat` to really
+    /// be a FIFO before it is returned. Defaults to `false`.
+    ///
+    /// Together with `nofollow`, this closes the window in which a daemon
+    /// opening pipes in a world-writable directory could be handed a
+    /// symlink or a regular file swapped in by another user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::{Direction, PipeOptions};
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let file = PipeOptions::new(Direction::Read)
+    ///     .nofollow(true)
+    ///     .verify_fifo(true)
+    ///     .open(file_name)
+    ///     .expect("could not open fifo for reading");
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the opened file is not a FIFO, `Err(io::ErrorKind::InvalidInput)`
+    ///   will be returned and the descriptor is closed.
+    pub fn verify_fifo(&mut self, verify_fifo: bool) -> &mut PipeOptions {
+        self.verify_fifo = verify_fifo;
+        self
+    }
+
+    /// Sets whether a read end keeps the pipe open for writing as well, so
+    /// that it never reads end-of-file when the last writer disconnects.
+    /// Defaults to `false`, and has no effect on a write end.
+    ///
+    /// This is done by opening the read end with `O_RDWR`; the returned file
+    /// should still only be read from. Reads then return `WouldBlock` on a
+    /// non-blocking pipe, or wait on a blocking one, until the next writer
+    /// sends data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::{self, Read};
+    /// use unix_named_pipe::{Direction, PipeOptions};
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let mut file = PipeOptions::new(Direction::Read)
+    ///     .hold_open(true)
+    ///     .open(file_name)
+    ///     .expect("could not open fifo for reading");
+    /// drop(unix_named_pipe::open_write(file_name).unwrap());
+    ///
+    /// let err = file.read(&mut [0; 16]).unwrap_err();
+    /// assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    pub fn hold_open(&mut self, hold_open: bool) -> &mut PipeOptions {
+        self.hold_open = hold_open;
+        self
+    }
+
+    /// Sets whether a write end is opened with `O_APPEND`. Defaults to
+    /// `false`, and has no effect on a read end.
+    ///
+    /// Appending makes no difference to how a FIFO is written, since it has
+    /// no file offset; this is only kept for compatibility with earlier
+    /// versions of `open_write`, which always set it.
+    pub fn append(&mut self, append: bool) -> &mut PipeOptions {
+        self.append = append;
+        self
+    }
+
+    /// Sets the user id the opened FIFO must be owned by. Defaults to none,
+    /// which accepts any owner.
+    ///
+    /// The owner is checked with `fstat` on the opened descriptor, so it is
+    /// the pipe actually opened which is checked, not whatever is at `path`
+    /// by the time of the check. This keeps a daemon from reading commands
+    /// out of a pipe another user created in its place.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use unix_named_pipe::{Direction, Mode, PipeOptions};
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, Some(0o600)).unwrap();
+    /// let uid = unsafe { libc::geteuid() };
+    /// let file = PipeOptions::new(Direction::Read)
+    ///     .expect_owner(uid)
+    ///     .expect_mode_at_most(Mode::owner_rw().group_read().group_write())
+    ///     .open(file_name)
+    ///     .expect("fifo does not match policy");
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the opened FIFO has another owner,
+    ///   `Err(io::ErrorKind::PermissionDenied)` will be returned, carrying
+    ///   `PipeError::WrongOwner`, and the descriptor is closed.
+    pub fn expect_owner(&mut self, uid: u32) -> &mut PipeOptions {
+        self.expect_owner = Some(uid);
+        self
+    }
+
+    /// Sets the most permissive mode the opened FIFO may have. Defaults to
+    /// none, which accepts any mode. Like `expect_owner`, this is checked
+    /// on the opened descriptor.
+    ///
+    /// # Errors
+    ///
+    /// - If the opened FIFO has any permission bit set which `mode` does
+    ///   not have, `Err(io::ErrorKind::PermissionDenied)` will be returned,
+    ///   carrying `PipeError::ModeTooWide`, and the descriptor is closed.
+    pub fn expect_mode_at_most(&mut self, mode: Mode) -> &mut PipeOptions {
+        self.max_mode = Some(mode);
+        self
+    }
+
+    /// Sets whether the pipe is switched into packet mode by setting
+    /// `O_DIRECT` on the opened descriptor. Defaults to `false`. Only
+    /// available on Linux.
+    ///
+    /// In packet mode every write is kept apart as a packet of its own, and
+    /// each read returns at most one packet, so small records keep their
+    /// boundaries without any framing. It is the write end which marks the
+    /// packets, so it is the writer which needs this set. Writes larger
+    /// than `PIPE_BUF` are split into several packets, and a read with a
+    /// buffer smaller than the packet discards the rest of it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::{Read, Write};
+    /// use unix_named_pipe::{Direction, PipeOptions};
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let mut reader = unix_named_pipe::open_read(file_name).unwrap();
+    /// let mut writer = PipeOptions::new(Direction::Write)
+    ///     .packet_mode(true)
+    ///     .open(file_name)
+    ///     .expect("could not open fifo for writing");
+    /// writer.write_all(b"first").unwrap();
+    /// writer.write_all(b"second").unwrap();
+    ///
+    /// let mut buf = [0; 64];
+    /// assert_eq!(reader.read(&mut buf).unwrap(), 5);
+    /// assert_eq!(reader.read(&mut buf).unwrap(), 6);
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the kernel does not support packet mode pipes, which it does
+    ///   since Linux 3.4, the `EINVAL` error will be returned.
+    #[cfg(target_os = "linux")]
+    pub fn packet_mode(&mut self, packet_mode: bool) -> &mut PipeOptions {
+        self.packet_mode = packet_mode;
+        self
+    }
+
+    /// Opens the pipe at `path` with these options.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<File> {
+        let result = self.open_path(path.as_ref());
+        audit::record(AuditAction::Open, path.as_ref(), &result);
+
+        result
+    }
+
+    /// Opens the pipe at `path` with these options, returning the bare
+    /// descriptor instead of a `File`.
+    pub fn open_fd<P: AsRef<Path>>(&self, path: P) -> io::Result<OwnedFd> {
+        self.open(path).map(OwnedFd::from)
+    }
+
+    fn open_path(&self, path: &Path) -> io::Result<File> {
+        check_path(path)?;
+
+        let file = if self.symlinks == SymlinkPolicy::RejectAnySymlink {
+            open_no_symlinks(path, self.open_flags())?
+        } else {
+            self.open_std(path)?
+        };
+
+        if !self.cloexec {
+            set_cloexec(&file, false)?;
+        }
+
+        // Opening a FIFO with O_DIRECT fails, but it can be set afterwards.
+        #[cfg(target_os = "linux")]
+        if self.packet_mode {
+            set_packet_mode(&file)?;
+        }
+
+        if self.verify_fifo {
+            let file_type = file.metadata()?.file_type();
+            if !file_type.is_fifo() {
+                return Err(not_a_fifo("open", Some(path), file_type));
+            }
+        }
+
+        if self.expect_owner.is_some() || self.max_mode.is_some() {
+            self.check_policy(&file, path)?;
+        }
+
+        Ok(file)
+    }
+
+    fn open_std(&self, path: &Path) -> io::Result<File> {
+        let mut options = OpenOptions::new();
+        match self.direction {
+            Direction::Read => options.read(true).write(self.hold_open),
+            Direction::Write => options.write(true).append(self.append),
+        };
+        let mut flags = 0;
+        if self.nonblocking {
+            flags |= libc::O_NONBLOCK;
+        }
+        if self.symlinks == SymlinkPolicy::RejectFinalSymlink {
+            flags |= libc::O_NOFOLLOW;
+        }
+        options.custom_flags(flags);
+
+        // std always opens with O_CLOEXEC, so opting out means clearing it
+        // afterwards.
+        options.open(path).map_err(|err| open_error(err, path))
+    }
+
+    /// Returns the `open` flags for these options, as `open_std` would
+    /// have std pass them.
+    fn open_flags(&self) -> c_int {
+        let mut flags = match self.direction {
+            Direction::Read if self.hold_open => libc::O_RDWR,
+            Direction::Read => libc::O_RDONLY,
+            Direction::Write => libc::O_WRONLY,
+        };
+        if self.direction == Direction::Write && self.append {
+            flags |= libc::O_APPEND;
+        }
+        if self.nonblocking {
+            flags |= libc::O_NONBLOCK;
+        }
+
+        flags | libc::O_CLOEXEC
+    }
+
+    fn check_policy(&self, file: &File, path: &Path) -> io::Result<()> {
+        let metadata = file.metadata()?;
+        if let Some(uid) = self.expect_owner {
+            if metadata.uid() != uid {
+                return Err(PipeError::WrongOwner {
+                    path: path.to_path_buf(),
+                    expected: uid,
+                    actual: metadata.uid(),
+                }
+                .into());
+            }
+        }
+
+        if let Some(max_mode) = self.max_mode {
+            let mode = metadata.permissions().mode() & 0o7777;
+            if mode & !max_mode.bits() != 0 {
+                return Err(PipeError::ModeTooWide {
+                    path: path.to_path_buf(),
+                    allowed: max_mode.bits(),
+                    actual: mode,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn set_cloexec(file: &File, cloexec: bool) -> io::Result<()> {
+    let fd = file.as_raw_fd();
+    let flags = unsafe { fcntl(fd, F_GETFD) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let flags = if cloexec {
+        flags | FD_CLOEXEC
+    } else {
+        flags & !FD_CLOEXEC
+    };
+    if unsafe { fcntl(fd, F_SETFD, flags) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_packet_mode(file: &File) -> io::Result<()> {
+    let fd = file.as_raw_fd();
+    let flags = status_flags(fd)?;
+    if unsafe { fcntl(fd, libc::F_SETFL, flags | libc::O_DIRECT) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write, FileFIFOExt};
+    use super::*;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::symlink;
+
+    fn has_cloexec(file: &File) -> bool {
+        let flags = unsafe { fcntl(file.as_raw_fd(), F_GETFD) };
+        flags & FD_CLOEXEC != 0
+    }
+
+    #[test]
+    fn cloexec_by_default() {
+        let file_name = "/tmp/options-cloexec.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let read_file = open_read(file_name).expect("could not open fifo for reading");
+        let write_file = open_write(file_name).expect("could not open fifo for writing");
+        assert!(has_cloexec(&read_file));
+        assert!(has_cloexec(&write_file));
+
+        let inheritable = PipeOptions::new(Direction::Read)
+            .cloexec(false)
+            .open(file_name)
+            .expect("could not open fifo for reading");
+        assert!(!has_cloexec(&inheritable));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn blocking_open() {
+        let file_name = "/tmp/options-blocking.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let read_file = PipeOptions::new(Direction::Read)
+            .open(file_name)
+            .expect("could not open fifo for reading");
+        assert!(read_file.is_nonblocking().unwrap());
+
+        let write_file = PipeOptions::new(Direction::Write)
+            .nonblocking(false)
+            .open(file_name)
+            .expect("could not open fifo for writing");
+        assert!(!write_file.is_nonblocking().unwrap());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn hold_open_never_reads_eof() {
+        let file_name = "/tmp/options-hold-open.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut read_file = PipeOptions::new(Direction::Read)
+            .hold_open(true)
+            .open(file_name)
+            .expect("could not open fifo for reading");
+        for message in &[b"one", b"two"] {
+            let mut write_file = open_write(file_name).expect("could not open fifo for writing");
+            write_file
+                .write_all(*message)
+                .expect("could not write to fifo");
+            drop(write_file);
+
+            let mut actual = [0; 3];
+            read_file
+                .read_exact(&mut actual)
+                .expect("could not read from fifo");
+            assert_eq!(&actual, *message);
+            let err = read_file.read(&mut actual).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        }
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn nofollow_rejects_symlink() {
+        let file_name = "/tmp/options-nofollow.pipe";
+        let link_name = "/tmp/options-nofollow.link";
+        create(file_name, None).expect("could not create fifo");
+        symlink(file_name, link_name).expect("could not create symlink");
+
+        let mut options = PipeOptions::new(Direction::Read);
+        options.nofollow(true);
+        let err = options.open(link_name).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+        assert!(options.open(file_name).is_ok());
+
+        fs::remove_file(link_name).expect("could not remove symlink");
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn append_only_when_asked() {
+        let file_name = "/tmp/options-append.pipe";
+        create(file_name, None).expect("could not create fifo");
+        let _read_file = open_read(file_name).expect("could not open fifo for reading");
+
+        let status_flags = |file: &File| unsafe { fcntl(file.as_raw_fd(), libc::F_GETFL) };
+        let write_file = open_write(file_name).expect("could not open fifo for writing");
+        assert_eq!(status_flags(&write_file) & libc::O_APPEND, 0);
+
+        let append_file = PipeOptions::new(Direction::Write)
+            .append(true)
+            .open(file_name)
+            .expect("could not open fifo for writing");
+        assert_eq!(status_flags(&append_file) & libc::O_APPEND, libc::O_APPEND);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn expect_owner_and_mode() {
+        let file_name = "/tmp/options-policy.pipe";
+        create(file_name, Some(0o600)).expect("could not create fifo");
+        fs::set_permissions(file_name, fs::Permissions::from_mode(0o640)).unwrap();
+        let uid = unsafe { libc::geteuid() };
+
+        let mut options = PipeOptions::new(Direction::Read);
+        options
+            .expect_owner(uid)
+            .expect_mode_at_most(Mode::owner_rw().group_read());
+        assert!(options.open(file_name).is_ok());
+
+        let err = options
+            .expect_mode_at_most(Mode::owner_rw())
+            .open(file_name)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            PipeError::of(&err).as_ref().and_then(PipeError::path),
+            Some(Path::new(file_name))
+        );
+
+        let err = PipeOptions::new(Direction::Read)
+            .expect_owner(uid + 1)
+            .open(file_name)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        match PipeError::of(&err) {
+            Some(PipeError::WrongOwner { path, actual, .. }) => {
+                assert_eq!((path.as_path(), actual), (Path::new(file_name), uid))
+            }
+            other => panic!("expected WrongOwner, got {:?}", other),
+        }
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn verify_fifo_rejects_regular_file() {
+        let file_name = "/tmp/options-verify.txt";
+        fs::write(file_name, "").expect("could not write test file");
+
+        let err = PipeOptions::new(Direction::Read)
+            .verify_fifo(true)
+            .open(file_name)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_file(file_name).expect("could not remove test file");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn packet_mode_keeps_boundaries() {
+        let file_name = "/tmp/options-packet.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = PipeOptions::new(Direction::Write)
+            .packet_mode(true)
+            .open(file_name)
+            .expect("could not open fifo for writing");
+        let flags = unsafe { fcntl(writer.as_raw_fd(), libc::F_GETFL) };
+        assert_ne!(flags & libc::O_DIRECT, 0);
+
+        writer.write_all(b"one").unwrap();
+        writer.write_all(b"three").unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(reader.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"three");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
@@ -0,0 +1,181 @@
+//! Provides ownership changes for existing FIFOs, with user and group names
+//! resolved through the system user database.
+
+use super::audit::{self, AuditAction};
+use super::error::not_a_fifo;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::fs::{lchown, FileTypeExt};
+use std::path::Path;
+use std::ptr;
+
+/// Changes the owner and group of the named pipe at `path`, looking `user`
+/// and `group` up by name. Either can be `None` to leave it unchanged.
+/// Symbolic links are not followed, and anything which is not a FIFO is
+/// refused.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate unix_named_pipe;
+/// unix_named_pipe::create("/run/app/cmd.pipe", Some(0o660)).expect("could not create fifo");
+/// unix_named_pipe::set_owner("/run/app/cmd.pipe", Some("app"), Some("app"))
+///     .expect("could not hand fifo to worker");
+/// ```
+///
+/// # Errors
+///
+/// - If `user` or `group` does not exist, `Err(io::ErrorKind::NotFound)`
+///   will be returned.
+/// - If the file at `path` is not a FIFO, `Err(io::ErrorKind::InvalidInput)`
+///   will be returned and the file is left unchanged.
+/// - Any error returned by `lstat` or `lchown`.
+pub fn set_owner<P: AsRef<Path>>(
+    path: P,
+    user: Option<&str>,
+    group: Option<&str>,
+) -> io::Result<()> {
+    let uid = user.map(lookup_user).transpose()?;
+    let gid = group.map(lookup_group).transpose()?;
+    let path = path.as_ref();
+    let result = chown_fifo(path, uid, gid);
+    audit::record(AuditAction::SetOwner { uid, gid }, path, &result);
+
+    result
+}
+
+fn chown_fifo(path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    if !file_type.is_fifo() {
+        return Err(not_a_fifo("change owner of", Some(path), file_type));
+    }
+
+    lchown(path, uid, gid)
+}
+
+/// Returns the user id of the user called `name`, using `getpwnam_r`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// assert_eq!(unix_named_pipe::lookup_user("root").unwrap(), 0);
+/// ```
+///
+/// # Errors
+///
+/// - If there is no such user, `Err(io::ErrorKind::NotFound)` will be
+///   returned.
+pub fn lookup_user(name: &str) -> io::Result<u32> {
+    let c_name = CString::new(name)?;
+    let mut entry: libc::passwd = unsafe { mem::zeroed() };
+    let mut found: *mut libc::passwd = ptr::null_mut();
+
+    with_lookup_buffer(libc::_SC_GETPW_R_SIZE_MAX, |buf| unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    })?;
+    if found.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such user {:?}", name),
+        ));
+    }
+
+    Ok(entry.pw_uid)
+}
+
+/// Returns the group id of the group called `name`, using `getgrnam_r`.
+///
+/// # Errors
+///
+/// - If there is no such group, `Err(io::ErrorKind::NotFound)` will be
+///   returned.
+pub fn lookup_group(name: &str) -> io::Result<u32> {
+    let c_name = CString::new(name)?;
+    let mut entry: libc::group = unsafe { mem::zeroed() };
+    let mut found: *mut libc::group = ptr::null_mut();
+
+    with_lookup_buffer(libc::_SC_GETGR_R_SIZE_MAX, |buf| unsafe {
+        libc::getgrnam_r(
+            c_name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    })?;
+    if found.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such group {:?}", name),
+        ));
+    }
+
+    Ok(entry.gr_gid)
+}
+
+/// Runs a `get*nam_r` style lookup, growing the string buffer for as long as
+/// the lookup reports `ERANGE`.
+fn with_lookup_buffer<F>(size_hint: libc::c_int, mut lookup: F) -> io::Result<()>
+where
+    F: FnMut(&mut Vec<libc::c_char>) -> libc::c_int,
+{
+    let hint = unsafe { libc::sysconf(size_hint) };
+    let mut buf = vec![0; if hint > 0 { hint as usize } else { 1024 }];
+    loop {
+        match lookup(&mut buf) {
+            0 => return Ok(()),
+            libc::ERANGE if buf.len() < 1 << 20 => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            errno => return Err(io::Error::from_raw_os_error(errno)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, FileFIFOExt};
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn lookup_names() {
+        assert_eq!(lookup_user("root").unwrap(), 0);
+        assert_eq!(lookup_group("root").unwrap(), 0);
+
+        let err = lookup_user("no-such-user-for-fifo-tests").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = lookup_group("no-such-group-for-fifo-tests").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn set_owner_to_current_user() {
+        let file_name = "/tmp/owner.pipe";
+        create(file_name, None).expect("could not create fifo");
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        file.set_owner(Some(uid), Some(gid))
+            .expect("could not change owner");
+        set_owner(file_name, None, None).expect("could not change owner");
+        let metadata = fs::metadata(file_name).unwrap();
+        assert_eq!((metadata.uid(), metadata.gid()), (uid, gid));
+
+        let err = set_owner(file_name, Some("no-such-user-for-fifo-tests"), None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
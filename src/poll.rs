@@ -0,0 +1,60 @@
+//! Provides the `poll(2)` wait behind `FileFIFOExt::poll_readable` and
+//! `FileFIFOExt::poll_writable`.
+
+use libc::{c_int, c_short, pollfd};
+use std::io;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+use std::time::{Duration, Instant};
+
+/// Waits until `fd` reports one of `events`, or an error or hangup, for at
+/// most `timeout`, or forever if it is `None`. Returns the reported events,
+/// or `None` if the timeout expired first. Interrupted waits are resumed
+/// with the time which is left.
+pub(crate) fn poll_fd(
+    fd: BorrowedFd<'_>,
+    events: c_short,
+    timeout: Option<Duration>,
+) -> io::Result<Option<c_short>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let mut fds = pollfd {
+            fd: fd.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        let wait = match deadline {
+            Some(deadline) => timeout_ms(deadline.saturating_duration_since(Instant::now())),
+            None => -1,
+        };
+        match unsafe { libc::poll(&mut fds, 1, wait) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok(None),
+            _ => return Ok(Some(fds.revents)),
+        }
+    }
+}
+
+/// Converts `timeout` to the milliseconds `poll` takes, rounding up so
+/// that a short timeout still waits instead of polling in a busy loop.
+fn timeout_ms(timeout: Duration) -> c_int {
+    let ms = timeout.as_nanos().div_ceil(1_000_000);
+    ms.min(c_int::MAX as u128) as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_timeouts_up() {
+        assert_eq!(timeout_ms(Duration::from_secs(0)), 0);
+        assert_eq!(timeout_ms(Duration::from_micros(1)), 1);
+        assert_eq!(timeout_ms(Duration::from_millis(1500)), 1500);
+        assert_eq!(timeout_ms(Duration::from_secs(u64::MAX)), c_int::MAX);
+    }
+}
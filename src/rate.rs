@@ -0,0 +1,239 @@
+//! Provides `RateLimitedWriter`, which paces writes to a pipe so that a
+//! fast producer cannot swamp a slow consumer.
+
+use super::{Clock, SystemClock};
+use std::cmp;
+use std::fmt;
+use std::io::{self, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A token bucket which holds at most one second's worth of tokens.
+#[derive(Clone, Copy, Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> TokenBucket {
+        assert!(rate > 0, "rate limit must not be zero");
+
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+
+    /// Returns how long it takes for `wanted` tokens to be available.
+    fn wait_for(&self, wanted: f64) -> Duration {
+        if self.tokens >= wanted {
+            return Duration::from_secs(0);
+        }
+
+        Duration::from_secs_f64((wanted - self.tokens) / self.rate)
+    }
+}
+
+/// A writer which limits how many bytes and how many writes per second
+/// reach the wrapped writer, sleeping as needed.
+///
+/// Both limits are token buckets which allow a burst of up to one second's
+/// worth at once. Every call to `write` counts as one message, and a write
+/// larger than the byte rate is cut down to it, so `write_all` sends large
+/// buffers one second's worth at a time.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::Write;
+/// use unix_named_pipe::RateLimitedWriter;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// # let reader = unix_named_pipe::open_read(file_name).unwrap();
+/// let file = unix_named_pipe::open_write(file_name).expect("could not open fifo for writing");
+/// let mut writer = RateLimitedWriter::new(file)
+///     .bytes_per_sec(64 * 1024)
+///     .messages_per_sec(100);
+/// writer.write_all(b"paced\n").unwrap();
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+pub struct RateLimitedWriter<W> {
+    inner: W,
+    bytes: Option<TokenBucket>,
+    messages: Option<TokenBucket>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<W: Write> RateLimitedWriter<W> {
+    /// Wraps `inner` without any limits.
+    pub fn new(inner: W) -> RateLimitedWriter<W> {
+        RateLimitedWriter {
+            inner,
+            bytes: None,
+            messages: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Limits the bytes written per second to `rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is zero.
+    pub fn bytes_per_sec(mut self, rate: u64) -> RateLimitedWriter<W> {
+        self.bytes = Some(TokenBucket::new(rate, self.clock.now()));
+        self
+    }
+
+    /// Limits the writes per second to `rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is zero.
+    pub fn messages_per_sec(mut self, rate: u64) -> RateLimitedWriter<W> {
+        self.messages = Some(TokenBucket::new(rate, self.clock.now()));
+        self
+    }
+
+    /// Measures and waits for time with `clock` instead of the system
+    /// clock. Set it before the limits.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> RateLimitedWriter<W> {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the wrapped writer mutably.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for RateLimitedWriter<W> {
+    /// Waits until the limits allow it, and then writes as much of `buf`
+    /// as one second's worth of bytes.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by the wrapped writer. Nothing is counted against
+    /// the limits for a write which fails.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.inner.write(buf);
+        }
+
+        let len = match self.bytes {
+            Some(ref bytes) => cmp::min(buf.len(), cmp::max(bytes.rate as usize, 1)),
+            None => buf.len(),
+        };
+        loop {
+            let now = self.clock.now();
+            let mut wait = Duration::from_secs(0);
+            if let Some(ref mut bytes) = self.bytes {
+                bytes.refill(now);
+                wait = cmp::max(wait, bytes.wait_for(len as f64));
+            }
+            if let Some(ref mut messages) = self.messages {
+                messages.refill(now);
+                wait = cmp::max(wait, messages.wait_for(1.0));
+            }
+            if wait == Duration::from_secs(0) {
+                break;
+            }
+
+            self.clock.sleep(wait);
+        }
+
+        let written = self.inner.write(&buf[..len])?;
+        if let Some(ref mut bytes) = self.bytes {
+            bytes.tokens -= written as f64;
+        }
+        if let Some(ref mut messages) = self.messages {
+            messages.tokens -= 1.0;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: fmt::Debug> fmt::Debug for RateLimitedWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RateLimitedWriter")
+            .field("inner", &self.inner)
+            .field("bytes_per_sec", &self.bytes.map(|bytes| bytes.rate))
+            .field(
+                "messages_per_sec",
+                &self.messages.map(|messages| messages.rate),
+            )
+            .finish()
+    }
+}
+
+impl<W: AsFd> AsFd for RateLimitedWriter<W> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+impl<W: AsRawFd> AsRawFd for RateLimitedWriter<W> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MockClock;
+    use super::*;
+
+    #[test]
+    fn paces_bytes_and_messages() {
+        let clock = Arc::new(MockClock::new());
+        let start = clock.now();
+        let mut writer = RateLimitedWriter::new(Vec::new())
+            .clock(clock.clone())
+            .bytes_per_sec(10);
+        writer.write_all(b"0123456789").unwrap();
+        assert_eq!(clock.now(), start);
+        writer.write_all(b"01234").unwrap();
+        assert_eq!(clock.now() - start, Duration::from_millis(500));
+        writer.write_all(&[0; 25]).unwrap();
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+        assert_eq!(writer.get_ref().len(), 40);
+
+        let start = clock.now();
+        let mut writer = RateLimitedWriter::new(Vec::new())
+            .clock(clock.clone())
+            .messages_per_sec(2);
+        for _ in 0..4 {
+            writer.write_all(b"message").unwrap();
+        }
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+    }
+}
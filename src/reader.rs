@@ -0,0 +1,674 @@
+//! Provides `PipeReader`, which decides once what a read from an empty
+//! non-blocking pipe does, instead of in every read loop, and
+//! `FifoBufReader`, a buffered reader which copes with non-blocking pipes.
+
+use super::{Demux, FileFIFOExt, Framing, Messages, PipeError, Readiness, RetryPolicy};
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::mem;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::time::Duration;
+
+/// What a `PipeReader` does when the pipe is empty but still has a writer.
+#[derive(Clone, Debug)]
+pub enum WaitStrategy {
+    /// Fail the read with `io::ErrorKind::WouldBlock`, as a plain
+    /// non-blocking read does.
+    ReturnWouldBlock,
+    /// Sleep in `poll` until data arrives or the writers hang up, for at
+    /// most the given time, or forever if it is `None`.
+    Poll(Option<Duration>),
+    /// Retry the read, backing off between attempts as described by the
+    /// policy, which also sets how long to keep trying.
+    Backoff(RetryPolicy),
+}
+
+impl Default for WaitStrategy {
+    fn default() -> WaitStrategy {
+        WaitStrategy::Poll(None)
+    }
+}
+
+/// A reader for a non-blocking pipe which waits for data according to a
+/// `WaitStrategy`, so that `read` only returns once there is data, the
+/// writers are gone, or the strategy gives up.
+///
+/// Interrupted reads are retried. When every writer has hung up, `read`
+/// returns `Ok(0)` as usual, unless `reopen_on_eof` is set.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::{Read, Write};
+/// use std::time::Duration;
+/// use unix_named_pipe::{PipeReader, WaitStrategy};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+/// let mut reader = PipeReader::new(file)
+///     .wait_strategy(WaitStrategy::Poll(Some(Duration::from_secs(5))));
+///
+/// let mut writer = unix_named_pipe::open_write(file_name).unwrap();
+/// writer.write_all(b"no match arm needed").unwrap();
+/// drop(writer);
+///
+/// let mut message = String::new();
+/// reader.read_to_string(&mut message).unwrap();
+/// assert_eq!(message, "no match arm needed");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+pub struct PipeReader<R> {
+    inner: R,
+    strategy: WaitStrategy,
+    reopen: Option<ReopenFn<R>>,
+}
+
+type ReopenFn<R> = Box<dyn FnMut(&mut R) -> io::Result<()> + Send>;
+
+impl<R: Read + AsFd> PipeReader<R> {
+    /// Wraps `inner`, waiting for data with `WaitStrategy::Poll(None)`.
+    pub fn new(inner: R) -> PipeReader<R> {
+        PipeReader {
+            inner,
+            strategy: WaitStrategy::default(),
+            reopen: None,
+        }
+    }
+
+    /// Sets what a read from an empty pipe does.
+    pub fn wait_strategy(mut self, strategy: WaitStrategy) -> PipeReader<R> {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Reopens the wrapped reader with `reopen` whenever every writer has
+    /// hung up, and then waits for data as the `WaitStrategy` says, so that
+    /// `read` never returns `Ok(0)` and whatever sits on top of the reader
+    /// survives short-lived writers.
+    ///
+    /// For a `NamedPipe`, `NamedPipe::reopen` does the job.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::{Read, Write};
+    /// use unix_named_pipe::{Direction, NamedPipe, PipeReader};
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let pipe = NamedPipe::open(file_name, Direction::Read).unwrap();
+    /// let mut reader = PipeReader::new(pipe).reopen_on_eof(NamedPipe::reopen);
+    ///
+    /// let mut writer = unix_named_pipe::open_write(file_name).unwrap();
+    /// writer.write_all(b"short-lived").unwrap();
+    /// drop(writer);
+    ///
+    /// let mut buf = [0; 11];
+    /// reader.read_exact(&mut buf).unwrap();
+    /// assert_eq!(&buf, b"short-lived");
+    /// # fs::remove_file(file_name).unwrap();
+    /// ```
+    pub fn reopen_on_eof<F>(mut self, reopen: F) -> PipeReader<R>
+    where
+        F: FnMut(&mut R) -> io::Result<()> + Send + 'static,
+    {
+        self.reopen = Some(Box::new(reopen));
+        self
+    }
+
+    /// Returns the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the wrapped reader mutably.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Reads into `buf` once, reopening `inner` at the end of its data if
+/// `reopen` is given, which leaves nothing to read for now.
+fn read_or_reopen<R: Read>(
+    inner: &mut R,
+    reopen: &mut Option<ReopenFn<R>>,
+    buf: &mut [u8],
+) -> io::Result<usize> {
+    match (inner.read(buf), reopen) {
+        (Ok(0), &mut Some(ref mut reopen)) if !buf.is_empty() => {
+            reopen(inner)?;
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+        (result, _) => result,
+    }
+}
+
+impl<R: Read + AsFd> Read for PipeReader<R> {
+    /// Reads into `buf`, waiting for data as the `WaitStrategy` says.
+    ///
+    /// # Errors
+    ///
+    /// - With `WaitStrategy::ReturnWouldBlock`, if the pipe is empty,
+    ///   `Err(io::ErrorKind::WouldBlock)` will be returned.
+    /// - With `WaitStrategy::Poll` or `WaitStrategy::Backoff`, if nothing
+    ///   arrives in time, `Err(io::ErrorKind::TimedOut)` will be returned,
+    ///   carrying `PipeError::TimedOut`.
+    /// - Any other error returned by `read`, `poll`, or the `reopen_on_eof`
+    ///   callback.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let PipeReader {
+            inner,
+            strategy,
+            reopen,
+        } = self;
+        loop {
+            match read_or_reopen(inner, reopen, buf) {
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            match *strategy {
+                WaitStrategy::ReturnWouldBlock => {
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+                WaitStrategy::Poll(timeout) => {
+                    if inner.poll_readable(timeout)? == Readiness::TimedOut {
+                        let timeout = timeout.unwrap_or_default();
+                        return Err(PipeError::TimedOut {
+                            path: None,
+                            timeout,
+                        }
+                        .into());
+                    }
+                }
+                WaitStrategy::Backoff(ref policy) => {
+                    return policy.retry_while(
+                        || read_or_reopen(inner, reopen, buf),
+                        |err| {
+                            err.kind() == io::ErrorKind::WouldBlock
+                                || err.kind() == io::ErrorKind::Interrupted
+                        },
+                        |timeout| {
+                            PipeError::TimedOut {
+                                path: None,
+                                timeout,
+                            }
+                            .into()
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for PipeReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PipeReader")
+            .field("inner", &self.inner)
+            .field("strategy", &self.strategy)
+            .field("reopen_on_eof", &self.reopen.is_some())
+            .finish()
+    }
+}
+
+impl<R: AsFd> AsFd for PipeReader<R> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+impl<R: AsRawFd> AsRawFd for PipeReader<R> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// A buffered reader for a non-blocking pipe, which buffers as much as the
+/// pipe itself can hold.
+///
+/// Unlike `std::io::BufReader`, every call to `fill_buf` reads whatever
+/// has arrived into the free part of the buffer, even when data is already
+/// buffered, and an empty pipe only fails with `io::ErrorKind::WouldBlock`
+/// when nothing at all is buffered. Otherwise `WouldBlock` just means the
+/// buffer did not grow, so data already read is never held back or lost.
+///
+/// Consumed space at the front is reclaimed by moving the rest of the data
+/// back once the buffer runs full.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::{self, BufRead, Write};
+/// use unix_named_pipe::FifoBufReader;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+/// let mut reader = FifoBufReader::new(file).unwrap();
+/// let mut writer = unix_named_pipe::open_write(file_name).unwrap();
+///
+/// writer.write_all(b"par").unwrap();
+/// assert_eq!(reader.fill_buf().unwrap(), b"par");
+/// writer.write_all(b"tial").unwrap();
+/// assert_eq!(reader.fill_buf().unwrap(), b"partial");
+/// reader.consume(7);
+///
+/// let err = reader.fill_buf().unwrap_err();
+/// assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FifoBufReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: Read + AsFd> FifoBufReader<R> {
+    /// Wraps `inner` with a buffer as large as the capacity of its pipe.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by `FileFIFOExt::buffer_size`.
+    pub fn new(inner: R) -> io::Result<FifoBufReader<R>> {
+        let capacity = inner.buffer_size()?;
+
+        Ok(FifoBufReader::with_capacity(capacity, inner))
+    }
+
+    /// Wraps `inner` with a buffer of `capacity` bytes, but at least one.
+    pub fn with_capacity(capacity: usize, inner: R) -> FifoBufReader<R> {
+        FifoBufReader {
+            inner,
+            buf: vec![0; capacity.max(1)].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    /// Returns the data buffered at the moment, without reading.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Returns how many bytes the buffer holds at most.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the wrapped reader mutably. Reading from it directly skips
+    /// past the buffered data.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped reader. Buffered data
+    /// is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns an iterator over the lines of the pipe which does not stop
+    /// or lose data when the pipe is empty. See `LinesNonblocking`.
+    pub fn lines_nonblocking(self) -> LinesNonblocking<FifoBufReader<R>> {
+        LinesNonblocking::new(self)
+    }
+
+    /// Returns an iterator over the messages of the pipe, framed as given
+    /// by `framing`, which waits for each message to arrive in full. See
+    /// `Messages`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `framing` is `Framing::FixedSize(0)`.
+    pub fn messages(self, framing: Framing) -> Messages<R> {
+        Messages::new(self, framing)
+    }
+
+    /// Returns a demultiplexer for the channels a `Mux` sends over the
+    /// pipe. See `Demux`.
+    pub fn demux(self) -> Demux<R> {
+        Demux::new(self)
+    }
+}
+
+impl<R: Read + AsFd> BufRead for FifoBufReader<R> {
+    /// Reads whatever has arrived into the free part of the buffer, and
+    /// returns all buffered data.
+    ///
+    /// # Errors
+    ///
+    /// - If nothing is buffered and the pipe is empty,
+    ///   `Err(io::ErrorKind::WouldBlock)` will be returned.
+    /// - Any other error returned by `read`. Buffered data is kept for the
+    ///   next call.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.filled {
+            self.pos = 0;
+            self.filled = 0;
+        } else if self.filled == self.buf.len() && self.pos > 0 {
+            self.buf.copy_within(self.pos..self.filled, 0);
+            self.filled -= self.pos;
+            self.pos = 0;
+        }
+
+        while self.filled < self.buf.len() {
+            match self.inner.read(&mut self.buf[self.filled..]) {
+                Ok(read) => self.filled += read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::WouldBlock && self.pos < self.filled => {}
+                Err(err) => return Err(err),
+            }
+            break;
+        }
+
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+impl<R: Read + AsFd> Read for FifoBufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+impl<R: AsFd> AsFd for FifoBufReader<R> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+impl<R: AsRawFd> AsRawFd for FifoBufReader<R> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// An iterator over the lines of a non-blocking pipe, made by
+/// `FifoBufReader::lines_nonblocking`.
+///
+/// Each item is `Some` with the next line, or `None` if no complete line
+/// has arrived yet. A partial line is kept until the rest of it arrives,
+/// however many reads that takes. Lines are returned without their `\n`
+/// or `\r\n`, as with `BufRead::lines`. The iterator ends once every
+/// writer has hung up, after returning any unterminated last line.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::Write;
+/// use unix_named_pipe::FifoBufReader;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let file = unix_named_pipe::open_read(file_name).expect("could not open fifo for reading");
+/// let mut lines = FifoBufReader::new(file).unwrap().lines_nonblocking();
+/// let mut writer = unix_named_pipe::open_write(file_name).unwrap();
+///
+/// writer.write_all(b"{\"numbers\":").unwrap();
+/// assert!(lines.next().unwrap().is_none());
+/// writer.write_all(b"[1,2]}\n").unwrap();
+/// let line = lines.next().unwrap().unwrap().unwrap();
+/// assert_eq!(line, "{\"numbers\":[1,2]}");
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct LinesNonblocking<B> {
+    reader: B,
+    partial: Vec<u8>,
+}
+
+impl<B: BufRead> LinesNonblocking<B> {
+    /// Reads lines from `reader`, which must fail with
+    /// `io::ErrorKind::WouldBlock` only when it has nothing buffered.
+    pub fn new(reader: B) -> LinesNonblocking<B> {
+        LinesNonblocking {
+            reader,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Returns the start of a line which has not been completed yet.
+    pub fn partial_line(&self) -> &[u8] {
+        &self.partial
+    }
+
+    /// Returns the wrapped reader.
+    pub fn get_ref(&self) -> &B {
+        &self.reader
+    }
+
+    /// Returns the wrapped reader mutably.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.reader
+    }
+
+    /// Consumes the iterator, returning the wrapped reader. A partial line
+    /// is lost.
+    pub fn into_inner(self) -> B {
+        self.reader
+    }
+
+    fn take_line(&mut self) -> io::Result<String> {
+        let mut line = mem::take(&mut self.partial);
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+
+        String::from_utf8(line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+impl<B: BufRead> Iterator for LinesNonblocking<B> {
+    type Item = Option<io::Result<String>>;
+
+    fn next(&mut self) -> Option<Option<io::Result<String>>> {
+        loop {
+            let (used, complete) = match self.reader.fill_buf() {
+                Ok([]) => {
+                    if self.partial.is_empty() {
+                        return None;
+                    }
+                    return Some(Some(self.take_line()));
+                }
+                Ok(buf) => match buf.iter().position(|&byte| byte == b'\n') {
+                    Some(end) => {
+                        self.partial.extend_from_slice(&buf[..=end]);
+                        (end + 1, true)
+                    }
+                    None => {
+                        self.partial.extend_from_slice(buf);
+                        (buf.len(), false)
+                    }
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Some(None),
+                Err(err) => return Some(Some(Err(err))),
+            };
+
+            self.reader.consume(used);
+            if complete {
+                return Some(Some(self.take_line()));
+            }
+        }
+    }
+}
+
+impl<B: AsFd> AsFd for LinesNonblocking<B> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.reader.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write, open_write_retry, MockClock};
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn strategies_for_empty_pipe() {
+        let file_name = "/tmp/pipe-reader.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        let mut reader = PipeReader::new(file).wait_strategy(WaitStrategy::ReturnWouldBlock);
+        let mut buf = [0; 4];
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        let mut reader = reader.wait_strategy(WaitStrategy::Poll(Some(Duration::from_millis(20))));
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let policy = RetryPolicy::new()
+            .deadline(Duration::from_secs(1))
+            .clock(Arc::new(MockClock::new()));
+        let mut reader = reader.wait_strategy(WaitStrategy::Backoff(policy));
+        let err = reader.read(&mut buf).unwrap_err();
+        assert!(matches!(
+            PipeError::of(&err),
+            Some(PipeError::TimedOut { .. })
+        ));
+
+        let mut reader = reader.wait_strategy(WaitStrategy::Poll(None));
+        let feeder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            writer.write_all(b"late").unwrap();
+        });
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"late");
+        feeder.join().unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn reopens_after_writers_leave() {
+        let file_name = "/tmp/pipe-reader-reopen.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let mut reader = PipeReader::new(file).reopen_on_eof(move |file| {
+            *file = open_read(file_name)?;
+            Ok(())
+        });
+        let feeder = thread::spawn(move || {
+            let policy = RetryPolicy::new().deadline(Duration::from_secs(5));
+            for client in &["one", "two"] {
+                let mut writer = open_write_retry(file_name, &policy).unwrap();
+                writer.write_all(client.as_bytes()).unwrap();
+                drop(writer);
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+
+        let mut buf = [0; 6];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"onetwo");
+        feeder.join().unwrap();
+
+        let mut reader = reader.wait_strategy(WaitStrategy::ReturnWouldBlock);
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn buf_reader_grows_without_losing_data() {
+        let file_name = "/tmp/fifo-buf-reader.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        let mut reader = FifoBufReader::with_capacity(4, file);
+        writer.write_all(b"abcdefgh").unwrap();
+        assert_eq!(reader.fill_buf().unwrap(), b"abcd");
+        assert_eq!(reader.buffer(), b"abcd");
+        reader.consume(2);
+        assert_eq!(reader.buffer(), b"cd");
+        assert_eq!(reader.fill_buf().unwrap(), b"cdef");
+        reader.consume(4);
+        assert_eq!(reader.fill_buf().unwrap(), b"gh");
+        reader.consume(1);
+        assert_eq!(reader.fill_buf().unwrap(), b"h");
+        assert_eq!(reader.fill_buf().unwrap(), b"h");
+
+        let mut buf = [0; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        let err = reader.fill_buf().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        drop(writer);
+        assert!(reader.fill_buf().unwrap().is_empty());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn lines_survive_split_writes() {
+        let file_name = "/tmp/lines-nonblocking.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let file = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        let mut lines = FifoBufReader::with_capacity(4, file).lines_nonblocking();
+        assert!(lines.next().unwrap().is_none());
+
+        writer.write_all(b"first li").unwrap();
+        assert!(lines.next().unwrap().is_none());
+        assert_eq!(lines.partial_line(), b"first li");
+        writer.write_all(b"ne\r\nsecond\nla").unwrap();
+        assert_eq!(lines.next().unwrap().unwrap().unwrap(), "first line");
+        assert_eq!(lines.next().unwrap().unwrap().unwrap(), "second");
+        assert!(lines.next().unwrap().is_none());
+
+        writer.write_all(b"st").unwrap();
+        drop(writer);
+        assert_eq!(lines.next().unwrap().unwrap().unwrap(), "last");
+        assert!(lines.next().is_none());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
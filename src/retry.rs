@@ -0,0 +1,463 @@
+//! Provides opens which wait for the other end of a named pipe to attach,
+//! instead of failing with `ENXIO` straight away or blocking forever.
+
+use super::error::is_no_reader;
+use super::{
+    open_at, open_write, Clock, Direction, FileFIFOExt, PipeError, PipeOptions, SystemClock,
+};
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Describes how `open_write_retry` backs off while the pipe has no reader.
+///
+/// The first retry waits `initial_delay`, and every retry after that waits
+/// `multiplier` times longer, up to `max_delay`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: u32,
+    deadline: Option<Duration>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RetryPolicy {
+    /// Creates a policy which starts by retrying after 10 milliseconds and
+    /// doubles the delay up to one second, with no overall deadline.
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2,
+            deadline: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn initial_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.initial_delay = delay;
+        self.max_delay = cmp::max(self.max_delay, delay);
+        self
+    }
+
+    /// Sets the longest delay between two retries.
+    pub fn max_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Sets how much longer each delay is than the one before. A multiplier
+    /// of `1` retries at a fixed interval.
+    pub fn multiplier(mut self, multiplier: u32) -> RetryPolicy {
+        self.multiplier = cmp::max(multiplier, 1);
+        self
+    }
+
+    /// Sets how long to keep retrying for in total before giving up.
+    pub fn deadline(mut self, deadline: Duration) -> RetryPolicy {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Waits between retries with `clock` instead of the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> RetryPolicy {
+        self.clock = clock;
+        self
+    }
+
+    /// Calls `open` until it returns anything other than `ENXIO`, backing
+    /// off in between.
+    pub(crate) fn retry<F>(&self, path: &Path, mut open: F) -> io::Result<File>
+    where
+        F: FnMut(&Path) -> io::Result<File>,
+    {
+        self.retry_while(
+            || open(path),
+            is_no_reader,
+            |timeout| {
+                PipeError::TimedOut {
+                    path: Some(path.to_path_buf()),
+                    timeout,
+                }
+                .into()
+            },
+        )
+    }
+
+    /// Calls `attempt` until it returns anything other than an error for
+    /// which `transient` holds, backing off in between. Once the deadline
+    /// passes, the error made by `timed_out` is returned instead.
+    pub(crate) fn retry_while<T, F, R, E>(
+        &self,
+        mut attempt: F,
+        transient: R,
+        timed_out: E,
+    ) -> io::Result<T>
+    where
+        F: FnMut() -> io::Result<T>,
+        R: Fn(&io::Error) -> bool,
+        E: FnOnce(Duration) -> io::Error,
+    {
+        let start = self.clock.now();
+        let mut delay = self.initial_delay;
+        loop {
+            match attempt() {
+                Err(ref err) if transient(err) => {}
+                result => return result,
+            }
+
+            let mut wait = delay;
+            if let Some(deadline) = self.deadline {
+                let elapsed = self.clock.now().duration_since(start);
+                if elapsed >= deadline {
+                    return Err(timed_out(deadline));
+                }
+                wait = cmp::min(wait, deadline - elapsed);
+            }
+
+            self.clock.sleep(wait);
+            delay = delay
+                .checked_mul(self.multiplier)
+                .map_or(self.max_delay, |delay| cmp::min(delay, self.max_delay));
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+/// Opens a named pipe for writing like `open_write`, retrying as described
+/// by `policy` for as long as there is no reader.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # use std::thread;
+/// use std::time::Duration;
+/// use unix_named_pipe::RetryPolicy;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// # let reader_name = file_name.clone();
+/// # let reader = thread::spawn(move || {
+/// #     thread::sleep(Duration::from_millis(20));
+/// #     unix_named_pipe::open_read(reader_name).unwrap()
+/// # });
+/// let policy = RetryPolicy::new().deadline(Duration::from_secs(5));
+/// let file = unix_named_pipe::open_write_retry(file_name, &policy)
+///     .expect("no reader attached to fifo");
+/// # reader.join().unwrap();
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - If no reader attaches before the policy's deadline,
+///   `Err(io::ErrorKind::TimedOut)` will be returned, carrying
+///   `PipeError::TimedOut`.
+/// - Any other error returned by `open_write`, without retrying.
+pub fn open_write_retry<P: AsRef<Path>>(path: P, policy: &RetryPolicy) -> io::Result<File> {
+    policy.retry(path.as_ref(), |path| open_write(path))
+}
+
+/// Opens a named pipe for blocking reads, waiting at most `timeout` for a
+/// writer to attach. Once opened, the file behaves like one opened with
+/// `PipeOptions::new(Direction::Read).nonblocking(false)`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io;
+/// use std::time::Duration;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let err = unix_named_pipe::open_read_timeout(file_name, Duration::from_millis(10))
+///     .unwrap_err();
+/// assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - If no writer attaches within `timeout`, `Err(io::ErrorKind::TimedOut)`
+///   will be returned, carrying `PipeError::TimedOut`. A writer which
+///   attaches just as the timeout passes is still handed its reader; one
+///   racing the release writer this function attaches to unblock its own
+///   open may see that reader close.
+/// - Any other error returned by the blocking open.
+pub fn open_read_timeout<P: AsRef<Path>>(path: P, timeout: Duration) -> io::Result<File> {
+    let path = path.as_ref();
+    let release_path = ReleasePath::new(path)?;
+    let owned_path = path.to_path_buf();
+    let (sender, receiver) = mpsc::channel();
+    let opener = thread::spawn(move || {
+        let result = PipeOptions::new(Direction::Read)
+            .nonblocking(false)
+            .open(&owned_path);
+        let _ = sender.send(result);
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => {
+            let _ = opener.join();
+            return result;
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            return Err(io::Error::other("blocking open of fifo panicked"));
+        }
+        Err(RecvTimeoutError::Timeout) => {}
+    }
+
+    // The opener is still blocked in open. Attaching a writer of our own
+    // releases it, after which both ends are simply closed again. A writer
+    // which attached on its own in the meantime keeps the file it opened.
+    loop {
+        if let Ok(result) = receiver.try_recv() {
+            let _ = opener.join();
+            return result;
+        }
+        match open_at(libc::AT_FDCWD, release_path.as_path(), libc::O_WRONLY) {
+            Ok(_release) => {
+                let _ = receiver.recv();
+                let _ = opener.join();
+                break;
+            }
+            // The opener has not reached open yet, or has just returned.
+            Err(ref err) if is_no_reader(err) => thread::yield_now(),
+            // The FIFO can no longer be reached; the opener stays blocked
+            // until some other writer opens it.
+            Err(_) => break,
+        }
+    }
+
+    Err(PipeError::TimedOut {
+        path: Some(path.to_path_buf()),
+        timeout,
+    }
+    .into())
+}
+
+/// Names the FIFO `open_read_timeout` is waiting on, for the writer which
+/// releases its opener thread.
+///
+/// On Linux an `O_PATH` descriptor is held on the FIFO itself, and reopened
+/// through `/proc/self/fd`, so the opener is released even if `path` has
+/// been removed or replaced in the meantime. Elsewhere `path` is opened
+/// again, and an opener waiting on a removed FIFO is left blocked.
+struct ReleasePath {
+    #[cfg(target_os = "linux")]
+    _held: File,
+    path: PathBuf,
+}
+
+impl ReleasePath {
+    #[cfg(target_os = "linux")]
+    fn new(path: &Path) -> io::Result<ReleasePath> {
+        use std::os::unix::io::AsRawFd;
+
+        let held = open_at(libc::AT_FDCWD, path, libc::O_PATH)?;
+        let path = PathBuf::from(format!("/proc/self/fd/{}", held.as_raw_fd()));
+        Ok(ReleasePath { _held: held, path })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new(path: &Path) -> io::Result<ReleasePath> {
+        Ok(ReleasePath {
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn as_path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Opens a named pipe for blocking writes, waiting at most `timeout` for a
+/// reader to attach. Once opened, the file behaves like one opened with
+/// `PipeOptions::new(Direction::Write).nonblocking(false)`.
+///
+/// # Errors
+///
+/// - If no reader attaches within `timeout`, `Err(io::ErrorKind::TimedOut)`
+///   will be returned, carrying `PipeError::TimedOut`.
+/// - Any other error returned by `open_write`.
+pub fn open_write_timeout<P: AsRef<Path>>(path: P, timeout: Duration) -> io::Result<File> {
+    open_write_timeout_with_clock(path, timeout, Arc::new(SystemClock))
+}
+
+/// Opens a named pipe like `open_write_timeout`, measuring the timeout and
+/// waiting between attempts with `clock`.
+///
+/// # Errors
+///
+/// The same as for `open_write_timeout`.
+pub fn open_write_timeout_with_clock<P: AsRef<Path>>(
+    path: P,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+) -> io::Result<File> {
+    let policy = RetryPolicy::new()
+        .initial_delay(Duration::from_millis(1))
+        .max_delay(Duration::from_millis(50))
+        .deadline(timeout)
+        .clock(clock);
+    let file = policy.retry(path.as_ref(), |path| open_write(path))?;
+    file.set_nonblocking(false)?;
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, MockClock};
+    use super::*;
+    use std::fs;
+    use std::time::Instant;
+
+    #[test]
+    fn backs_off_until_deadline() {
+        let file_name = "/tmp/retry-deadline.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let clock = Arc::new(MockClock::new());
+        let start = clock.now();
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(400))
+            .deadline(Duration::from_secs(1))
+            .clock(clock.clone());
+
+        let mut attempts: Vec<Instant> = Vec::new();
+        let err = policy
+            .retry(Path::new(file_name), |path| {
+                attempts.push(clock.now());
+                open_write(path)
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let offsets: Vec<u64> = attempts
+            .iter()
+            .map(|at| at.duration_since(start).as_millis() as u64)
+            .collect();
+        assert_eq!(offsets, vec![0, 100, 300, 700, 1000]);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn open_write_timeout_uses_clock() {
+        let file_name = "/tmp/retry-write-clock.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let clock = Arc::new(MockClock::new());
+        let start = clock.now();
+        let err = open_write_timeout_with_clock(file_name, Duration::from_secs(60), clock.clone())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(clock.now() - start, Duration::from_secs(60));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn unbounded_delay_saturates() {
+        let clock = Arc::new(MockClock::new());
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_nanos(1))
+            .max_delay(Duration::MAX)
+            .multiplier(1 << 31)
+            .deadline(Duration::from_secs(10_000_000_000))
+            .clock(clock);
+
+        let mut attempts = 0;
+        let err = policy
+            .retry_while(
+                || -> io::Result<()> {
+                    attempts += 1;
+                    Err(io::Error::from(io::ErrorKind::WouldBlock))
+                },
+                |err| err.kind() == io::ErrorKind::WouldBlock,
+                |_| io::ErrorKind::TimedOut.into(),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(attempts, 5);
+    }
+
+    #[test]
+    fn open_read_timeout_waits_for_writer() {
+        let file_name = "/tmp/retry-read-timeout.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let err = open_read_timeout(file_name, Duration::from_millis(20)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(
+            PipeError::of(&err).as_ref().and_then(PipeError::path),
+            Some(Path::new(file_name))
+        );
+
+        let writer = thread::spawn(move || {
+            open_write_timeout(file_name, Duration::from_secs(5))
+                .expect("could not open fifo for writing")
+        });
+        let read_file = open_read_timeout(file_name, Duration::from_secs(5))
+            .expect("could not open fifo for reading");
+        let write_file = writer.join().unwrap();
+        assert!(!read_file.is_nonblocking().unwrap());
+        assert!(!write_file.is_nonblocking().unwrap());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn open_read_timeout_after_removal() {
+        let file_name = "/tmp/retry-read-removed.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let remover = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            fs::remove_file(file_name).expect("could not remove fifo");
+        });
+        let err = open_read_timeout(file_name, Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        remover.join().unwrap();
+    }
+
+    #[test]
+    fn open_write_timeout_without_reader() {
+        let file_name = "/tmp/retry-write-timeout.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let err = open_write_timeout(file_name, Duration::from_millis(20)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(
+            err.to_string(),
+            "timed out after 20ms waiting for /tmp/retry-write-timeout.pipe"
+        );
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn gives_up_on_other_errors() {
+        let policy = RetryPolicy::new().clock(Arc::new(MockClock::new()));
+        let err = open_write_retry("/tmp/retry-missing.pipe", &policy).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
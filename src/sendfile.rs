@@ -0,0 +1,228 @@
+//! Provides `send_file`, which streams a regular file into a pipe with
+//! kernel-side copies where the system has them.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd};
+
+/// How many bytes are copied by one call into the kernel.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// How many bytes the read and write fallback moves at a time.
+const FALLBACK_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Streams `file` into `pipe`, starting at `offset`, until the end of the
+/// file. Returns how many bytes were sent by this call.
+///
+/// On Linux the data is copied inside the kernel with `sendfile`, so it
+/// never passes through a userspace buffer. Elsewhere, or where `sendfile`
+/// refuses the file, it is read with `pread` and written with `write`.
+///
+/// `offset` is advanced past every byte which reached the pipe, also when
+/// an error is returned, so a transfer which fails part-way, such as with
+/// `WouldBlock` on a non-blocking pipe, can be resumed by calling this
+/// again. The file position of `file` is not used or changed.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::Read;
+/// use std::thread;
+/// use unix_named_pipe::FileFIFOExt;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # let artifact_name = &unix_named_pipe::unique_path("artifact");
+/// # fs::write(artifact_name, vec![7; 200_000]).unwrap();
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let mut reader = unix_named_pipe::open_read(file_name).unwrap();
+/// let consumer = thread::spawn(move || {
+///     let mut received = Vec::new();
+///     reader.read_to_end(&mut received).map(|_| received)
+/// });
+///
+/// let writer = unix_named_pipe::open_write(file_name).unwrap();
+/// writer.set_nonblocking(false).unwrap();
+/// let artifact = fs::File::open(artifact_name).unwrap();
+/// let mut offset = 0;
+/// let sent = unix_named_pipe::send_file(&artifact, &writer, &mut offset)
+///     .expect("could not send artifact");
+/// assert_eq!(sent, 200_000);
+/// assert_eq!(offset, 200_000);
+/// # drop(writer);
+/// # let _ = consumer.join();
+/// # fs::remove_file(file_name).unwrap();
+/// # fs::remove_file(artifact_name).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - If `pipe` is non-blocking and full, `Err(io::ErrorKind::WouldBlock)`
+///   will be returned.
+/// - If the reader has gone away, `Err(io::ErrorKind::BrokenPipe)` will be
+///   returned, with the raw `EPIPE` code.
+/// - Any other error returned by `sendfile`, `pread` or `write`.
+pub fn send_file<F: AsFd>(file: &File, pipe: F, offset: &mut u64) -> io::Result<u64> {
+    let pipe = pipe.as_fd();
+    let mut in_kernel = cfg!(target_os = "linux");
+    let mut sent = 0;
+    loop {
+        let result = if in_kernel {
+            match kernel_copy(file, pipe, *offset) {
+                Err(ref err) if fallback_needed(err) => {
+                    in_kernel = false;
+                    continue;
+                }
+                result => result,
+            }
+        } else {
+            buffered_copy(file, pipe, offset, &mut sent)
+        };
+
+        match result {
+            Ok(0) => return Ok(sent),
+            Ok(copied) => {
+                *offset += copied as u64;
+                sent += copied as u64;
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Returns whether `sendfile` cannot handle this pair of files at all.
+fn fallback_needed(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP)
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn kernel_copy(file: &File, pipe: BorrowedFd<'_>, offset: u64) -> io::Result<usize> {
+    let mut offset = offset as libc::off_t;
+    let copied =
+        unsafe { libc::sendfile(pipe.as_raw_fd(), file.as_raw_fd(), &mut offset, CHUNK_SIZE) };
+    if copied == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(copied as usize)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn kernel_copy(_file: &File, _pipe: BorrowedFd<'_>, _offset: u64) -> io::Result<usize> {
+    Err(io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+/// Reads one buffer of `file` at `offset` and writes it to `pipe`. Partial
+/// writes are accounted for in `offset` and `sent` directly, so that only
+/// the final write of the buffer is returned as its result.
+fn buffered_copy(
+    file: &File,
+    pipe: BorrowedFd<'_>,
+    offset: &mut u64,
+    sent: &mut u64,
+) -> io::Result<usize> {
+    let mut buf = vec![0; FALLBACK_BUFFER_SIZE];
+    let read = file.read_at(&mut buf, *offset)?;
+    let mut pending = &buf[..read];
+    while !pending.is_empty() {
+        let written = unsafe {
+            libc::write(
+                pipe.as_raw_fd(),
+                pending.as_ptr() as *const libc::c_void,
+                pending.len(),
+            )
+        };
+        if written == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        let written = written as usize;
+        if written == pending.len() {
+            return Ok(written);
+        }
+        *offset += written as u64;
+        *sent += written as u64;
+        pending = &pending[written..];
+    }
+
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write, FileFIFOExt};
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn resumes_after_full_pipe() {
+        let file_name = "/tmp/sendfile.pipe";
+        let data_name = "/tmp/sendfile.data";
+        create(file_name, None).expect("could not create fifo");
+        let data: Vec<u8> = (0..300_000).map(|i| i as u8).collect();
+        fs::write(data_name, &data).expect("could not write data to file");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let source = File::open(data_name).unwrap();
+
+        let mut offset = 0;
+        let mut received = Vec::new();
+        loop {
+            match send_file(&source, &writer, &mut offset) {
+                Ok(_) => break,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => panic!("could not send file: {}", err),
+            }
+            assert!(offset > 0 && offset < data.len() as u64);
+            let mut buf = vec![0; reader.bytes_available().unwrap()];
+            reader.read_exact(&mut buf).unwrap();
+            received.extend_from_slice(&buf);
+        }
+        drop(writer);
+        reader.set_nonblocking(false).unwrap();
+        reader.read_to_end(&mut received).unwrap();
+
+        assert_eq!(offset, data.len() as u64);
+        assert!(received == data);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+        fs::remove_file(data_name).expect("could not remove file");
+    }
+
+    #[test]
+    fn falls_back_to_buffered_copy() {
+        let file_name = "/tmp/sendfile-fallback.pipe";
+        let data_name = "/tmp/sendfile-fallback.data";
+        create(file_name, None).expect("could not create fifo");
+        fs::write(data_name, b"fallback").expect("could not write data to file");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let source = File::open(data_name).unwrap();
+
+        let mut offset = 4;
+        let mut sent = 0;
+        assert_eq!(
+            buffered_copy(&source, writer.as_fd(), &mut offset, &mut sent).unwrap(),
+            4
+        );
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"back");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+        fs::remove_file(data_name).expect("could not remove file");
+    }
+}
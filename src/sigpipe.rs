@@ -0,0 +1,158 @@
+//! Provides writes to a named pipe which cannot raise `SIGPIPE`, for
+//! processes where the signal would otherwise kill them when the reader
+//! goes away mid-stream.
+//!
+//! Rust programs ignore `SIGPIPE` from `main` on already, but a library
+//! loaded into a C program, or a binary built with `-Zon-broken-pipe=kill`,
+//! gets the default action of terminating.
+
+use libc::{c_int, sigset_t, SIGPIPE};
+use std::io::{self, IoSlice};
+use std::mem;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd};
+use std::ptr;
+
+/// Ignores `SIGPIPE` for the whole process, so that writing to a pipe
+/// without a reader fails with `EPIPE` instead of killing the process.
+///
+/// This changes the signal disposition for every thread, including any a
+/// host program relies on. `write_without_sigpipe` and
+/// `NamedPipe::suppress_sigpipe` avoid that.
+pub fn ignore_sigpipe() -> io::Result<()> {
+    if unsafe { libc::signal(SIGPIPE, libc::SIG_IGN) } == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Writes `buf` to `fd` with `SIGPIPE` blocked for the calling thread, so
+/// a reader going away cannot raise the signal. Any `SIGPIPE` the write
+/// causes is consumed before the old signal mask is restored.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::PipeError;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let reader = unix_named_pipe::open_read(file_name).unwrap();
+/// let writer = unix_named_pipe::open_write(file_name).unwrap();
+/// drop(reader);
+///
+/// let err = unix_named_pipe::write_without_sigpipe(&writer, b"lost").unwrap_err();
+/// assert!(matches!(PipeError::of(&err), Some(PipeError::ReaderGone)));
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - If the reader has gone away, `Err(io::ErrorKind::BrokenPipe)` will be
+///   returned, with the raw `EPIPE` code.
+/// - Any other error returned by `write`.
+pub fn write_without_sigpipe<F: AsFd>(fd: F, buf: &[u8]) -> io::Result<usize> {
+    write_blocking_sigpipe(fd.as_fd(), buf)
+}
+
+/// Writes like `write_without_sigpipe`.
+pub(crate) fn write_blocking_sigpipe(fd: BorrowedFd<'_>, buf: &[u8]) -> io::Result<usize> {
+    blocking_sigpipe(|| unsafe {
+        libc::write(
+            fd.as_raw_fd(),
+            buf.as_ptr() as *const libc::c_void,
+            buf.len(),
+        )
+    })
+}
+
+/// Writes `bufs` to `fd` with a single `writev`, with `SIGPIPE` blocked as
+/// in `write_blocking_sigpipe`.
+pub(crate) fn writev_blocking_sigpipe(
+    fd: BorrowedFd<'_>,
+    bufs: &[IoSlice<'_>],
+) -> io::Result<usize> {
+    // `IoSlice` is guaranteed to have the layout of `iovec` on Unix.
+    blocking_sigpipe(|| unsafe {
+        libc::writev(
+            fd.as_raw_fd(),
+            bufs.as_ptr() as *const libc::iovec,
+            bufs.len().min(c_int::MAX as usize) as c_int,
+        )
+    })
+}
+
+/// Runs the write `f` with `SIGPIPE` blocked for the calling thread, and
+/// consumes any `SIGPIPE` it causes before the old signal mask is restored.
+fn blocking_sigpipe<F: FnOnce() -> isize>(f: F) -> io::Result<usize> {
+    unsafe {
+        let mut sigpipe: sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut sigpipe);
+        libc::sigaddset(&mut sigpipe, SIGPIPE);
+
+        // A SIGPIPE which was already pending belongs to somebody else and
+        // must survive this write.
+        let mut pending: sigset_t = mem::zeroed();
+        libc::sigpending(&mut pending);
+        let was_pending = libc::sigismember(&pending, SIGPIPE) == 1;
+
+        let mut old_mask: sigset_t = mem::zeroed();
+        let errno = libc::pthread_sigmask(libc::SIG_BLOCK, &sigpipe, &mut old_mask);
+        if errno != 0 {
+            return Err(io::Error::from_raw_os_error(errno));
+        }
+
+        let written = f();
+        let result = if written == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(written as usize)
+        };
+
+        if !was_pending {
+            libc::sigpending(&mut pending);
+            if libc::sigismember(&pending, SIGPIPE) == 1 {
+                let mut signal = 0;
+                libc::sigwait(&sigpipe, &mut signal);
+            }
+        }
+        libc::pthread_sigmask(libc::SIG_SETMASK, &old_mask, ptr::null_mut());
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write, PipeError};
+    use super::*;
+    use libc::EPIPE;
+    use std::fs;
+
+    #[test]
+    fn consumes_sigpipe() {
+        let file_name = "/tmp/sigpipe.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        assert_eq!(write_without_sigpipe(&writer, b"kept").unwrap(), 4);
+        drop(reader);
+
+        let err = write_without_sigpipe(&writer, b"lost").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(err.raw_os_error(), Some(EPIPE));
+        assert!(matches!(PipeError::of(&err), Some(PipeError::ReaderGone)));
+
+        let pending = unsafe {
+            let mut pending: sigset_t = mem::zeroed();
+            libc::sigpending(&mut pending);
+            libc::sigismember(&pending, SIGPIPE)
+        };
+        assert_eq!(pending, 0);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}
@@ -0,0 +1,120 @@
+//! Provides zero-copy transfers with `splice(2)` and `tee(2)`, behind the
+//! `splice` feature. The kernel moves pages between the pipe buffer and the
+//! other file directly, so the data never passes through a userspace buffer.
+
+use libc::{loff_t, SPLICE_F_MOVE};
+use std::io;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+use std::ptr;
+
+/// Moves up to `len` bytes from `src` to `dst` with `splice`, one of which
+/// must be a pipe. Interrupted calls are retried.
+pub(crate) fn splice(src: BorrowedFd<'_>, dst: BorrowedFd<'_>, len: usize) -> io::Result<usize> {
+    loop {
+        let moved = unsafe {
+            libc::splice(
+                src.as_raw_fd(),
+                ptr::null_mut::<loff_t>(),
+                dst.as_raw_fd(),
+                ptr::null_mut::<loff_t>(),
+                len,
+                SPLICE_F_MOVE,
+            )
+        };
+        if moved == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        return Ok(moved as usize);
+    }
+}
+
+/// Copies up to `len` bytes from the pipe `src` into the pipe `dst` with
+/// `tee`, leaving them in `src`. Interrupted calls are retried.
+pub(crate) fn tee(src: BorrowedFd<'_>, dst: BorrowedFd<'_>, len: usize) -> io::Result<usize> {
+    loop {
+        let copied = unsafe { libc::tee(src.as_raw_fd(), dst.as_raw_fd(), len, 0) };
+        if copied == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        return Ok(copied as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write, FileFIFOExt};
+    use std::fs;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::unix::io::AsFd;
+
+    #[test]
+    fn splices_between_fifo_and_file() {
+        let file_name = "/tmp/splice.pipe";
+        let data_name = "/tmp/splice.data";
+        create(file_name, None).expect("could not create fifo");
+        fs::write(data_name, b"spliced").expect("could not write data to file");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = open_write(file_name).expect("could not open fifo for writing");
+        let source = fs::File::open(data_name).unwrap();
+        assert_eq!(writer.splice_from(source.as_fd(), 64).unwrap(), 7);
+        let mut buf = [0; 7];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"spliced");
+
+        let mut sink = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(data_name)
+            .unwrap();
+        let mut writer = writer;
+        writer.write_all(b"back").unwrap();
+        assert_eq!(reader.splice_to(sink.as_fd(), 64).unwrap(), 4);
+        sink.seek(SeekFrom::Start(0)).unwrap();
+        let mut contents = String::new();
+        sink.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "back");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+        fs::remove_file(data_name).expect("could not remove file");
+    }
+
+    #[test]
+    fn tee_leaves_data_in_source() {
+        let source_name = "/tmp/tee-source.pipe";
+        let tap_name = "/tmp/tee-tap.pipe";
+        create(source_name, None).expect("could not create fifo");
+        create(tap_name, None).expect("could not create fifo");
+
+        let mut source = open_read(source_name).expect("could not open fifo for reading");
+        let mut producer = open_write(source_name).expect("could not open fifo for writing");
+        let mut tap = open_read(tap_name).expect("could not open fifo for reading");
+        let tap_writer = open_write(tap_name).expect("could not open fifo for writing");
+
+        producer.write_all(b"mirrored").unwrap();
+        assert_eq!(source.tee_to(tap_writer.as_fd(), 64).unwrap(), 8);
+
+        let mut buf = [0; 8];
+        tap.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"mirrored");
+        source.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"mirrored");
+
+        let regular = fs::File::open("/dev/null").unwrap();
+        assert!(regular.tee_to(tap_writer.as_fd(), 64).is_err());
+
+        fs::remove_file(source_name).expect("could not remove fifo");
+        fs::remove_file(tap_name).expect("could not remove fifo");
+    }
+}
@@ -0,0 +1,578 @@
+//! Provides `SpoolingWriter`, which holds messages in memory, and then in an
+//! optional journal file, while a named pipe has no reader or is full, and
+//! writes them in order once it can.
+
+use super::error::is_no_reader;
+use super::sigpipe::write_blocking_sigpipe;
+use super::{open_write, Backpressure, Clock, FileFIFOExt, PipeError, SystemClock};
+use libc::EPIPE;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many bytes a `SpoolingWriter` holds back by default.
+pub const DEFAULT_SPOOL_CAPACITY: usize = 1 << 20;
+
+/// How long a write blocked by a full spool waits before trying the pipe
+/// again.
+const BLOCK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A writer for a named pipe which keeps what cannot be written yet in
+/// memory, so a producer may start before its consumer.
+///
+/// Every write is treated as a message. While the pipe has no reader or is
+/// full, messages are queued, up to a capacity. Each later write or flush
+/// first writes as much of the queue as the pipe takes, so the reader sees
+/// the messages in the order they were written. A reader which goes away
+/// is waited for again, without `SIGPIPE` being raised.
+///
+/// Queued messages are lost when the writer is dropped; `flush` until
+/// `queued_bytes` is zero to make sure they have all been written. With a
+/// `journal`, messages which do not fit in memory are appended to a file
+/// instead, which outlives the writer.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::Write;
+/// use unix_named_pipe::SpoolingWriter;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let mut writer = SpoolingWriter::new(file_name);
+/// writer.write_all(b"sent before the consumer started").unwrap();
+/// assert_eq!(writer.queued_messages(), 1);
+///
+/// let reader = unix_named_pipe::open_read(file_name).unwrap();
+/// writer.flush().unwrap();
+/// assert_eq!(writer.queued_bytes(), 0);
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SpoolingWriter {
+    path: PathBuf,
+    pipe: Option<File>,
+    queue: VecDeque<Vec<u8>>,
+    written: usize,
+    queued: usize,
+    capacity: usize,
+    backpressure: Backpressure,
+    dropped: u64,
+    journal_path: Option<PathBuf>,
+    journal: Option<File>,
+    journal_len: u64,
+    replayed: u64,
+    clock: Arc<dyn Clock>,
+}
+
+impl SpoolingWriter {
+    /// Creates a writer for the pipe at `path`, holding back at most
+    /// `DEFAULT_SPOOL_CAPACITY` bytes. The pipe is not opened until the
+    /// first write.
+    pub fn new<P: AsRef<Path>>(path: P) -> SpoolingWriter {
+        SpoolingWriter {
+            path: path.as_ref().to_path_buf(),
+            pipe: None,
+            queue: VecDeque::new(),
+            written: 0,
+            queued: 0,
+            capacity: DEFAULT_SPOOL_CAPACITY,
+            backpressure: Backpressure::default(),
+            dropped: 0,
+            journal_path: None,
+            journal: None,
+            journal_len: 0,
+            replayed: 0,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets how many bytes may be held back at most.
+    pub fn capacity(mut self, bytes: usize) -> SpoolingWriter {
+        self.capacity = bytes;
+        self
+    }
+
+    /// Sets what a write does when its message does not fit in memory and
+    /// there is no journal, which defaults to `Backpressure::Error`.
+    /// `Backpressure::DropOldest` never drops a message which has been
+    /// written in part.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> SpoolingWriter {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Appends messages which do not fit in memory to the file at `path`,
+    /// and replays them into the pipe after those in memory.
+    ///
+    /// Once anything is in the journal, later messages go there too, so
+    /// that the order is kept. The journal is emptied again once it has
+    /// been replayed in full. Whatever a previous writer left in it is
+    /// replayed first, so a message which was being replayed when that
+    /// writer went away may be delivered twice, but none are lost.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate unix_named_pipe;
+    /// # use std::fs;
+    /// use std::io::Write;
+    /// use unix_named_pipe::SpoolingWriter;
+    ///
+    /// # let file_name = &unix_named_pipe::unique_path("fifo");
+    /// # let journal_name = &unix_named_pipe::unique_path("journal");
+    /// # unix_named_pipe::create(file_name, None).unwrap();
+    /// let mut writer = SpoolingWriter::new(file_name).capacity(0).journal(journal_name);
+    /// writer.write_all(b"kept on disk").unwrap();
+    /// assert_eq!(writer.journaled_bytes(), 12);
+    /// # fs::remove_file(file_name).unwrap();
+    /// # fs::remove_file(journal_name).unwrap();
+    /// ```
+    pub fn journal<P: AsRef<Path>>(mut self, path: P) -> SpoolingWriter {
+        self.journal_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Waits for a reader with `clock` instead of the system clock while a
+    /// blocked write has no pipe to poll.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> SpoolingWriter {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the path of the pipe.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns how many bytes are held back in memory, waiting to be
+    /// written.
+    pub fn queued_bytes(&self) -> usize {
+        self.queued
+    }
+
+    /// Returns how many bytes are held back in the journal, waiting to be
+    /// written. This is zero until the journal has been opened by the first
+    /// write or flush.
+    pub fn journaled_bytes(&self) -> u64 {
+        self.journal_len - self.replayed
+    }
+
+    /// Returns how many messages are held back, counting one which has
+    /// been written in part.
+    pub fn queued_messages(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns how many messages have been dropped because the spool was
+    /// full.
+    pub fn dropped_messages(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns whether the pipe is open at the moment.
+    pub fn is_connected(&self) -> bool {
+        self.pipe.is_some()
+    }
+
+    /// Opens the pipe if it is not open yet. Returns `false` if there is
+    /// still no reader.
+    fn connect(&mut self) -> io::Result<bool> {
+        if self.pipe.is_some() {
+            return Ok(true);
+        }
+
+        match open_write(&self.path) {
+            Ok(file) => {
+                self.pipe = Some(file);
+                Ok(true)
+            }
+            Err(ref err) if is_no_reader(err) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes `buf` to the open pipe, forgetting the pipe if the reader
+    /// has gone away.
+    fn write_pipe(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = match self.pipe {
+            Some(ref pipe) => write_blocking_sigpipe(pipe.as_fd(), buf),
+            None => return Err(io::ErrorKind::NotConnected.into()),
+        };
+
+        if let Err(ref err) = result {
+            if err.raw_os_error() == Some(EPIPE) {
+                self.pipe = None;
+            }
+        }
+
+        result
+    }
+
+    /// Opens the journal if there is one and it is not open yet, picking up
+    /// whatever a previous writer left in it.
+    fn open_journal(&mut self) -> io::Result<()> {
+        if let (None, Some(path)) = (&self.journal, &self.journal_path) {
+            let file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(path)?;
+            self.journal_len = file.metadata()?.len();
+            self.journal = Some(file);
+        }
+
+        Ok(())
+    }
+
+    /// Writes as much of the queue and then the journal as the pipe will
+    /// take. Returns `true` once the pipe is open and nothing is left to
+    /// write.
+    fn drain_queue(&mut self) -> io::Result<bool> {
+        self.open_journal()?;
+        if !self.connect()? {
+            return Ok(false);
+        }
+
+        while let Some(message) = self.queue.pop_front() {
+            match self.write_pipe(&message[self.written..]) {
+                Ok(written) => {
+                    self.written += written;
+                    self.queued -= written;
+                    if self.written < message.len() {
+                        self.queue.push_front(message);
+                    } else {
+                        self.written = 0;
+                    }
+                }
+                Err(err) => {
+                    self.queue.push_front(message);
+                    return match err.kind() {
+                        io::ErrorKind::Interrupted => continue,
+                        io::ErrorKind::WouldBlock => Ok(false),
+                        _ if err.raw_os_error() == Some(EPIPE) => Ok(false),
+                        _ => Err(err),
+                    };
+                }
+            }
+        }
+
+        self.replay_journal()
+    }
+
+    /// Replays as much of the journal into the pipe as it will take.
+    /// Returns `true` once nothing is left to replay.
+    fn replay_journal(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        while self.replayed < self.journal_len {
+            let count = match self.journal {
+                Some(ref file) => file.read_at(&mut chunk, self.replayed)?,
+                None => break,
+            };
+            if count == 0 {
+                break;
+            }
+
+            match self.write_pipe(&chunk[..count]) {
+                Ok(written) => self.replayed += written as u64,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(ref err) if err.raw_os_error() == Some(EPIPE) => return Ok(false),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let Some(ref file) = self.journal {
+            if self.journal_len > 0 {
+                file.set_len(0)?;
+            }
+        }
+        self.journal_len = 0;
+        self.replayed = 0;
+
+        Ok(true)
+    }
+
+    /// Queues `buf` as a message in memory if there is room for it and the
+    /// journal is empty, or else in the journal.
+    fn enqueue(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let fits = self.queued + buf.len() <= self.capacity;
+        let in_memory = fits && self.journaled_bytes() == 0;
+        if let (Some(ref mut file), false) = (&mut self.journal, in_memory) {
+            file.write_all(buf)?;
+            self.journal_len += buf.len() as u64;
+            return Ok(buf.len());
+        }
+
+        if !fits {
+            let backpressure = self.backpressure;
+            match backpressure {
+                Backpressure::DropOldest if self.drop_oldest(buf.len()) => {}
+                Backpressure::DropNewest | Backpressure::DropOldest => {
+                    self.dropped += 1;
+                    return Ok(buf.len());
+                }
+                Backpressure::Block | Backpressure::Error => {
+                    return Err(PipeError::SpoolFull {
+                        len: buf.len(),
+                        capacity: self.capacity,
+                    }
+                    .into())
+                }
+            }
+        }
+
+        self.queue.push_back(buf.to_vec());
+        self.queued += buf.len();
+
+        Ok(buf.len())
+    }
+}
+
+impl SpoolingWriter {
+    /// Drops the oldest messages which have not been written in part until
+    /// `len` more bytes fit. Returns whether they do.
+    fn drop_oldest(&mut self, len: usize) -> bool {
+        let oldest = if self.written > 0 { 1 } else { 0 };
+        while self.queued + len > self.capacity {
+            match self.queue.remove(oldest) {
+                Some(message) => {
+                    self.queued -= message.len();
+                    self.dropped += 1;
+                }
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Returns whether a message of `len` bytes can be held back now.
+    fn has_room(&self, len: usize) -> bool {
+        self.journal.is_some() || self.queued + len <= self.capacity
+    }
+
+    /// Waits a little for the pipe to take more of the queue.
+    fn wait(&self) -> io::Result<()> {
+        match self.pipe {
+            Some(ref pipe) => pipe.poll_writable(Some(BLOCK_INTERVAL)).map(|_| ()),
+            None => {
+                self.clock.sleep(BLOCK_INTERVAL);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Write for SpoolingWriter {
+    /// Writes `buf` to the pipe after the queue, or queues it if the pipe
+    /// cannot take it yet.
+    ///
+    /// # Errors
+    ///
+    /// - With `Backpressure::Error`, if `buf` has to be queued but does not
+    ///   fit in memory and there is no journal, `Err(io::ErrorKind::WouldBlock)`
+    ///   will be returned, carrying `PipeError::SpoolFull`. So it will with
+    ///   `Backpressure::Block` if `buf` is larger than the whole spool.
+    /// - Any other error returned by `open_write`, `write`, or writing the
+    ///   journal.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            if self.drain_queue()? {
+                match self.write_pipe(buf) {
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(ref err) if err.raw_os_error() == Some(EPIPE) => {}
+                    result => return result,
+                }
+            }
+
+            let blocks = self.backpressure == Backpressure::Block && buf.len() <= self.capacity;
+            if !blocks || self.has_room(buf.len()) {
+                return self.enqueue(buf);
+            }
+            self.wait()?;
+        }
+    }
+
+    /// Writes as much of the queue as the pipe will take. Messages which
+    /// still do not fit stay queued, and the journal is synced to disk.
+    fn flush(&mut self) -> io::Result<()> {
+        self.drain_queue()?;
+        match self.journal {
+            Some(ref file) if self.journal_len > 0 => file.sync_data(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, FileFIFOExt, MockClock};
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use std::thread;
+
+    #[test]
+    fn spools_until_reader_attaches() {
+        let file_name = "/tmp/spooling-writer.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut writer = SpoolingWriter::new(file_name).capacity(8);
+        assert_eq!(writer.write(b"one,").unwrap(), 4);
+        assert_eq!(writer.write(b"two,").unwrap(), 4);
+        let err = writer.write(b"three").unwrap_err();
+        assert!(matches!(
+            PipeError::of(&err),
+            Some(PipeError::SpoolFull {
+                len: 5,
+                capacity: 8
+            })
+        ));
+        assert_eq!(writer.queued_messages(), 2);
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        writer.write_all(b"three").unwrap();
+        assert_eq!(writer.queued_bytes(), 0);
+        let mut buf = [0; 13];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"one,two,three");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn spools_while_pipe_is_full() {
+        let file_name = "/tmp/spooling-writer-full.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = SpoolingWriter::new(file_name);
+        writer.write_all(b"connect").unwrap();
+        let capacity = reader.buffer_size().unwrap();
+        let filler = vec![0; capacity];
+        let written = writer.write(&filler).unwrap();
+        writer.write_all(b"later").unwrap();
+        assert!(writer.queued_bytes() >= 5);
+
+        let mut received = Vec::new();
+        while received.len() < 7 + written + 5 {
+            reader.read_available(&mut received).unwrap();
+            writer.flush().unwrap();
+        }
+        assert_eq!(writer.queued_bytes(), 0);
+        assert!(received.ends_with(b"later"));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn backpressure_when_spool_is_full() {
+        let file_name = "/tmp/spooling-backpressure.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut writer = SpoolingWriter::new(file_name)
+            .capacity(6)
+            .backpressure(Backpressure::DropNewest);
+        writer.write_all(b"a,").unwrap();
+        writer.write_all(b"b,").unwrap();
+        writer.write_all(b"c,").unwrap();
+        writer.write_all(b"d,").unwrap();
+        assert_eq!(writer.dropped_messages(), 1);
+
+        let mut writer = writer.backpressure(Backpressure::DropOldest);
+        writer.write_all(b"e,").unwrap();
+        writer.write_all(b"f,g,").unwrap();
+        assert_eq!(writer.dropped_messages(), 4);
+        assert_eq!(writer.queued_bytes(), 6);
+
+        let mut writer = writer.backpressure(Backpressure::Block);
+        let consumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let mut reader = open_read(file_name).expect("could not open fifo for reading");
+            let mut received = Vec::new();
+            while received.len() < 8 {
+                reader.read_available(&mut received).unwrap();
+                thread::sleep(Duration::from_millis(1));
+            }
+            reader.set_nonblocking(false).unwrap();
+            reader.read_to_end(&mut received).map(|_| received)
+        });
+        writer.write_all(b"h,").unwrap();
+        drop(writer);
+        assert_eq!(consumer.join().unwrap().unwrap(), b"e,f,g,h,");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn blocked_write_waits_with_clock() {
+        let file_name = "/tmp/spooling-clock.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let clock = Arc::new(MockClock::new());
+        let start = clock.now();
+        let mut writer = SpoolingWriter::new(file_name)
+            .capacity(2)
+            .backpressure(Backpressure::Block)
+            .clock(clock.clone());
+        writer.write_all(b"a,").unwrap();
+        let consumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let mut reader = open_read(file_name).expect("could not open fifo for reading");
+            let mut received = Vec::new();
+            while received.len() < 2 {
+                reader.read_available(&mut received).unwrap();
+                thread::sleep(Duration::from_millis(1));
+            }
+            reader.set_nonblocking(false).unwrap();
+            reader.read_to_end(&mut received).map(|_| received)
+        });
+        writer.write_all(b"b,").unwrap();
+        drop(writer);
+        assert_eq!(consumer.join().unwrap().unwrap(), b"a,b,");
+        assert!(clock.now() - start >= BLOCK_INTERVAL);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn overflows_into_journal() {
+        let file_name = "/tmp/spooling-journal.pipe";
+        let journal_name = "/tmp/spooling-journal.journal";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut writer = SpoolingWriter::new(file_name)
+            .capacity(4)
+            .journal(journal_name);
+        writer.write_all(b"mem,").unwrap();
+        writer.write_all(b"disk,").unwrap();
+        writer.write_all(b"d").unwrap();
+        assert_eq!(writer.queued_bytes(), 4);
+        assert_eq!(writer.journaled_bytes(), 6);
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut writer = SpoolingWriter::new(file_name).journal(journal_name);
+        writer.write_all(b"new").unwrap();
+        assert_eq!(writer.journaled_bytes(), 9);
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        writer.flush().unwrap();
+        assert_eq!(writer.journaled_bytes(), 0);
+        assert_eq!(fs::metadata(journal_name).unwrap().len(), 0);
+        let mut buf = [0; 9];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"disk,dnew");
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+        fs::remove_file(journal_name).expect("could not remove journal");
+    }
+}
@@ -0,0 +1,139 @@
+//! Provides `FifoStat`, a snapshot of everything worth knowing about an open
+//! FIFO, for diagnostics.
+
+use super::Mode;
+use std::fmt;
+use std::fs::Metadata;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The state of an open FIFO, as returned by `FileFIFOExt::stat`.
+///
+/// The `Display` implementation prints it on one line, for logs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct FifoStat {
+    /// Whether the file really is a FIFO.
+    pub is_fifo: bool,
+    /// The permission bits of the FIFO.
+    pub mode: Mode,
+    /// The user id of the owner.
+    pub uid: u32,
+    /// The group id of the owning group.
+    pub gid: u32,
+    /// The inode number.
+    pub ino: u64,
+    /// The id of the device the FIFO lives on.
+    pub dev: u64,
+    /// When the FIFO was last read from.
+    pub accessed: SystemTime,
+    /// When the FIFO was last written to.
+    pub modified: SystemTime,
+    /// When the inode last changed, such as by a write or `chmod`.
+    pub changed: SystemTime,
+    /// The capacity of the pipe buffer, as with `FileFIFOExt::buffer_size`.
+    /// Only known on Linux.
+    pub capacity: Option<usize>,
+    /// The number of bytes waiting to be read, as with
+    /// `FileFIFOExt::bytes_available`, if it could be queried.
+    pub buffered: Option<usize>,
+}
+
+impl FifoStat {
+    pub(crate) fn new(
+        metadata: &Metadata,
+        capacity: Option<usize>,
+        buffered: Option<usize>,
+    ) -> FifoStat {
+        FifoStat {
+            is_fifo: metadata.file_type().is_fifo(),
+            mode: Mode::from_bits(metadata.mode() & 0o777).unwrap_or_default(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            ino: metadata.ino(),
+            dev: metadata.dev(),
+            accessed: timestamp(metadata.atime(), metadata.atime_nsec()),
+            modified: timestamp(metadata.mtime(), metadata.mtime_nsec()),
+            changed: timestamp(metadata.ctime(), metadata.ctime_nsec()),
+            capacity,
+            buffered,
+        }
+    }
+}
+
+impl fmt::Display for FifoStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} dev={} ino={} mode={:o} uid={} gid={}",
+            if self.is_fifo { "fifo" } else { "not a fifo" },
+            self.dev,
+            self.ino,
+            self.mode.bits(),
+            self.uid,
+            self.gid
+        )?;
+        if let Some(buffered) = self.buffered {
+            write!(f, " buffered={}", buffered)?;
+        }
+        if let Some(capacity) = self.capacity {
+            write!(f, " capacity={}", capacity)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Turns seconds and nanoseconds relative to the epoch into a `SystemTime`.
+fn timestamp(secs: i64, nsecs: i64) -> SystemTime {
+    let nsecs = Duration::from_nanos(nsecs as u64);
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64) + nsecs
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + nsecs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write, FileFIFOExt};
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn snapshots_fifo_state() {
+        let file_name = "/tmp/fifo-stat.pipe";
+        create(file_name, Some(0o640)).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = open_write(file_name).expect("could not open fifo for writing");
+        writer.write_all(b"queued").unwrap();
+
+        let stat = reader.stat().expect("could not stat fifo");
+        let metadata = fs::metadata(file_name).unwrap();
+        assert!(stat.is_fifo);
+        assert_eq!(stat.mode.bits() & 0o600, 0o600);
+        assert_eq!(stat.ino, metadata.ino());
+        assert_eq!(stat.uid, metadata.uid());
+        assert_eq!(stat.buffered, Some(6));
+        assert_eq!(stat.modified, metadata.modified().unwrap());
+        #[cfg(target_os = "linux")]
+        assert_eq!(stat.capacity, Some(reader.buffer_size().unwrap()));
+
+        let line = stat.to_string();
+        assert!(line.starts_with("fifo dev="));
+        assert!(line.contains(" buffered=6"));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn converts_timestamps() {
+        assert_eq!(timestamp(0, 0), UNIX_EPOCH);
+        assert_eq!(
+            timestamp(-1, 500_000_000),
+            UNIX_EPOCH - Duration::from_millis(500)
+        );
+    }
+}
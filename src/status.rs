@@ -0,0 +1,96 @@
+//! Provides the outcomes of non-blocking FIFO reads, writes and readiness
+//! waits, which plain `io::Result`s report ambiguously, and the `Backlog` of
+//! a pipe.
+
+/// The outcome of `FileFIFOExt::read_status`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReadStatus {
+    /// This many bytes were read into the buffer.
+    Data(usize),
+    /// A writer has the pipe open, but has not written anything new yet.
+    /// Retry once the descriptor polls readable.
+    Empty,
+    /// No writer has the pipe open, so there is nothing left to read until
+    /// one opens it again. This is also the status of a pipe no writer has
+    /// opened yet.
+    WriterClosed,
+}
+
+/// The outcome of `FileFIFOExt::try_write`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WriteStatus {
+    /// This many bytes were written, which may be fewer than were given.
+    Written(usize),
+    /// The pipe buffer is full. Retry once the descriptor polls writable.
+    Full,
+    /// The reader has closed its end of the pipe, so nothing written now
+    /// would ever be read.
+    NoReader,
+}
+
+/// The outcome of `FileFIFOExt::poll_readable` and
+/// `FileFIFOExt::poll_writable`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Readiness {
+    /// The pipe can be used without blocking: there is data to read, or
+    /// room to write.
+    Ready,
+    /// The other end of the pipe has been closed. A reader gets this once
+    /// every writer is gone and all data has been read, a writer as soon as
+    /// the reader is gone, as nothing written could be read any more.
+    HangUp,
+    /// The timeout expired first.
+    TimedOut,
+}
+
+/// How full a pipe buffer is, as returned by `FileFIFOExt::backlog`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Backlog {
+    /// The number of bytes waiting to be read.
+    pub used: usize,
+    /// The number of bytes the pipe buffer holds.
+    pub capacity: usize,
+}
+
+impl Backlog {
+    /// Returns the share of the buffer in use, from `0.0` for an empty pipe
+    /// to `1.0` for a full one. Writes to a non-blocking pipe start failing
+    /// with `WouldBlock` as this approaches `1.0`.
+    ///
+    /// Outside of Linux the capacity is only a lower bound, so the value is
+    /// capped at `1.0`.
+    pub fn fill_level(&self) -> f32 {
+        if self.capacity == 0 {
+            return 1.0;
+        }
+
+        (self.used as f32 / self.capacity as f32).min(1.0)
+    }
+
+    /// Returns how many more bytes fit into the buffer.
+    pub fn free(&self) -> usize {
+        self.capacity.saturating_sub(self.used)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_level() {
+        let backlog = Backlog {
+            used: 16384,
+            capacity: 65536,
+        };
+        assert_eq!(backlog.fill_level(), 0.25);
+        assert_eq!(backlog.free(), 49152);
+
+        let overfull = Backlog {
+            used: 1024,
+            capacity: 512,
+        };
+        assert_eq!(overfull.fill_level(), 1.0);
+        assert_eq!(overfull.free(), 0);
+    }
+}
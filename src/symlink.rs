@@ -0,0 +1,171 @@
+//! Provides `SymlinkPolicy`, which controls whether creating and opening a
+//! named pipe may pass through symbolic links.
+
+use super::audit::{self, AuditAction};
+use super::{create, make_fifo_at, mode_bits, open_at, open_at_exact, IntoMode};
+use libc::{c_int, AT_FDCWD, O_DIRECTORY, O_NOFOLLOW};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Component, Path};
+
+/// The flags each directory on the way to a pipe is opened with when no
+/// symbolic links are allowed. `O_PATH` needs only search permission.
+#[cfg(target_os = "linux")]
+const WALK_FLAGS: c_int = libc::O_PATH | O_DIRECTORY | O_NOFOLLOW;
+#[cfg(not(target_os = "linux"))]
+const WALK_FLAGS: c_int = libc::O_RDONLY | O_DIRECTORY | O_NOFOLLOW;
+
+/// Which symbolic links a path may contain when a pipe is created or opened
+/// with it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SymlinkPolicy {
+    /// Follow symbolic links anywhere in the path, as `open` does.
+    #[default]
+    FollowAll,
+    /// Refuse a symbolic link as the final component of the path, with
+    /// `O_NOFOLLOW`. Links in the directories leading up to it are followed.
+    RejectFinalSymlink,
+    /// Refuse a symbolic link anywhere in the path. Every directory on the
+    /// way is opened with `O_NOFOLLOW` in turn, and the pipe is created or
+    /// opened relative to the last of them.
+    RejectAnySymlink,
+}
+
+/// Creates a new named pipe at `path` like `create`, refusing symbolic
+/// links as described by `policy`.
+///
+/// `mkfifo` never follows a symbolic link in the final component, so only
+/// `SymlinkPolicy::RejectAnySymlink` differs from `create`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use unix_named_pipe::SymlinkPolicy;
+///
+/// # let dir = unix_named_pipe::unique_path("fifo-dir");
+/// # fs::create_dir_all(&dir).unwrap();
+/// unix_named_pipe::create_with_policy(dir.join("app.pipe"), None, SymlinkPolicy::RejectAnySymlink)
+///     .expect("could not create fifo");
+/// # fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - With `SymlinkPolicy::RejectAnySymlink`, if a directory in `path` is a
+///   symbolic link, the `ENOTDIR` or `ELOOP` error will be returned.
+/// - Any error returned by `create`.
+pub fn create_with_policy<P: AsRef<Path>, M: IntoMode>(
+    path: P,
+    mode: M,
+    policy: SymlinkPolicy,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    if policy != SymlinkPolicy::RejectAnySymlink {
+        return create(path, mode);
+    }
+
+    let mode = mode_bits(mode)?;
+    let result = walk_parents(path)
+        .and_then(|(parent, name)| make_fifo_at(dir_fd(&parent), Path::new(name), mode));
+    audit::record(AuditAction::Create { mode }, path, &result);
+
+    result
+}
+
+/// Opens `path` with `openat` and exactly `flags`, plus `O_NOFOLLOW`, after
+/// walking its directories without following symbolic links.
+pub(crate) fn open_no_symlinks(path: &Path, flags: c_int) -> io::Result<File> {
+    let (parent, name) = walk_parents(path)?;
+    open_at_exact(dir_fd(&parent), Path::new(name), flags | O_NOFOLLOW)
+}
+
+fn dir_fd(dir: &Option<File>) -> RawFd {
+    dir.as_ref().map_or(AT_FDCWD, |dir| dir.as_raw_fd())
+}
+
+/// Opens every directory leading up to the final component of `path`
+/// with `O_NOFOLLOW`, returning the innermost one and the final component.
+/// `None` stands for the current directory.
+fn walk_parents(path: &Path) -> io::Result<(Option<File>, &OsStr)> {
+    let mut names = Vec::new();
+    let mut current: Option<File> = None;
+    for component in path.components() {
+        match component {
+            Component::RootDir => current = Some(open_at(AT_FDCWD, Path::new("/"), WALK_FLAGS)?),
+            Component::CurDir => {}
+            Component::ParentDir => names.push(OsStr::new("..")),
+            Component::Normal(name) => names.push(name),
+            Component::Prefix(_) => return Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        }
+    }
+
+    let (last, parents) = match names.split_last() {
+        Some(split) => split,
+        None => return Err(io::Error::from_raw_os_error(libc::ENOENT)),
+    };
+
+    for name in parents {
+        let dir = open_at(dir_fd(&current), Path::new(name), WALK_FLAGS)?;
+        current = Some(dir);
+    }
+
+    Ok((current, last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open_read, Direction, PipeOptions};
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn rejects_symlinked_directory() {
+        let dir = "/tmp/symlink-policy";
+        let link = "/tmp/symlink-policy.link";
+        fs::create_dir_all(dir).expect("could not create test dir");
+        symlink(dir, link).expect("could not create symlink");
+
+        let linked = Path::new(link).join("app.pipe");
+        let err = create_with_policy(&linked, None, SymlinkPolicy::RejectAnySymlink).unwrap_err();
+        assert!(
+            err.raw_os_error() == Some(libc::ENOTDIR) || err.raw_os_error() == Some(libc::ELOOP)
+        );
+
+        create_with_policy(&linked, None, SymlinkPolicy::RejectFinalSymlink)
+            .expect("could not create fifo");
+        let direct = Path::new(dir).join("app.pipe");
+        let err = PipeOptions::new(Direction::Read)
+            .symlinks(SymlinkPolicy::RejectAnySymlink)
+            .open(&linked)
+            .unwrap_err();
+        assert!(
+            err.raw_os_error() == Some(libc::ENOTDIR) || err.raw_os_error() == Some(libc::ELOOP)
+        );
+        assert!(PipeOptions::new(Direction::Read)
+            .symlinks(SymlinkPolicy::RejectAnySymlink)
+            .open(&direct)
+            .is_ok());
+        assert!(open_read(&linked).is_ok());
+
+        fs::remove_file(link).expect("could not remove symlink");
+        fs::remove_dir_all(dir).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn walks_relative_paths() {
+        let dir = "/tmp/symlink-policy-relative";
+        fs::create_dir_all(dir).expect("could not create test dir");
+
+        let path = Path::new(dir).join("../symlink-policy-relative/./app.pipe");
+        create_with_policy(&path, None, SymlinkPolicy::RejectAnySymlink)
+            .expect("could not create fifo");
+        assert!(Path::new(dir).join("app.pipe").exists());
+
+        fs::remove_dir_all(dir).expect("could not remove test dir");
+    }
+}
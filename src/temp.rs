@@ -0,0 +1,208 @@
+//! Provides `TempFifo`, a uniquely named FIFO which is removed again when it
+//! goes out of scope.
+
+use super::{create, remove_if_fifo, Direction, IntoMode, Mode, PipeOptions};
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many names are tried before giving up on finding a free one.
+const MAX_ATTEMPTS: usize = 128;
+
+/// A named pipe with a random name in a temporary directory, removed when
+/// dropped, in the spirit of `tempfile::NamedTempFile`.
+///
+/// The pipe is created with mode `0o600`, so only the current user can open
+/// it. This also makes it a convenient fixture for tests which run in
+/// parallel, as every test gets a pipe of its own.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// use unix_named_pipe::{Direction, TempFifo};
+///
+/// let fifo = TempFifo::new().expect("could not create temporary fifo");
+/// let reader = fifo.open(Direction::Read).expect("could not open fifo for reading");
+/// let path = fifo.path().to_path_buf();
+/// drop(fifo);
+/// assert!(!path.exists());
+/// ```
+#[derive(Debug)]
+pub struct TempFifo {
+    path: PathBuf,
+}
+
+impl TempFifo {
+    /// Creates a temporary FIFO in `std::env::temp_dir()`.
+    pub fn new() -> io::Result<TempFifo> {
+        TempFifo::new_in(env::temp_dir())
+    }
+
+    /// Creates a temporary FIFO in the directory `dir`.
+    pub fn new_in<D: AsRef<Path>>(dir: D) -> io::Result<TempFifo> {
+        let path = create_temp_in(dir, Some(0o600))?;
+
+        Ok(TempFifo { path })
+    }
+
+    /// Returns the path of the FIFO.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the given end of the FIFO with the default options of
+    /// `open_read` and `open_write`.
+    pub fn open(&self, direction: Direction) -> io::Result<File> {
+        PipeOptions::new(direction).open(&self.path)
+    }
+
+    /// Keeps the FIFO on disk instead of removing it, returning its path.
+    pub fn keep(mut self) -> PathBuf {
+        let path = mem::take(&mut self.path);
+        mem::forget(self);
+
+        path
+    }
+}
+
+impl Drop for TempFifo {
+    fn drop(&mut self) {
+        let _ = remove_if_fifo(&self.path);
+    }
+}
+
+/// Creates a new named pipe with a random name in `dir` and returns its
+/// path. The pipe is not removed again; use `TempFifo` for that.
+/// Pipe will be created with mode `mode` if given, else `0o644` will be used.
+///
+/// Names are picked until one is free, so this never reuses an existing
+/// file, even when several processes create pipes in `dir` at once.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # let dir = unix_named_pipe::unique_path("fifo-dir");
+/// # fs::create_dir_all(&dir).unwrap();
+/// let path = unix_named_pipe::create_temp_in(&dir, Some(0o600))
+///     .expect("could not create fifo");
+/// assert!(path.starts_with(&dir));
+/// # fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// - If no free name is found after many attempts,
+///   `Err(io::ErrorKind::AlreadyExists)` will be returned.
+/// - Any other error returned by `create`.
+pub fn create_temp_in<D: AsRef<Path>, M: IntoMode>(dir: D, mode: M) -> io::Result<PathBuf> {
+    let dir = dir.as_ref();
+    let mode = mode.into_mode()?;
+    for _ in 0..MAX_ATTEMPTS {
+        let path = unique_path_in(dir, "fifo");
+        match create(&path, mode.map(Mode::bits)) {
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+            Ok(()) => return Ok(path),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("could not find an unused fifo name in {:?}", dir),
+    ))
+}
+
+/// Returns a path in `std::env::temp_dir()` which starts with `prefix` and
+/// ends in a random suffix, for tests and examples which need a pipe path
+/// that no concurrently running test will pick too. Nothing is created at
+/// the path; use `TempFifo` for a FIFO which is also cleaned up.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// let file_name = unix_named_pipe::unique_path("server");
+/// unix_named_pipe::create(&file_name, None).expect("could not create fifo");
+/// # fs::remove_file(&file_name).unwrap();
+/// ```
+pub fn unique_path(prefix: &str) -> PathBuf {
+    unique_path_in(&env::temp_dir(), prefix)
+}
+
+fn unique_path_in(dir: &Path, prefix: &str) -> PathBuf {
+    dir.join(format!("{}-{:016x}", prefix, random_id()))
+}
+
+/// Returns a random 64 bit identifier. `RandomState` is seeded from the
+/// operating system, and the process id and a counter keep successive ids
+/// apart.
+fn random_id() -> u64 {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(process::id());
+    hasher.write_usize(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::FileFIFOExt;
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    #[test]
+    fn temp_fifos_are_unique_and_removed() {
+        let dir = "/tmp/temp-fifo";
+        fs::create_dir_all(dir).expect("could not create test dir");
+
+        let first = TempFifo::new_in(dir).expect("could not create temporary fifo");
+        let second = TempFifo::new_in(dir).expect("could not create temporary fifo");
+        assert_ne!(first.path(), second.path());
+        assert_eq!(first.path().parent(), Some(Path::new(dir)));
+
+        let metadata = fs::metadata(first.path()).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        let reader = first.open(Direction::Read).unwrap();
+        assert!(reader.is_fifo().unwrap());
+
+        let kept = second.keep();
+        drop(first);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+        assert!(kept.exists());
+
+        fs::remove_dir_all(dir).expect("could not remove test dir");
+    }
+
+    #[test]
+    fn create_temp_in_skips_taken_names() {
+        let dir = "/tmp/temp-fifo-many";
+        fs::create_dir_all(dir).expect("could not create test dir");
+
+        let mut paths: Vec<PathBuf> = (0..32)
+            .map(|_| create_temp_in(dir, None).expect("could not create fifo"))
+            .collect();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 32);
+        assert!(paths
+            .iter()
+            .all(|path| fs::metadata(path).unwrap().file_type().is_fifo()));
+
+        let err = create_temp_in("/tmp/temp-fifo-missing", None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        fs::remove_dir_all(dir).expect("could not remove test dir");
+    }
+}
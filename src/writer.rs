@@ -0,0 +1,579 @@
+//! Provides `PipeWriter`, which reconnects to a named pipe whenever its
+//! reader goes away and comes back, so producers can outlive consumers,
+//! `BlockingWriter`, which waits for room in a non-blocking pipe, and
+//! `SharedWriter`, which lets threads share a pipe one message at a time.
+
+use super::sigpipe::write_blocking_sigpipe;
+use super::{
+    open_write_retry, Backpressure, FileFIFOExt, PipeError, Readiness, RetryPolicy, PIPE_BUF,
+};
+use libc::EPIPE;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type ReconnectCallback = Box<dyn FnMut(&Path) + Send>;
+
+/// A writer for a named pipe which reopens it whenever the reader goes
+/// away, and then carries on with the write that failed.
+///
+/// The pipe is opened on the first write, and opened again after a write
+/// fails with `EPIPE`, waiting for a reader as described by the
+/// `RetryPolicy`. `SIGPIPE` is blocked during writes, as with
+/// `write_without_sigpipe`, so a vanishing reader cannot kill the process.
+///
+/// Data which was sitting in the pipe buffer when the reader went away is
+/// lost along with the old pipe; only the write which failed is repeated.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::io::Write;
+/// use std::time::Duration;
+/// use unix_named_pipe::{PipeWriter, RetryPolicy};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// # let reader = unix_named_pipe::open_read(file_name).unwrap();
+/// let mut writer = PipeWriter::new(file_name)
+///     .retry_policy(RetryPolicy::new().deadline(Duration::from_secs(30)))
+///     .on_reconnect(|path| eprintln!("reader of {} is back", path.display()));
+/// writer.write_all(b"survives consumer restarts\n").unwrap();
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+pub struct PipeWriter {
+    path: PathBuf,
+    pipe: Option<File>,
+    policy: RetryPolicy,
+    on_reconnect: Option<ReconnectCallback>,
+    backpressure: Backpressure,
+    connected_before: bool,
+    reconnects: u64,
+    dropped: u64,
+}
+
+impl PipeWriter {
+    /// Creates a writer for the pipe at `path`, retrying opens with the
+    /// default `RetryPolicy`, which never gives up. The pipe is not opened
+    /// until the first write.
+    pub fn new<P: AsRef<Path>>(path: P) -> PipeWriter {
+        PipeWriter {
+            path: path.as_ref().to_path_buf(),
+            pipe: None,
+            policy: RetryPolicy::new(),
+            on_reconnect: None,
+            backpressure: Backpressure::default(),
+            connected_before: false,
+            reconnects: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Sets how opening the pipe is retried while it has no reader.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> PipeWriter {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets a callback which is called with the path of the pipe each time
+    /// it has been reopened after losing its reader.
+    pub fn on_reconnect<F: FnMut(&Path) + Send + 'static>(mut self, callback: F) -> PipeWriter {
+        self.on_reconnect = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets what a write does when the pipe is full, which defaults to
+    /// `Backpressure::Error`. `Backpressure::DropOldest` drops the new
+    /// message too, as nothing is held back.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> PipeWriter {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Returns the path of the pipe.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns how many times the pipe has been reopened after losing its
+    /// reader.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Returns how many messages have been dropped because the pipe was
+    /// full.
+    pub fn dropped_messages(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns whether the pipe is open at the moment.
+    pub fn is_connected(&self) -> bool {
+        self.pipe.is_some()
+    }
+
+    fn connect(&mut self) -> io::Result<&File> {
+        if self.pipe.is_none() {
+            let pipe = open_write_retry(&self.path, &self.policy)?;
+            if self.connected_before {
+                self.reconnects += 1;
+                if let Some(ref mut callback) = self.on_reconnect {
+                    callback(&self.path);
+                }
+            }
+            self.connected_before = true;
+            self.pipe = Some(pipe);
+        }
+
+        Ok(self.pipe.as_ref().unwrap())
+    }
+}
+
+impl Write for PipeWriter {
+    /// Writes `buf` to the pipe, reopening it first if it is not open.
+    ///
+    /// # Errors
+    ///
+    /// - If no reader attaches before the policy's deadline,
+    ///   `Err(io::ErrorKind::TimedOut)` will be returned.
+    /// - With `Backpressure::Error`, if the pipe is full,
+    ///   `Err(io::ErrorKind::WouldBlock)` will be returned.
+    /// - Any other error returned by `open_write`, `write`, or `poll`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let pipe = self.connect()?;
+            match write_blocking_sigpipe(pipe.as_fd(), buf) {
+                Err(ref err) if err.raw_os_error() == Some(EPIPE) => self.pipe = None,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    match self.backpressure {
+                        Backpressure::Block => {
+                            // A hang-up shows up as `EPIPE` on the next write.
+                            if let Some(ref pipe) = self.pipe {
+                                pipe.poll_writable(None)?;
+                            }
+                        }
+                        Backpressure::DropNewest | Backpressure::DropOldest => {
+                            self.dropped += 1;
+                            return Ok(buf.len());
+                        }
+                        Backpressure::Error => return Err(io::ErrorKind::WouldBlock.into()),
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PipeWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PipeWriter")
+            .field("path", &self.path)
+            .field("connected", &self.pipe.is_some())
+            .field("reconnects", &self.reconnects)
+            .finish()
+    }
+}
+
+/// A writer for a non-blocking pipe which sleeps in `poll` while the pipe
+/// is full, so that `write_all` either writes the whole buffer or fails
+/// with an error that is not `WouldBlock`.
+///
+/// Interrupted writes are retried, and a reader going away is reported as
+/// `PipeError::ReaderGone`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// # use std::thread;
+/// use std::io::{Read, Write};
+/// use unix_named_pipe::{BlockingWriter, FileFIFOExt};
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// let mut reader = unix_named_pipe::open_read(file_name).unwrap();
+/// reader.set_nonblocking(false).unwrap();
+/// let file = unix_named_pipe::open_write(file_name).unwrap();
+///
+/// // Far more than the pipe holds, so the writer has to wait for the reader.
+/// let data = vec![7; 1 << 20];
+/// let consumer = thread::spawn(move || {
+///     let mut received = Vec::new();
+///     reader.read_to_end(&mut received).map(|_| received)
+/// });
+/// BlockingWriter::new(file).write_all(&data).unwrap();
+/// assert_eq!(consumer.join().unwrap().unwrap(), data);
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct BlockingWriter<W> {
+    inner: W,
+    timeout: Option<Duration>,
+}
+
+impl<W: Write + AsFd> BlockingWriter<W> {
+    /// Wraps `inner`, waiting for room in the pipe for as long as it takes.
+    pub fn new(inner: W) -> BlockingWriter<W> {
+        BlockingWriter {
+            inner,
+            timeout: None,
+        }
+    }
+
+    /// Sets how long a single wait for room in the pipe may take, or `None`
+    /// to wait forever.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> BlockingWriter<W> {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the wrapped writer mutably.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write + AsFd> Write for BlockingWriter<W> {
+    /// Writes some of `buf`, waiting for room in the pipe first if it is
+    /// full.
+    ///
+    /// # Errors
+    ///
+    /// - If the reader has gone away, `Err(io::ErrorKind::BrokenPipe)` will
+    ///   be returned, with the raw `EPIPE` code.
+    /// - If the pipe stays full for longer than the timeout,
+    ///   `Err(io::ErrorKind::TimedOut)` will be returned, carrying
+    ///   `PipeError::TimedOut`.
+    /// - Any other error returned by `write` or `poll`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.inner.write(buf) {
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            match self.inner.poll_writable(self.timeout)? {
+                Readiness::Ready => {}
+                Readiness::HangUp => return Err(PipeError::ReaderGone.into()),
+                Readiness::TimedOut => {
+                    let timeout = self.timeout.unwrap_or_default();
+                    return Err(PipeError::TimedOut {
+                        path: None,
+                        timeout,
+                    }
+                    .into());
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: AsFd> AsFd for BlockingWriter<W> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+impl<W: AsRawFd> AsRawFd for BlockingWriter<W> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// A handle to the write end of a pipe which threads can clone and share,
+/// and whose messages never interleave.
+///
+/// Each message is written under a lock, waiting for room in the pipe as
+/// `BlockingWriter` does, so the messages of threads holding clones of the
+/// same writer never interleave. Messages up to `PIPE_BUF` bytes are written
+/// with `FileFIFOExt::write_atomic` as well, so they do not interleave with
+/// those of other processes either.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate unix_named_pipe;
+/// # use std::fs;
+/// use std::thread;
+/// use unix_named_pipe::SharedWriter;
+///
+/// # let file_name = &unix_named_pipe::unique_path("fifo");
+/// # unix_named_pipe::create(file_name, None).unwrap();
+/// # let reader = unix_named_pipe::open_read(file_name).unwrap();
+/// let file = unix_named_pipe::open_write(file_name).expect("could not open fifo for writing");
+/// let writer = SharedWriter::new(file);
+/// let workers: Vec<_> = (0..4)
+///     .map(|id| {
+///         let writer = writer.clone();
+///         thread::spawn(move || writer.write_message(format!("worker {} done\n", id).as_bytes()))
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap().expect("could not log to fifo");
+/// }
+/// # fs::remove_file(file_name).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct SharedWriter {
+    file: Arc<Mutex<File>>,
+    fd: RawFd,
+}
+
+impl SharedWriter {
+    /// Shares the write end `file`.
+    pub fn new(file: File) -> SharedWriter {
+        SharedWriter {
+            fd: file.as_raw_fd(),
+            file: Arc::new(Mutex::new(file)),
+        }
+    }
+
+    /// Writes all of `message` without interleaving it with the messages
+    /// of other clones of this writer, waiting for room in the pipe.
+    ///
+    /// # Errors
+    ///
+    /// - If the reader has gone away, `Err(io::ErrorKind::BrokenPipe)` will
+    ///   be returned, with the raw `EPIPE` code. A message larger than
+    ///   `PIPE_BUF` may have been written in part.
+    /// - Any other error returned by `write` or `poll`.
+    pub fn write_message(&self, message: &[u8]) -> io::Result<()> {
+        let file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        if message.len() > PIPE_BUF {
+            return BlockingWriter::new(&*file).write_all(message);
+        }
+
+        loop {
+            match file.write_atomic(message) {
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    // A hang-up shows up as `EPIPE` on the next write.
+                    file.poll_writable(None)?;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Write for SharedWriter {
+    /// Writes all of `buf` as one message, as `write_message` does.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_message(buf).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsFd for SharedWriter {
+    /// Borrows the descriptor without taking the lock, so writing to it
+    /// directly may interleave with messages.
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // The descriptor lives as long as the `File` in the `Arc`, which
+        // outlives `self`.
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+}
+
+impl AsRawFd for SharedWriter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{create, open_read, open_write};
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn reconnects_to_new_reader() {
+        let file_name = "/tmp/pipe-writer.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut first = open_read(file_name).expect("could not open fifo for reading");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut writer = PipeWriter::new(file_name)
+            .retry_policy(RetryPolicy::new().initial_delay(Duration::from_millis(1)))
+            .on_reconnect(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        writer.write_all(b"one").unwrap();
+        let mut buf = [0; 3];
+        first.read_exact(&mut buf).unwrap();
+        drop(first);
+
+        let consumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let second = open_read(file_name).expect("could not open fifo for reading");
+            let mut buf = Vec::new();
+            while buf.len() < 3 {
+                second.read_available(&mut buf).unwrap();
+                thread::sleep(Duration::from_millis(1));
+            }
+            buf
+        });
+        writer.write_all(b"two").unwrap();
+        assert_eq!(consumer.join().unwrap(), b"two");
+        assert_eq!(writer.reconnects(), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn backpressure_when_full() {
+        let file_name = "/tmp/pipe-writer-full.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let mut writer = PipeWriter::new(file_name);
+        let capacity = reader.buffer_size().unwrap();
+        writer.write_all(&vec![0; capacity]).unwrap();
+        let err = writer.write(b"full").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        let mut writer = writer.backpressure(Backpressure::DropNewest);
+        assert_eq!(writer.write(b"dropped").unwrap(), 7);
+        assert_eq!(writer.dropped_messages(), 1);
+
+        let mut writer = writer.backpressure(Backpressure::Block);
+        let consumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let mut buf = vec![0; capacity];
+            reader.read_available(&mut buf).map(|_| reader)
+        });
+        assert_eq!(writer.write(b"waited").unwrap(), 6);
+        drop(consumer.join().unwrap().unwrap());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn gives_up_after_deadline() {
+        let file_name = "/tmp/pipe-writer-idle.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut writer = PipeWriter::new(file_name)
+            .retry_policy(RetryPolicy::new().deadline(Duration::from_millis(20)));
+        let err = writer.write(b"nobody").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(!writer.is_connected());
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn blocking_writer_waits_for_room() {
+        let file_name = "/tmp/blocking-writer.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let reader = open_read(file_name).expect("could not open fifo for reading");
+        let file = open_write(file_name).expect("could not open fifo for writing");
+        let capacity = file.buffer_size().unwrap();
+        let mut writer = BlockingWriter::new(file).timeout(Some(Duration::from_millis(20)));
+        writer.write_all(&vec![1; capacity]).unwrap();
+
+        let err = writer.write_all(b"full").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        drop(reader);
+        let err = writer.write_all(b"gone").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(matches!(PipeError::of(&err), Some(PipeError::ReaderGone)));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn shared_fd_borrows_without_locking() {
+        let file_name = "/tmp/shared-fd.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let _reader = open_read(file_name).expect("could not open fifo for reading");
+        let file = open_write(file_name).expect("could not open fifo");
+        let fd = file.as_raw_fd();
+        let writer = SharedWriter::new(file);
+
+        let _guard = writer.file.lock().unwrap();
+        assert_eq!(writer.as_fd().as_raw_fd(), fd);
+        assert_eq!(writer.as_raw_fd(), fd);
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+
+    #[test]
+    fn shared_messages_never_interleave() {
+        let file_name = "/tmp/shared-writer.pipe";
+        create(file_name, None).expect("could not create fifo");
+
+        let mut reader = open_read(file_name).expect("could not open fifo for reading");
+        let writer = SharedWriter::new(open_write(file_name).expect("could not open fifo"));
+        let producers: Vec<_> = (b'a'..b'e')
+            .map(|byte| {
+                let writer = writer.clone();
+                thread::spawn(move || {
+                    for len in &[100, PIPE_BUF, 3 * PIPE_BUF, 10] {
+                        let mut message = vec![byte; *len];
+                        message.push(b'\n');
+                        writer.write_message(&message).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(writer);
+
+        reader.set_nonblocking(false).unwrap();
+        let consumer = thread::spawn(move || {
+            let mut received = Vec::new();
+            reader.read_to_end(&mut received).map(|_| received)
+        });
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let received = consumer.join().unwrap().unwrap();
+        let lines: Vec<&[u8]> = received.split(|&byte| byte == b'\n').collect();
+        assert_eq!(lines.len(), 4 * 4 + 1);
+        assert!(lines
+            .iter()
+            .all(|line| line.iter().all(|&byte| byte == line[0])));
+
+        fs::remove_file(file_name).expect("could not remove fifo");
+    }
+}